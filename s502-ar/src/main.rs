@@ -0,0 +1,160 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::process::ExitCode;
+
+use s502_ln::archive::{read_archive, write_archive, Archive, Member};
+use s502_ln::object::read_object;
+
+fn main() -> ExitCode {
+    let arg_matches = clap::App::new("s502-ar 0.1")
+        .setting(clap::AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            clap::SubCommand::with_name("create")
+                .alias("c")
+                .about("Create an archive from object files, replacing it if it exists")
+                .arg(
+                    clap::Arg::with_name("archive")
+                        .required(true)
+                        .help("The archive to create (*.65r)"),
+                )
+                .arg(
+                    clap::Arg::with_name("objects")
+                        .multiple(true)
+                        .required(true)
+                        .help("The object files (*.65o) to put in it"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("list")
+                .alias("t")
+                .about("List the members of an archive")
+                .arg(
+                    clap::Arg::with_name("symbols")
+                        .short("s")
+                        .long("symbols")
+                        .help("List the symbol index instead"),
+                )
+                .arg(
+                    clap::Arg::with_name("archive")
+                        .required(true)
+                        .help("The archive to list (*.65r)"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("extract")
+                .alias("x")
+                .about("Write members of an archive back out as object files")
+                .arg(
+                    clap::Arg::with_name("directory")
+                        .short("d")
+                        .long("directory")
+                        .takes_value(true)
+                        .help("Directory to write the members to (default current)"),
+                )
+                .arg(
+                    clap::Arg::with_name("archive")
+                        .required(true)
+                        .help("The archive to extract from (*.65r)"),
+                )
+                .arg(
+                    clap::Arg::with_name("members")
+                        .multiple(true)
+                        .help("The members to extract (default all)"),
+                ),
+        )
+        .get_matches();
+
+    let result = match arg_matches.subcommand() {
+        ("create", Some(args)) => create(
+            args.value_of("archive").unwrap(),
+            args.values_of("objects").unwrap().collect(),
+        ),
+        ("list", Some(args)) => list(
+            args.value_of("archive").unwrap(),
+            args.is_present("symbols"),
+        ),
+        ("extract", Some(args)) => extract(
+            args.value_of("archive").unwrap(),
+            args.values_of("members").into_iter().flatten().collect(),
+            Path::new(args.value_of("directory").unwrap_or(".")),
+        ),
+        _ => return ExitCode::FAILURE,
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Bundle object files into a new archive.
+fn create(archive: &str, objects: Vec<&str>) -> Result<(), String> {
+    let mut members = Vec::with_capacity(objects.len());
+    for file in objects {
+        let name = Path::new(file)
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        if members.iter().any(|m: &Member| m.name == name) {
+            return Err(format!("archive would have more than one member {}", name));
+        }
+        let data = fs::read(file).map_err(|_| format!("error reading file {}", file))?;
+        if read_object(name.clone(), &data[..]).is_err() {
+            return Err(format!("{} is not an object file", file));
+        }
+        members.push(Member { name, data });
+    }
+
+    File::create(archive)
+        .and_then(|f| write_archive(&Archive::new(members)?, f))
+        .map_err(|_| format!("error writing archive {}", archive))
+}
+
+/// Print each member and its size, or each indexed symbol and its member.
+fn list(archive: &str, symbols: bool) -> Result<(), String> {
+    let archive = open(archive)?;
+    if symbols {
+        for (name, member) in &archive.symbols {
+            println!("{:32} {}", name, archive.members[*member].name);
+        }
+    } else {
+        for member in &archive.members {
+            println!("{:8} {}", member.data.len(), member.name);
+        }
+    }
+    Ok(())
+}
+
+/// Write the named members, or every member, into a directory.
+fn extract(archive: &str, names: Vec<&str>, dir: &Path) -> Result<(), String> {
+    let archive = open(archive)?;
+    if let Some(name) = names
+        .iter()
+        .find(|&&name| !archive.members.iter().any(|m| m.name == name))
+    {
+        return Err(format!("archive has no member {}", name));
+    }
+    for member in archive
+        .members
+        .iter()
+        .filter(|m| names.is_empty() || names.contains(&m.name.as_str()))
+    {
+        let path = dir.join(&member.name);
+        fs::write(&path, &member.data)
+            .map_err(|_| format!("error writing file {}", path.to_string_lossy()))?;
+    }
+    Ok(())
+}
+
+fn open(archive: &str) -> Result<Archive, String> {
+    File::open(archive)
+        .and_then(|f| read_archive(BufReader::new(f)))
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData => format!("{} is not an archive", archive),
+            _ => format!("error reading file {}", archive),
+        })
+}
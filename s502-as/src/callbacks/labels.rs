@@ -24,6 +24,19 @@ pub fn vis_global(lex: &mut Lexer<Token>) -> Filter<()> {
     }
 }
 
+/// A weak label is global, but the linker uses another object's global label of
+/// the same name in its place if there is one.
+pub fn vis_weak(lex: &mut Lexer<Token>) -> Filter<()> {
+    if !lex.extras.start_line {
+        lex.extras.err = "visibility modifier must be first in the line";
+        Filter::Emit(())
+    } else {
+        lex.extras.start_line = false;
+        lex.extras.vis = Some(Visibility::Weak);
+        Filter::Skip
+    }
+}
+
 /// Process a root label, either at the beginning of the line or in the operand.
 pub fn label(lex: &mut Lexer<Token>) -> Filter<()> {
     // validate the label name
@@ -64,24 +77,13 @@ pub fn label(lex: &mut Lexer<Token>) -> Filter<()> {
             }
         })
         .unwrap();
-    if lex.extras.ins.is_none() {
-        // label at beginning of line, insert in section
-        sect.last_parent = Some(sect.labels.len());
-        sect.num_parents += 1;
-        sect.labels.push(Label {
-            vis: lex.extras.vis.unwrap_or(Visibility::Object),
-            name: name,
-            num_children: 0,
-            offset: sect.size,
-        });
-        lex.extras.start_line = false;
-    } else {
+    if let Some(ins) = &lex.extras.ins {
         let rf = Reference {
             parent: name,
             child: None,
             offset: sect.size + 1, // add 1 so it goes after the opcode
             which_byte: ByteSelect::Both,
-            branch: lex.extras.ins.as_ref().unwrap().is_branch(),
+            branch: ins.is_branch(),
         };
         sect.references.push(rf);
         // label is a reference in an operand
@@ -95,6 +97,17 @@ pub fn label(lex: &mut Lexer<Token>) -> Filter<()> {
                 return Filter::Emit(());
             }
         });
+    } else {
+        // label at beginning of line, insert in section
+        sect.last_parent = Some(sect.labels.len());
+        sect.num_parents += 1;
+        sect.labels.push(Label {
+            vis: lex.extras.vis.take().unwrap_or(Visibility::Object),
+            name,
+            num_children: 0,
+            offset: sect.size,
+        });
+        lex.extras.start_line = false;
     }
 
     Filter::Skip
@@ -155,8 +168,8 @@ pub fn child_label(lex: &mut Lexer<Token>) -> Filter<()> {
                 // update last parent's children, add new label
                 sect.labels[parent].num_children += 1;
                 sect.labels.push(Label {
-                    vis: lex.extras.vis.unwrap_or(Visibility::Hidden),
-                    name: name,
+                    vis: lex.extras.vis.take().unwrap_or(Visibility::Hidden),
+                    name,
                     num_children: 0,
                     offset: sect.size,
                 });
@@ -11,7 +11,7 @@ fn insert_mnem(lex: &mut Lexer<Token>, ins: Mnemonic) -> Filter<()> {
     if lex.extras.ins.is_some() {
         lex.extras.err = "multiple mnemonics on one line";
         Filter::Emit(())
-    } else if !lex.extras.op.is_none() {
+    } else if lex.extras.op.is_some() {
         lex.extras.err = "Mnemonic must appear before operand";
         Filter::Emit(())
     } else {
@@ -89,12 +89,71 @@ mnem!(tya, Tya);
 mnem!(dfb, Dfb);
 mnem!(dfw, Dfw);
 mnem!(hlt, Hlt);
+mnem!(aln, Aln);
+mnem!(pag, Pag);
+
+/// Keep an `ast expression, "message"` line for the linker to check.
+///
+/// The expression is a linker script expression, where `.` is the address the
+/// assertion is at, and it may use the object's labels and any global symbol. The
+/// message may not contain quotes.
+pub fn assertion(lex: &mut Lexer<Token>) -> Filter<()> {
+    // only a directive where a mnemonic could be, otherwise a label named `ast`
+    if lex.extras.ins.is_some()
+        || lex.extras.op.is_some()
+        || (!lex.extras.start_line && lex.extras.vis.is_some())
+    {
+        return super::labels::label(lex);
+    }
+    // the expression is only parsed by the linker so the rest of the line is taken
+    let remainder = lex.remainder();
+    let mut len = remainder.find(['\n', ';', '"']).unwrap_or(remainder.len());
+    if remainder[len..].starts_with('"') {
+        len += match remainder[len + 1..].find(['\n', '"']) {
+            Some(end) if remainder[len + 1 + end..].starts_with('"') => end + 2,
+            Some(end) => end + 1,
+            None => remainder.len() - len,
+        };
+    }
+    lex.bump(len);
+    let rest = lex.slice()[3..].trim();
+    let (expression, message) = match rest.strip_suffix('"').and_then(|r| r.split_once('"')) {
+        Some((expression, message)) => (expression.trim_end(), message),
+        None => {
+            lex.extras.err = "assertion requires a message in quotes";
+            return Filter::Emit(());
+        }
+    };
+    let expression = match expression.strip_suffix(',') {
+        Some(expression) if !expression.trim().is_empty() => expression.trim(),
+        _ => {
+            lex.extras.err = "expected an expression and `,` before the message";
+            return Filter::Emit(());
+        }
+    };
+    let (section, offset) = match lex.extras.active {
+        Some(active) => (active, lex.extras.sections[&active].size),
+        None => {
+            lex.extras.err = "no section has been set";
+            return Filter::Emit(());
+        }
+    };
+    lex.extras.assertions.push(Assertion {
+        section,
+        offset,
+        line: lex.extras.line,
+        expression: expression.to_string(),
+        message: message.to_string(),
+    });
+    lex.extras.start_line = false;
+    Filter::Skip
+}
 
 pub fn sct(lex: &mut Lexer<Token>) -> Filter<()> {
     if lex.extras.ins.is_some() {
         lex.extras.err = "multiple mnemonics on one line";
         Filter::Emit(())
-    } else if !lex.extras.op.is_none() {
+    } else if lex.extras.op.is_some() {
         lex.extras.err = "Mnemonic must appear before operand";
         Filter::Emit(())
     } else {
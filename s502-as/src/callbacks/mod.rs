@@ -15,6 +15,21 @@ use OpState::*;
 use OpVal::*;
 
 /// Process an assembly statement at the end of each line and put it in the binary.
+///
+/// Besides instructions there are these directives:
+/// ```text
+/// dfb $12     a byte, or one byte of a label's address
+/// dfw label   a word, or a label's address
+/// sct name    switch to the named section
+/// aln $100    pad the section with zeros to a multiple of a power of two, which
+///             makes the linker start the section at a multiple of it too
+/// pag         the block from the last label to the next one must not cross a page
+/// ast expr, "message"
+///             the linker reports the message if the expression is 0 once
+///             everything is placed, see `assertion`
+/// ```
+/// A `pag` block runs from its parent label to the next parent label or the end of
+/// the section, so `table pag` on a line of its own keeps `table` in one page.
 pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
     // for inserting into current section
     macro_rules! insert_byte {
@@ -66,8 +81,9 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
                 insert_byte!(b);
             } else if let Plain(Ref(rf)) = op {
                 if rf.which_byte != ByteSelect::Both {
+                    // there is no opcode before the reference
+                    data_reference(lex);
                     insert_byte!(0x00);
-                // insert_ref!(rf);
                 } else {
                     lex.extras.err = "invalid operand type for dfb";
                     return Filter::Emit(());
@@ -76,14 +92,20 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
                 lex.extras.err = "invalid operand type for dfb";
                 return Filter::Emit(());
             }
+            lex.extras.line += 1;
+            lex.extras.vis = None;
+            lex.extras.start_line = true;
+            return Filter::Skip;
         }
         Dfw => {
             if let Plain(Word(w)) = op {
                 insert_word!(w);
+            } else if let Plain(Byte(b)) = op {
+                insert_word!(b as u16);
             } else if let Plain(Ref(rf)) = op {
                 if rf.which_byte == ByteSelect::Both {
+                    data_reference(lex);
                     insert_word!(0x0000);
-                // insert_ref!(rf);
                 } else {
                     lex.extras.err = "invalid operand type for dfw";
                     return Filter::Emit(());
@@ -92,6 +114,10 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
                 lex.extras.err = "invalid operand type for dfw";
                 return Filter::Emit(());
             }
+            lex.extras.line += 1;
+            lex.extras.vis = None;
+            lex.extras.start_line = true;
+            return Filter::Skip;
         }
         Sct => {
             if let Plain(Ref(rf)) = op {
@@ -113,20 +139,11 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
                     .unwrap()
                     .references
                     .pop();
-                lex.extras.active = match &lex.extras.active {
-                    Some(name) if name == &[0; 32] => {
-                        let _ = lex.extras.sections.remove(name);
-                        lex.extras.sections.insert(rf.parent, Section::default());
-                        Some(rf.parent)
-                    }
-                    Some(_) => {
-                        if !lex.extras.sections.contains_key(&rf.parent) {
-                            lex.extras.sections.insert(rf.parent, Section::default());
-                        }
-                        Some(rf.parent)
-                    }
-                    None => unreachable!(),
-                };
+                // drop the dummy section and switch to the named one,
+                // keeping its contents if it was already started
+                let _ = lex.extras.sections.remove(&[0; 32]);
+                lex.extras.sections.entry(rf.parent).or_default();
+                lex.extras.active = Some(rf.parent);
                 lex.extras.line += 1;
                 lex.extras.vis = None;
                 lex.extras.start_line = true;
@@ -136,12 +153,84 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
                 return Filter::Emit(());
             }
         }
+        Aln => {
+            let align = match op {
+                Plain(Byte(b)) => b as usize,
+                Plain(Word(w)) => w as usize,
+                _ => {
+                    lex.extras.err = "invalid operand type for aln";
+                    return Filter::Emit(());
+                }
+            };
+            if !align.is_power_of_two() {
+                lex.extras.err = "aln directive requires a power of two";
+                return Filter::Emit(());
+            }
+            // pad with zeros up to the next multiple
+            loop {
+                let sect = match lex
+                    .extras
+                    .active
+                    .and_then(|active| lex.extras.sections.get_mut(&active))
+                {
+                    Some(sect) => sect,
+                    None => {
+                        lex.extras.err = "no section has been set";
+                        return Filter::Emit(());
+                    }
+                };
+                sect.align = sect.align.max(align);
+                if sect.size % align == 0 {
+                    break;
+                }
+                insert_byte!(0x00);
+            }
+            lex.extras.line += 1;
+            lex.extras.vis = None;
+            lex.extras.start_line = true;
+            return Filter::Skip;
+        }
+        Pag => {
+            if !matches!(op, Impl) {
+                lex.extras.err = "pag directive takes no operand";
+                return Filter::Emit(());
+            }
+            let sect = match lex
+                .extras
+                .active
+                .and_then(|active| lex.extras.sections.get_mut(&active))
+            {
+                Some(sect) => sect,
+                None => {
+                    lex.extras.err = "no section has been set";
+                    return Filter::Emit(());
+                }
+            };
+            match sect.last_parent {
+                Some(parent) if !sect.pages.contains(&parent) => sect.pages.push(parent),
+                Some(_) => (),
+                None => {
+                    lex.extras.err = "pag directive must come after a label";
+                    return Filter::Emit(());
+                }
+            }
+            lex.extras.line += 1;
+            lex.extras.vis = None;
+            lex.extras.start_line = true;
+            return Filter::Skip;
+        }
         _ => (),
     }
 
     // get address mode for looking up opcode
-    // and value to put into the binary
-    let (mode, val) = match op.destruct() {
+    // and value to put into the binary,
+    // a branch to a label is always relative so it's parsed like zpg
+    let (mode, val) = match match op {
+        Plain(Ref(rf)) if ins.is_branch() && rf.which_byte == ByteSelect::Both => {
+            Some((AddressMode::Zpg, Byte(0)))
+        }
+        op => op.destruct(),
+    } {
         Some(operand) => operand,
         None => {
             lex.extras.err = "invalid operand";
@@ -175,3 +264,26 @@ pub fn eol(lex: &mut Lexer<Token>) -> Filter<()> {
 
     Filter::Skip
 }
+
+/// End a line and hand control back to the caller, whether or not it had an error.
+///
+/// Every skipped token makes the lexer call itself, so skipping newlines too would
+/// recurse once per line and overflow the stack on long sources.
+pub fn line_end(lex: &mut Lexer<Token>) -> Filter<()> {
+    let _ = eol(lex);
+    Filter::Emit(())
+}
+
+/// Move the last reference back onto the current byte because a data directive
+/// has no opcode in front of it.
+fn data_reference(lex: &mut Lexer<Token>) {
+    if let Some(sect) = lex
+        .extras
+        .active
+        .and_then(|active| lex.extras.sections.get_mut(&active))
+    {
+        if let Some(rf) = sect.references.last_mut() {
+            rf.offset -= 1;
+        }
+    }
+}
@@ -9,34 +9,7 @@ use logos::{Filter, Lexer};
 /// This goes after the number instead of before because each state transition
 /// naturally acts on the current state.
 pub fn byte_high(lex: &mut Lexer<Token>) -> Filter<()> {
-    match lex.extras.op {
-        Some(OpState::Plain(OpVal::Ref(mut rf)))
-        | Some(OpState::Imme(OpVal::Ref(mut rf)))
-        | Some(OpState::MaybeInd(IndOp::Other(OpVal::Ref(mut rf))))
-            if rf.which_byte == ByteSelect::Both =>
-        {
-            lex.extras
-                .sections
-                .get_mut(match &lex.extras.active {
-                    Some(active) => active,
-                    None => {
-                        lex.extras.err = "no section has been set";
-                        return Filter::Emit(());
-                    }
-                })
-                .unwrap()
-                .references
-                .last_mut()
-                .unwrap()
-                .which_byte = ByteSelect::High;
-            // rf.which_byte = ByteSelect::High
-        }
-        _ => {
-            lex.extras.err = "invalid placement of byte selector";
-            return Filter::Emit(());
-        }
-    };
-    Filter::Skip
+    select_byte(lex, ByteSelect::High)
 }
 
 /// Take the low byte of the preceding reference.
@@ -44,33 +17,46 @@ pub fn byte_high(lex: &mut Lexer<Token>) -> Filter<()> {
 /// This goes after the number instead of before because each state transition
 /// naturally acts on the current state.
 pub fn byte_low(lex: &mut Lexer<Token>) -> Filter<()> {
-    match lex.extras.op {
-        Some(OpState::Plain(OpVal::Ref(mut rf)))
-        | Some(OpState::Imme(OpVal::Ref(mut rf)))
-        | Some(OpState::MaybeInd(IndOp::Other(OpVal::Ref(mut rf))))
+    select_byte(lex, ByteSelect::Low)
+}
+
+/// Change which byte of the preceding reference is taken, both in the operand
+/// state and the reference recorded in the section.
+fn select_byte(lex: &mut Lexer<Token>, which: ByteSelect) -> Filter<()> {
+    lex.extras.op = match lex.extras.op.take() {
+        Some(OpState::Plain(OpVal::Ref(mut rf))) if rf.which_byte == ByteSelect::Both => {
+            rf.which_byte = which;
+            Some(OpState::Plain(OpVal::Ref(rf)))
+        }
+        Some(OpState::Imme(OpVal::Ref(mut rf))) if rf.which_byte == ByteSelect::Both => {
+            rf.which_byte = which;
+            Some(OpState::Imme(OpVal::Ref(rf)))
+        }
+        Some(OpState::MaybeInd(IndOp::Other(OpVal::Ref(mut rf))))
             if rf.which_byte == ByteSelect::Both =>
         {
-            lex.extras
-                .sections
-                .get_mut(match &lex.extras.active {
-                    Some(active) => active,
-                    None => {
-                        lex.extras.err = "no section has been set";
-                        return Filter::Emit(());
-                    }
-                })
-                .unwrap()
-                .references
-                .last_mut()
-                .unwrap()
-                .which_byte = ByteSelect::Low;
-            // rf.which_byte = ByteSelect::Low
+            rf.which_byte = which;
+            Some(OpState::MaybeInd(IndOp::Other(OpVal::Ref(rf))))
         }
         _ => {
             lex.extras.err = "invalid placement of byte selector";
             return Filter::Emit(());
         }
     };
+    lex.extras
+        .sections
+        .get_mut(match &lex.extras.active {
+            Some(active) => active,
+            None => {
+                lex.extras.err = "no section has been set";
+                return Filter::Emit(());
+            }
+        })
+        .unwrap()
+        .references
+        .last_mut()
+        .unwrap()
+        .which_byte = which;
     Filter::Skip
 }
 
@@ -171,7 +157,7 @@ pub fn number(lex: &mut Lexer<Token>) -> Filter<()> {
         _ => 10,
     };
     let s = if base == 10 {
-        &lex.slice()
+        lex.slice()
     } else {
         &lex.slice()[1..]
     };
@@ -278,3 +264,163 @@ pub fn comma(lex: &mut Lexer<Token>) -> Filter<()> {
 
     Filter::Skip
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logos::Logos;
+
+    /// The operand state after lexing an operand.
+    fn state(operand: &str) -> Option<OpState> {
+        let mut lex = Token::lexer(operand);
+        // callbacks only emit a token on an error
+        assert!(lex.next().is_none(), "{}: {}", operand, lex.extras.err);
+        lex.extras.op
+    }
+
+    /// The error from lexing an operand.
+    fn error(operand: &str) -> &'static str {
+        let mut lex = Token::lexer(operand);
+        while lex.next().is_some() {
+            if !lex.extras.err.is_empty() {
+                return lex.extras.err;
+            }
+        }
+        panic!("{} was accepted", operand);
+    }
+
+    #[test]
+    fn numbers() {
+        assert!(matches!(
+            state("$12"),
+            Some(OpState::Plain(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(state("18"), Some(OpState::Plain(OpVal::Byte(18)))));
+        assert!(matches!(
+            state("%101"),
+            Some(OpState::Plain(OpVal::Byte(5)))
+        ));
+        assert!(matches!(
+            state("@17"),
+            Some(OpState::Plain(OpVal::Byte(0o17)))
+        ));
+        assert!(matches!(
+            state("$1234"),
+            Some(OpState::Plain(OpVal::Word(0x1234)))
+        ));
+        assert!(matches!(
+            state("300"),
+            Some(OpState::Plain(OpVal::Word(300)))
+        ));
+        // padding with 0 makes a word
+        assert!(matches!(
+            state("$0012"),
+            Some(OpState::Plain(OpVal::Word(0x12)))
+        ));
+        assert_eq!(error("$10000"), "invalid number");
+    }
+
+    #[test]
+    fn indexed() {
+        assert!(matches!(
+            state("$12,"),
+            Some(OpState::Idx(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(
+            state("$12,x"),
+            Some(OpState::ZpgX(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(
+            state("$12,y"),
+            Some(OpState::ZpgY(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(
+            state("$1234,x"),
+            Some(OpState::AbsX(OpVal::Word(0x1234)))
+        ));
+        assert!(matches!(
+            state("$1234,y"),
+            Some(OpState::AbsY(OpVal::Word(0x1234)))
+        ));
+        assert_eq!(error("x"), "invalid placement of X");
+        assert_eq!(error("$12,,"), "invalid placement of comma");
+    }
+
+    #[test]
+    fn indirect() {
+        assert!(matches!(state("("), Some(OpState::StartInd)));
+        assert!(matches!(
+            state("($12"),
+            Some(OpState::MaybeInd(IndOp::Other(OpVal::Byte(0x12))))
+        ));
+        assert!(matches!(
+            state("($12,"),
+            Some(OpState::MaybeInd(IndOp::XindComma(OpVal::Byte(0x12))))
+        ));
+        assert!(matches!(
+            state("($12,x"),
+            Some(OpState::MaybeInd(IndOp::Xind(OpVal::Byte(0x12))))
+        ));
+        assert!(matches!(
+            state("($12,x)"),
+            Some(OpState::Xind(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(
+            state("($12)"),
+            Some(OpState::MaybeInd(IndOp::IndY(OpVal::Byte(0x12))))
+        ));
+        assert!(matches!(
+            state("($12),"),
+            Some(OpState::MaybeInd(IndOp::IndYComma(OpVal::Byte(0x12))))
+        ));
+        assert!(matches!(
+            state("($12),y"),
+            Some(OpState::IndY(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(
+            state("($1234)"),
+            Some(OpState::Ind(OpVal::Word(0x1234)))
+        ));
+        // only zeropage can be indexed inside the parentheses
+        assert_eq!(error("($1234,"), "invalid placement of comma");
+        assert_eq!(error("($12),x"), "invalid placement of X");
+        assert_eq!(error("$12)"), "invalid placement of right parenthesis");
+        assert_eq!(
+            error("$12("),
+            "left parenthesis must be first part of operand"
+        );
+    }
+
+    #[test]
+    fn immediate_and_accumulator() {
+        assert!(matches!(state("#"), Some(OpState::StartImme)));
+        assert!(matches!(
+            state("#$12"),
+            Some(OpState::Imme(OpVal::Byte(0x12)))
+        ));
+        assert!(matches!(state("a"), Some(OpState::Acc)));
+        assert_eq!(error("#$1234"), "invalid placement of number");
+        assert_eq!(
+            error("$12#"),
+            "immediate pound must be first part of operand"
+        );
+        assert_eq!(error("$12 a"), "Accumulator argument must appear alone");
+    }
+
+    #[test]
+    fn byte_selectors() {
+        let lines = "sct text\n lda #label<";
+        let mut lex = Token::lexer(lines);
+        while lex.next().is_some() {
+            assert!(lex.extras.err.is_empty(), "{}", lex.extras.err);
+        }
+        match lex.extras.op {
+            Some(OpState::Imme(OpVal::Ref(rf))) => assert!(rf.which_byte == ByteSelect::High),
+            _ => panic!("expected an immediate reference"),
+        }
+        let sect = &lex.extras.sections[&lex.extras.active.unwrap()];
+        assert!(sect.references.last().unwrap().which_byte == ByteSelect::High);
+
+        assert_eq!(error("$12<"), "invalid placement of byte selector");
+    }
+}
@@ -11,6 +11,7 @@ pub struct Program {
     pub ins: Option<Mnemonic>,
     pub op: Option<OpState>,
     pub err: &'static str,
+    pub assertions: Vec<Assertion>,
 }
 
 pub struct Section {
@@ -20,6 +21,20 @@ pub struct Section {
     pub size: usize,
     pub last_parent: Option<usize>,
     pub num_parents: usize,
+    /// The section must start at a multiple of this, the largest `aln` in it.
+    pub align: usize,
+    /// Indices of the parent labels whose blocks may not cross a page, from `pag`.
+    pub pages: Vec<usize>,
+}
+
+/// An `ast` directive, checked by the linker once everything is placed.
+pub struct Assertion {
+    /// The section it's in, which is where `.` in the expression refers to.
+    pub section: [u8; 32],
+    pub offset: usize,
+    pub line: usize,
+    pub expression: String,
+    pub message: String,
 }
 
 #[derive(Clone, Copy)]
@@ -53,6 +68,7 @@ pub enum Visibility {
     Hidden = 0,
     Object = 1,
     Global = 2,
+    Weak = 3,
 }
 
 pub enum OpState {
@@ -114,6 +130,7 @@ impl Default for Program {
             ins: None,
             op: None,
             err: "",
+            assertions: Vec::new(),
         };
 
         prog.sections.insert([0; 32], Section::default());
@@ -131,12 +148,14 @@ impl Default for Section {
             size: 0,
             last_parent: None,
             num_parents: 0,
+            align: 1,
+            pages: Vec::new(),
         }
     }
 }
 
 /// A mnemonic, both for instructions and directives.
-#[derive(Enum)]
+#[derive(Enum, PartialEq, Clone, Copy, Debug)]
 pub enum Mnemonic {
     Adc,
     And,
@@ -198,21 +217,89 @@ pub enum Mnemonic {
     Dfw,
     Hlt,
     Sct,
+    Aln,
+    Pag,
 }
 
 impl Mnemonic {
     /// Checks if the mnemonic is a branch instruction.
     pub fn is_branch(&self) -> bool {
+        use Mnemonic::*;
+        matches!(self, Bcc | Bcs | Beq | Bmi | Bne | Bpl | Bvc | Bvs)
+    }
+
+    /// The mnemonic as it's written in source.
+    pub fn name(&self) -> &'static str {
         use Mnemonic::*;
         match self {
-            Bcc | Bcs | Beq | Bmi | Bne | Bpl | Bvc | Bvs => true,
-            _ => false,
+            Adc => "adc",
+            And => "and",
+            Asl => "asl",
+            Bcc => "bcc",
+            Bcs => "bcs",
+            Beq => "beq",
+            Bit => "bit",
+            Bmi => "bmi",
+            Bne => "bne",
+            Bpl => "bpl",
+            Brk => "brk",
+            Bvc => "bvc",
+            Bvs => "bvs",
+            Clc => "clc",
+            Cld => "cld",
+            Cli => "cli",
+            Clv => "clv",
+            Cmp => "cmp",
+            Cpx => "cpx",
+            Cpy => "cpy",
+            Dec => "dec",
+            Dex => "dex",
+            Dey => "dey",
+            Eor => "eor",
+            Inc => "inc",
+            Inx => "inx",
+            Iny => "iny",
+            Jmp => "jmp",
+            Jsr => "jsr",
+            Lda => "lda",
+            Ldx => "ldx",
+            Ldy => "ldy",
+            Lsr => "lsr",
+            Nop => "nop",
+            Ora => "ora",
+            Pha => "pha",
+            Php => "php",
+            Pla => "pla",
+            Plp => "plp",
+            Rol => "rol",
+            Ror => "ror",
+            Rti => "rti",
+            Rts => "rts",
+            Sbc => "sbc",
+            Sec => "sec",
+            Sed => "sed",
+            Sei => "sei",
+            Sta => "sta",
+            Stx => "stx",
+            Sty => "sty",
+            Tax => "tax",
+            Tay => "tay",
+            Tsx => "tsx",
+            Txa => "txa",
+            Txs => "txs",
+            Tya => "tya",
+            Dfb => "dfb",
+            Dfw => "dfw",
+            Hlt => "hlt",
+            Sct => "sct",
+            Aln => "aln",
+            Pag => "pag",
         }
     }
 }
 
 /// The address mode parsed.
-#[derive(Enum, PartialEq, Clone, Copy)]
+#[derive(Enum, PartialEq, Clone, Copy, Debug)]
 pub enum AddressMode {
     Acc,
     Abs,
@@ -229,6 +316,18 @@ pub enum AddressMode {
     // relative is missing because it gets parsed as zpg
 }
 
+impl AddressMode {
+    /// The number of bytes following the opcode.
+    pub fn operand_size(&self) -> usize {
+        use AddressMode::*;
+        match self {
+            Acc | Impl => 0,
+            Abs | AbsX | AbsY | Ind => 2,
+            Imme | Xind | IndY | Zpg | ZpgX | ZpgY => 1,
+        }
+    }
+}
+
 impl OpState {
     /// Turn an opstate into an AddressMode because
     /// enum map only allows simple enums. An incomplete state returns None.
@@ -261,7 +360,7 @@ impl OpState {
 
             OpState::Imme(OpVal::Byte(b)) => (Imme, OpVal::Byte(b)),
             OpState::Imme(OpVal::Ref(rf)) if rf.which_byte != ByteSelect::Both => {
-                (Imme, OpVal::Word(0))
+                (Imme, OpVal::Byte(0))
             }
 
             OpState::Ind(OpVal::Word(w)) => (Ind, OpVal::Word(w)),
@@ -271,25 +370,92 @@ impl OpState {
 
             OpState::Xind(OpVal::Byte(b)) => (Xind, OpVal::Byte(b)),
             OpState::Xind(OpVal::Ref(rf)) if rf.which_byte != ByteSelect::Both => {
-                (Xind, OpVal::Word(0))
+                (Xind, OpVal::Byte(0))
             }
 
             OpState::IndY(OpVal::Byte(b)) => (IndY, OpVal::Byte(b)),
             OpState::IndY(OpVal::Ref(rf)) if rf.which_byte != ByteSelect::Both => {
-                (IndY, OpVal::Word(0))
+                (IndY, OpVal::Byte(0))
             }
 
             OpState::ZpgX(OpVal::Byte(b)) => (ZpgX, OpVal::Byte(b)),
             OpState::ZpgX(OpVal::Ref(rf)) if rf.which_byte != ByteSelect::Both => {
-                (ZpgX, OpVal::Word(0))
+                (ZpgX, OpVal::Byte(0))
             }
 
             OpState::ZpgY(OpVal::Byte(b)) => (ZpgY, OpVal::Byte(b)),
             OpState::ZpgY(OpVal::Ref(rf)) if rf.which_byte != ByteSelect::Both => {
-                (ZpgY, OpVal::Word(0))
+                (ZpgY, OpVal::Byte(0))
             }
 
             _ => return None,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(which_byte: ByteSelect) -> OpVal {
+        OpVal::Ref(Reference {
+            parent: [0; 32],
+            child: None,
+            offset: 0,
+            which_byte,
+            branch: false,
+        })
+    }
+
+    fn mode(state: OpState) -> Option<AddressMode> {
+        state.destruct().map(|(mode, _)| mode)
+    }
+
+    #[test]
+    fn destruct_complete_states() {
+        use AddressMode::*;
+        assert_eq!(mode(OpState::Impl), Some(Impl));
+        assert_eq!(mode(OpState::Acc), Some(Acc));
+        assert_eq!(mode(OpState::Plain(OpVal::Byte(1))), Some(Zpg));
+        assert_eq!(mode(OpState::Plain(OpVal::Word(1))), Some(Abs));
+        assert_eq!(mode(OpState::Plain(reference(ByteSelect::Both))), Some(Abs));
+        assert_eq!(mode(OpState::Plain(reference(ByteSelect::Low))), Some(Zpg));
+        assert_eq!(mode(OpState::AbsX(reference(ByteSelect::Both))), Some(AbsX));
+        assert_eq!(mode(OpState::AbsY(OpVal::Word(1))), Some(AbsY));
+        assert_eq!(mode(OpState::Imme(reference(ByteSelect::High))), Some(Imme));
+        assert_eq!(mode(OpState::Ind(OpVal::Word(1))), Some(Ind));
+        assert_eq!(mode(OpState::Xind(OpVal::Byte(1))), Some(Xind));
+        assert_eq!(mode(OpState::IndY(reference(ByteSelect::Low))), Some(IndY));
+        assert_eq!(mode(OpState::ZpgX(OpVal::Byte(1))), Some(ZpgX));
+        assert_eq!(mode(OpState::ZpgY(OpVal::Byte(1))), Some(ZpgY));
+    }
+
+    #[test]
+    fn destruct_keeps_value() {
+        assert!(matches!(
+            OpState::Plain(OpVal::Word(0x1234)).destruct(),
+            Some((AddressMode::Abs, OpVal::Word(0x1234)))
+        ));
+        // a reference leaves a filler of the operand's size
+        assert!(matches!(
+            OpState::Imme(reference(ByteSelect::Low)).destruct(),
+            Some((AddressMode::Imme, OpVal::Byte(0)))
+        ));
+        assert!(matches!(
+            OpState::AbsX(reference(ByteSelect::Both)).destruct(),
+            Some((AddressMode::AbsX, OpVal::Word(0)))
+        ));
+    }
+
+    #[test]
+    fn destruct_incomplete_states() {
+        assert!(mode(OpState::StartInd).is_none());
+        assert!(mode(OpState::StartImme).is_none());
+        assert!(mode(OpState::Idx(OpVal::Byte(1))).is_none());
+        assert!(mode(OpState::MaybeInd(IndOp::IndY(OpVal::Byte(1)))).is_none());
+        // a whole address can't be immediate or zeropage indexed
+        assert!(mode(OpState::Imme(reference(ByteSelect::Both))).is_none());
+        assert!(mode(OpState::ZpgX(reference(ByteSelect::Both))).is_none());
+        assert!(mode(OpState::AbsX(reference(ByteSelect::Low))).is_none());
+    }
+}
@@ -0,0 +1,325 @@
+//! The S502 assembler.
+//!
+//! Source code is assembled entirely in memory by [`assemble`], which produces an
+//! [`Object`] that may be written out in the format described by the linker's `formats`
+//! module. The `s502-as` binary is a thin wrapper around this that reads a source file
+//! and writes its object next to it.
+//!
+//! ```text
+//! let obj = s502_as::assemble("sct text\nreset lda #$00\n", &Options::default())?;
+//! obj.write(&mut file)?;
+//! ```
+
+// logos generates its impls inside of an anonymous const
+#![allow(non_local_definitions)]
+
+pub mod callbacks;
+pub mod ir;
+pub mod opcodes;
+pub mod output;
+pub mod token;
+
+use std::fmt;
+
+use callbacks::eol;
+use logos::{Filter, Logos};
+pub use output::Object;
+use token::Token;
+
+/// Options that affect how a source is assembled.
+#[derive(Default, Clone)]
+pub struct Options {
+    /// Name of the source file, only used when reporting diagnostics.
+    pub file: Option<String>,
+}
+
+/// An error found while assembling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// The source file the error is in if one was given in the options.
+    pub file: Option<String>,
+    /// The line the error is on, starting at 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(
+                f,
+                "error in {} on line {}: {}",
+                file, self.line, self.message
+            ),
+            None => write!(f, "error on line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+/// Assemble a source into an object.
+///
+/// The assembler stops at the first error, so the returned list currently holds
+/// one diagnostic.
+pub fn assemble(source: &str, options: &Options) -> Result<Object, Vec<Diagnostic>> {
+    let diagnostic = |line, message| {
+        vec![Diagnostic {
+            file: options.file.clone(),
+            line,
+            message,
+        }]
+    };
+
+    let mut lexer = Token::lexer(source);
+    // tokens are skipped on success except at the end of a line,
+    // anything else that's emitted is an error
+    while let Some(token) = lexer.next() {
+        match token {
+            Token::Eol if lexer.extras.err.is_empty() => (),
+            Token::Error => {
+                return Err(diagnostic(
+                    lexer.extras.line,
+                    format!("unrecognized token `{}`", lexer.slice()),
+                ));
+            }
+            _ => return Err(diagnostic(lexer.extras.line, lexer.extras.err.to_string())),
+        }
+    }
+
+    // if last line has no newline, process it manually,
+    // if it does have a newline all registers will be empty and nothing will happen
+    if let Filter::Emit(()) = eol(&mut lexer) {
+        return Err(diagnostic(lexer.extras.line, lexer.extras.err.to_string()));
+    }
+
+    Ok(Object {
+        sections: lexer.extras.sections,
+        assertions: lexer.extras.assertions,
+        file: options.file.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ir::{ByteSelect, Section, Visibility};
+
+    /// A section or label name as it's stored.
+    fn name(name: &str) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    fn assemble_ok(source: &str) -> Object {
+        assemble(source, &Options::default()).unwrap_or_else(|e| panic!("{}", e[0]))
+    }
+
+    fn section<'o>(obj: &'o Object, sect: &str) -> &'o Section {
+        &obj.sections[&name(sect)]
+    }
+
+    fn code(source: &str) -> Vec<u8> {
+        let obj = assemble_ok(source);
+        let sect = section(&obj, "text");
+        sect.code[..sect.size].to_vec()
+    }
+
+    fn error(source: &str) -> Diagnostic {
+        match assemble(source, &Options::default()) {
+            Ok(_) => panic!("{:?} was accepted", source),
+            Err(mut diagnostics) => diagnostics.remove(0),
+        }
+    }
+
+    #[test]
+    fn address_modes() {
+        let source = "sct text
+ asl a
+ lda $1234
+ lda $1234,x
+ lda $1234,y
+ lda #$12
+ rts
+ jmp ($1234)
+ lda ($12,x)
+ lda ($12),y
+ lda $12
+ lda $12,x
+ ldx $12,y
+ ldx $1234,y
+";
+        assert_eq!(
+            code(source),
+            [
+                0x0a, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12, 0xb9, 0x34, 0x12, 0xa9, 0x12, 0x60, 0x6c,
+                0x34, 0x12, 0xa1, 0x12, 0xb1, 0x12, 0xa5, 0x12, 0xb5, 0x12, 0xb6, 0x12, 0xbe, 0x34,
+                0x12
+            ]
+        );
+    }
+
+    #[test]
+    fn data() {
+        assert_eq!(
+            code("sct text\n dfb $12\n dfw $1234\n dfw $12\n"),
+            [0x12, 0x34, 0x12, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn labels_and_references() {
+        let obj = assemble_ok(
+            "sct text
+!!start ldx #$00
+.loop dex
+ bne start.loop
+ jsr helper
+ lda #helper<
+ dfw helper
+helper rts
+",
+        );
+        let sect = section(&obj, "text");
+        let labels = sect
+            .labels
+            .iter()
+            .map(|l| (l.name, l.offset, l.num_children))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            [
+                (name("start"), 0, 1),
+                (name("loop"), 2, 0),
+                (name("helper"), 12, 0)
+            ]
+        );
+        assert!(matches!(sect.labels[0].vis, Visibility::Global));
+        assert!(matches!(sect.labels[1].vis, Visibility::Hidden));
+        assert!(matches!(sect.labels[2].vis, Visibility::Object));
+
+        let references = sect
+            .references
+            .iter()
+            .map(|rf| (rf.parent, rf.child, rf.offset, rf.branch))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            references,
+            [
+                (name("start"), Some(name("loop")), 4, true),
+                (name("helper"), None, 6, false),
+                (name("helper"), None, 9, false),
+                (name("helper"), None, 10, false)
+            ]
+        );
+        assert!(sect.references[2].which_byte == ByteSelect::High);
+        // fillers are left where the references go
+        assert_eq!(
+            &sect.code[..sect.size],
+            [0xa2, 0x00, 0xca, 0xd0, 0x00, 0x20, 0x00, 0x00, 0xa9, 0x00, 0x00, 0x00, 0x60]
+        );
+    }
+
+    #[test]
+    fn sections_continue() {
+        let obj = assemble_ok("sct text\n nop\nsct data\n dfb $01\nsct text\n rts");
+        assert_eq!(&section(&obj, "text").code[..2], [0xea, 0x60]);
+        assert_eq!(section(&obj, "data").size, 1);
+        // nothing is left in the section before the first sct
+        assert!(!obj.sections.contains_key(&[0; 32]));
+    }
+
+    #[test]
+    fn assertions() {
+        let obj = assemble_ok(
+            "sct text
+ ast . < $c000, \"text is in the fixed bank\" ; checked by the linker
+!!start nop
+!!check ast start + 1 = ., \"nop is one byte\"
+!!ast rts
+ jmp ast
+",
+        );
+        let assertions = obj
+            .assertions
+            .iter()
+            .map(|a| (a.offset, a.line, a.expression.as_str(), a.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            assertions,
+            [
+                (0, 2, ". < $c000", "text is in the fixed bank"),
+                (1, 4, "start + 1 = .", "nop is one byte")
+            ]
+        );
+        // `ast` is a label anywhere a mnemonic can't be
+        let sect = section(&obj, "text");
+        assert_eq!(sect.labels[2].name, name("ast"));
+        assert!(matches!(sect.labels[2].vis, Visibility::Global));
+        assert_eq!(sect.references[0].parent, name("ast"));
+        assert_eq!(&sect.code[..5], [0xea, 0x60, 0x4c, 0x00, 0x00]);
+
+        let e = error("sct text\n ast . < $c000\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "assertion requires a message in quotes")
+        );
+    }
+
+    #[test]
+    fn alignment_and_pages() {
+        let obj = assemble_ok("sct text\n nop\n aln $08\n!!table pag\n dfb $01\n aln $04\n");
+        let sect = section(&obj, "text");
+        // padded with zeros and aligned to the largest
+        assert_eq!(
+            &sect.code[..sect.size],
+            [0xea, 0, 0, 0, 0, 0, 0, 0, 0x01, 0, 0, 0]
+        );
+        assert_eq!(sect.align, 8);
+        assert_eq!(sect.pages, [0]);
+        assert_eq!(sect.labels[0].name, name("table"));
+
+        let e = error("sct text\n aln $03\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "aln directive requires a power of two")
+        );
+        let e = error("sct text\n pag\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "pag directive must come after a label")
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let e = error("sct text\n nop\n lda #$1234\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (3, "invalid placement of number")
+        );
+        let e = error("sct text\n sta #$12\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "invalid instruction and address mode combination")
+        );
+        let e = error("sct text\n ldx $1234,x\n");
+        assert_eq!(
+            (e.line, e.message.as_str()),
+            (2, "invalid instruction and address mode combination")
+        );
+        let e = error("sct text\n lda ^\n");
+        assert_eq!((e.line, e.message.as_str()), (2, "unrecognized token `^`"));
+        let e = assemble(
+            "sct text\n lda lda\n",
+            &Options {
+                file: Some("main.65a".to_string()),
+            },
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            e[0].to_string(),
+            "error in main.65a on line 2: multiple mnemonics on one line"
+        );
+    }
+}
@@ -1,14 +1,9 @@
-#![feature(process_exitcode_placeholder)]
-
-mod callbacks;
-mod ir;
-mod opcodes;
-mod output;
-mod token;
-
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::Path;
 use std::process::ExitCode;
 
-use output::*;
+use s502_as::{assemble, Options};
 
 fn main() -> ExitCode {
     let arg_matches = clap::App::new("s502-as 0.1")
@@ -62,3 +57,54 @@ fn main() -> ExitCode {
         ExitCode::SUCCESS
     }
 }
+
+/// Assemble a file and output its object.
+fn asm(mut name: String, out_file: Option<String>) -> bool {
+    // validate assembly extention
+    if Path::new(&name)
+        .extension()
+        .and_then(OsStr::to_str)
+        .filter(|&s| s == "65a")
+        .is_none()
+    {
+        eprintln!("source file {} has wrong extension", name);
+        return false;
+    }
+    // then get source
+    let code = match std::fs::read_to_string(&name) {
+        Ok(code) => code,
+        Err(_) => {
+            eprintln!("error reading file {}", name);
+            return false;
+        }
+    };
+
+    let options = Options {
+        file: Some(name.clone()),
+    };
+    let obj = match assemble(&code, &options) {
+        Ok(obj) => obj,
+        Err(diagnostics) => {
+            for diagnostic in diagnostics {
+                println!("{}", diagnostic);
+            }
+            return false;
+        }
+    };
+
+    // then output an object for this program
+    let out_name = match out_file {
+        None => {
+            let _ = name.pop();
+            name.push('o');
+            name
+        }
+        Some(out) => out,
+    };
+    if File::create(out_name).and_then(|f| obj.write(f)).is_err() {
+        eprintln!("error writing object file");
+        return false;
+    }
+
+    true
+}
@@ -35,7 +35,7 @@ pub static ref OPCODES: EnumMap<Mnemonic, EnumMap<AddressMode, Option<u8>>> = en
     Jmp => enum_map! {Acc => None, Abs => Some(0x4c), AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => Some(0x6c), Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
     Jsr => enum_map! {Acc => None, Abs => Some(0x20), AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
     Lda => enum_map! {Acc => None, Abs => Some(0xad), AbsX => Some(0xbd), AbsY => Some(0xb9), Imme => Some(0xa9), Impl => None, Ind => None, Xind => Some(0xa1), IndY => Some(0xb1), Zpg => Some(0xa5), ZpgX => Some(0xb5), ZpgY => None},
-    Ldx => enum_map! {Acc => None, Abs => Some(0xae), AbsX => Some(0xbe), AbsY => None, Imme => Some(0xa2), Impl => None, Ind => None, Xind => None, IndY => None, Zpg => Some(0xa6), ZpgX => Some(0xb6), ZpgY => Some(0xb6)},
+    Ldx => enum_map! {Acc => None, Abs => Some(0xae), AbsX => None, AbsY => Some(0xbe), Imme => Some(0xa2), Impl => None, Ind => None, Xind => None, IndY => None, Zpg => Some(0xa6), ZpgX => None, ZpgY => Some(0xb6)},
     Ldy => enum_map! {Acc => None, Abs => Some(0xac), AbsX => Some(0xbc), AbsY => None, Imme => Some(0xa0), Impl => None, Ind => None, Xind => None, IndY => None, Zpg => Some(0xa4), ZpgX => Some(0xb4), ZpgY => None},
     Lsr => enum_map! {Acc => Some(0x4a), Abs => Some(0x4e), AbsX => Some(0x5e), AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => Some(0x46), ZpgX => Some(0x56), ZpgY => None},
     Nop => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => Some(0xea), Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
@@ -65,5 +65,83 @@ pub static ref OPCODES: EnumMap<Mnemonic, EnumMap<AddressMode, Option<u8>>> = en
     Dfw => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
     Sct => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
     Hlt => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
+    Aln => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
+    Pag => enum_map! {Acc => None, Abs => None, AbsX => None, AbsY => None, Imme => None, Impl => None, Ind => None, Xind => None, IndY => None, Zpg => None, ZpgX => None, ZpgY => None},
 };
+
+/// Lookup mnemonic and address mode from an opcode, the inverse of `OPCODES`.
+/// Branches are listed as zpg because that's how their relative operand is parsed.
+pub static ref DECODE: [Option<(Mnemonic, AddressMode)>; 256] = {
+    let mut decode = [None; 256];
+    for (mnem, modes) in OPCODES.iter() {
+        for (mode, opc) in modes.iter() {
+            if let Some(opc) = opc {
+                decode[*opc as usize] = Some((mnem, mode));
+            }
+        }
+    }
+    decode
+};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_inverts_opcodes() {
+        let mut count = 0;
+        for (mnem, modes) in OPCODES.iter() {
+            for (mode, opc) in modes.iter() {
+                if let Some(opc) = opc {
+                    assert_eq!(DECODE[*opc as usize], Some((mnem, mode)), "{:#04x}", opc);
+                    count += 1;
+                }
+            }
+        }
+        // every documented opcode once
+        assert_eq!(count, 151);
+        assert_eq!(DECODE.iter().filter(|d| d.is_some()).count(), 151);
+    }
+
+    #[test]
+    fn directives_have_no_opcodes() {
+        for mnem in &[Dfb, Dfw, Hlt, Sct, Aln, Pag] {
+            assert!(OPCODES[*mnem].values().all(Option::is_none), "{:?}", mnem);
+        }
+    }
+
+    #[test]
+    fn decodes_each_mode() {
+        assert_eq!(DECODE[0x0a], Some((Asl, Acc)));
+        assert_eq!(DECODE[0xad], Some((Lda, Abs)));
+        assert_eq!(DECODE[0xbd], Some((Lda, AbsX)));
+        assert_eq!(DECODE[0xb9], Some((Lda, AbsY)));
+        assert_eq!(DECODE[0xa9], Some((Lda, Imme)));
+        assert_eq!(DECODE[0xea], Some((Nop, Impl)));
+        assert_eq!(DECODE[0x6c], Some((Jmp, Ind)));
+        assert_eq!(DECODE[0xa1], Some((Lda, Xind)));
+        assert_eq!(DECODE[0xb1], Some((Lda, IndY)));
+        assert_eq!(DECODE[0xa5], Some((Lda, Zpg)));
+        assert_eq!(DECODE[0xb5], Some((Lda, ZpgX)));
+        assert_eq!(DECODE[0xb6], Some((Ldx, ZpgY)));
+        assert_eq!(DECODE[0xd0], Some((Bne, Zpg)));
+        assert_eq!(DECODE[0x02], None);
+    }
+
+    #[test]
+    fn indexed_by_y() {
+        let abs_y = OPCODES
+            .values()
+            .filter_map(|modes| modes[AbsY])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            abs_y,
+            [0x79, 0x39, 0xd9, 0x59, 0xb9, 0xbe, 0x19, 0xf9, 0x99]
+        );
+        assert_eq!(DECODE[0xbe], Some((Ldx, AbsY)));
+        // ldx and stx can't be indexed by x
+        assert!(OPCODES[Ldx][AbsX].is_none() && OPCODES[Ldx][ZpgX].is_none());
+        assert!(OPCODES[Stx][ZpgX].is_none());
+    }
 }
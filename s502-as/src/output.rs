@@ -1,134 +1,121 @@
 use std::collections::HashMap;
-use std::ffi::OsStr;
-use std::fs::File;
 use std::io::{self, BufWriter, Write};
-use std::path::Path;
 
-use super::callbacks::eol;
 use super::ir::*;
-use super::token::Token;
-use logos::{Filter, Logos};
 
-/// Assemble a file and output its object.
-pub fn asm(mut name: String, out_file: Option<String>) -> bool {
-    // validate assembly extention
-    if let None = Path::new(&name)
-        .extension()
-        .and_then(OsStr::to_str)
-        .filter(|&s| s == "65a")
-    {
-        eprintln!("source file {} has wrong extension", name);
-        return false;
-    }
-    // then get source
-    let code = match std::fs::read_to_string(&name) {
-        Ok(code) => code,
-        Err(_) => {
-            eprintln!("error reading file {}", name);
-            return false;
-        }
-    };
-
-    let mut lexer = Token::lexer(code.as_str());
-    // all tokens are skipped on success, so an error emits a token
-    match lexer.next() {
-        Some(Token::Error) => {
-            println!(
-                "error on line {}: unrecognized token `{}`",
-                lexer.extras.line,
-                lexer.slice()
-            );
-            return false;
-        }
-        Some(_) => {
-            println!("error on line {}: {}", lexer.extras.line, lexer.extras.err);
-            return false;
-        }
-        None => (),
-    }
-
-    // if last line has no newline, process it manually,
-    // if it does have a newline all registers will be empty and nothing will happen
-    if let Filter::Emit(()) = eol(&mut lexer) {
-        println!("error on line {}: {}", lexer.extras.line, lexer.extras.err);
-        return false;
-    }
+/// An assembled program, keyed by section name.
+pub struct Object {
+    pub sections: HashMap<[u8; 32], Section>,
+    pub assertions: Vec<Assertion>,
+    /// The source file, which the linker names when an assertion fails.
+    pub file: Option<String>,
+}
 
-    // then output an object for this program
-    if let Err(_) = create_object(
-        lexer.extras.sections,
-        match out_file {
-            None => {
-                let _ = name.pop();
-                name.push('o');
-                name
-            }
-            Some(out) => out,
-        },
-    ) {
-        eprintln!("error writing object file");
-        return false;
-    }
+impl Object {
+    /// Output the object in the object file format.
+    pub fn write<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut obj_file = BufWriter::with_capacity(0x10000, out);
+        // object header
+        obj_file.write_all(&(self.sections.len() as u32).to_le_bytes())?;
 
-    true
-}
+        for (name, sect) in self.sections.iter() {
+            // section header
+            obj_file.write_all(name)?;
+            obj_file.write_all(&(sect.size as u32).to_le_bytes())?;
+            obj_file.write_all(&(sect.num_parents as u32).to_le_bytes())?;
+            obj_file.write_all(&(sect.references.len() as u32).to_le_bytes())?;
 
-/// Output a program to an object file.
-pub fn create_object(sections: HashMap<[u8; 32], Section>, name: String) -> io::Result<()> {
-    let mut obj_file = BufWriter::with_capacity(0x10000, File::create(name)?);
-    // object header
-    obj_file.write(&(sections.len() as u32).to_le_bytes())?;
+            // label block
+            let mut label_iter = sect.labels.iter();
+            for _ in 0..sect.num_parents {
+                let parent = label_iter.next().unwrap();
+                obj_file.write_all(&parent.name)?;
+                obj_file.write_all(&parent.num_children.to_le_bytes())?;
+                obj_file.write_all(&(parent.offset as u32).to_le_bytes())?;
+                obj_file.write_all(&(parent.vis as u32).to_le_bytes())?;
 
-    for (name, sect) in sections.into_iter() {
-        // section header
-        obj_file.write(&name)?;
-        obj_file.write(&(sect.size as u32).to_le_bytes())?;
-        obj_file.write(&(sect.num_parents as u32).to_le_bytes())?;
-        obj_file.write(&(sect.references.len() as u32).to_le_bytes())?;
+                for _ in 0..(parent.num_children as usize) {
+                    let child = label_iter.next().unwrap();
+                    obj_file.write_all(&child.name)?;
+                    obj_file.write_all(&(child.offset as u32).to_le_bytes())?;
+                    obj_file.write_all(&(child.vis as u32).to_le_bytes())?;
+                }
+            }
+            // reference block
+            for rf in sect.references.iter() {
+                // truncate padding
+                let mut referred_buffer = [0; 64];
+                let parent_length = rf.parent.iter().position(|&c| c == 0x00).unwrap();
+                referred_buffer[..parent_length].copy_from_slice(&rf.parent[..parent_length]);
+                if let Some(child) = rf.child {
+                    referred_buffer[parent_length] = b'.';
+                    // guaranteed to have null terminator
+                    referred_buffer[(parent_length + 1)..(parent_length + 32)]
+                        .copy_from_slice(&child[..31]);
+                }
+                obj_file.write_all(&referred_buffer)?;
+                obj_file.write_all(&(rf.offset as u32).to_le_bytes())?;
+                obj_file.write_all(&(rf.which_byte as u16).to_le_bytes())?;
+                obj_file.write_all(&(rf.branch as u16).to_le_bytes())?;
+            }
 
-        // label block
-        let mut label_iter = sect.labels.into_iter();
-        for _ in 0..sect.num_parents {
-            let parent = label_iter.next().unwrap();
-            obj_file.write(&parent.name)?;
-            obj_file.write(&(parent.num_children as u32).to_le_bytes())?;
-            obj_file.write(&(parent.offset as u32).to_le_bytes())?;
-            obj_file.write(&(parent.vis as u32).to_le_bytes())?;
+            // pad code to 4 bytes
+            obj_file.write_all(&sect.code[0..(sect.size + ((4 - (sect.size & 3)) & 3))])?;
+        }
 
-            for _ in 0..(parent.num_children as usize) {
-                let child = label_iter.next().unwrap();
-                obj_file.write(&child.name)?;
-                obj_file.write(&(parent.offset as u32).to_le_bytes())?;
-                obj_file.write(&(parent.vis as u32).to_le_bytes())?;
+        // section attributes, only for the sections that have any
+        let attributed = self
+            .sections
+            .iter()
+            .filter(|(_, sect)| sect.align > 1 || !sect.pages.is_empty())
+            .collect::<Vec<_>>();
+        obj_file.write_all(&(attributed.len() as u32).to_le_bytes())?;
+        for (name, sect) in attributed {
+            obj_file.write_all(name)?;
+            obj_file.write_all(&(sect.align as u32).to_le_bytes())?;
+            obj_file.write_all(&(sect.pages.len() as u32).to_le_bytes())?;
+            for &idx in &sect.pages {
+                // the block ends at the next parent label
+                let parent = &sect.labels[idx];
+                let end = sect
+                    .labels
+                    .get(idx + 1 + parent.num_children as usize)
+                    .map_or(sect.size, |next| next.offset);
+                obj_file.write_all(&parent.name)?;
+                obj_file.write_all(&(parent.offset as u32).to_le_bytes())?;
+                obj_file.write_all(&((end - parent.offset) as u32).to_le_bytes())?;
             }
         }
-        // reference block
-        for rf in sect.references.into_iter() {
-            // truncate padding
-            let mut referred_buffer = [0; 64];
-            let parent_length = rf.parent.iter().position(|&c| c == 0x00).unwrap();
-            for i in 0..parent_length {
-                referred_buffer[i] = rf.parent[i];
+
+        // assertions for the linker
+        let file = self.file.as_deref().unwrap_or("");
+        obj_file.write_all(&(self.assertions.len() as u32).to_le_bytes())?;
+        for assertion in &self.assertions {
+            obj_file.write_all(&assertion.section)?;
+            obj_file.write_all(&(assertion.offset as u32).to_le_bytes())?;
+            obj_file.write_all(&(assertion.line as u32).to_le_bytes())?;
+            let strings = [file, &assertion.expression, &assertion.message];
+            for string in &strings {
+                obj_file.write_all(&(string.len() as u32).to_le_bytes())?;
             }
-            if let Some(child) = rf.child {
-                referred_buffer[parent_length] = b'.';
-                // guaranteed to have null terminator
-                for i in 0..31 {
-                    referred_buffer[parent_length + i + 1] = child[i];
-                }
+            // pad each to 4 bytes
+            for string in &strings {
+                obj_file.write_all(string.as_bytes())?;
+                obj_file.write_all(&[0; 3][..((4 - (string.len() & 3)) & 3)])?;
             }
-            obj_file.write(&referred_buffer)?;
-            obj_file.write(&(rf.offset as u32).to_le_bytes())?;
-            obj_file.write(&(rf.which_byte as u16).to_le_bytes())?;
-            obj_file.write(&(rf.branch as u16).to_le_bytes())?;
         }
 
-        // pad code to 4 bytes
-        obj_file.write(&sect.code[0..(sect.size + ((4 - (sect.size & 3)) & 3))])?;
+        obj_file.flush()?;
+        Ok(())
     }
 
-    obj_file.flush()?;
-    Ok(())
+    /// Output the object into a buffer in the object file format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(0x1000);
+        // writing to a vector can't fail
+        self.write(&mut bytes).unwrap();
+        bytes
+    }
 }
 
 // Invoke the linker.
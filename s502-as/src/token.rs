@@ -126,6 +126,12 @@ pub enum Token {
     Hlt,
     #[token("sct", sct)]
     Sct,
+    #[token("aln", aln)]
+    Aln,
+    #[token("pag", pag)]
+    Pag,
+    #[token("ast", assertion)]
+    Ast,
     #[token("a", acc)]
     A,
     #[token("x", xreg)]
@@ -148,6 +154,8 @@ pub enum Token {
     VisObj,
     #[token("!!", vis_global)]
     VisGlobal,
+    #[token("?", vis_weak)]
+    VisWeak,
     #[regex("\\$[0-9a-fA-F]+", number)]
     #[regex("%[0-1]+", number)]
     #[regex("@[0-7]+", number)]
@@ -156,10 +164,10 @@ pub enum Token {
     #[regex("[a-zA-Z_][a-zA-Z0-9_]*", label)]
     #[regex("\\.[a-zA-Z0-9_]+", child_label)]
     Ident,
-    #[regex("\n", eol)]
+    #[regex("\n", line_end)]
+    #[regex(";.*\n", line_end)]
     Eol,
     #[error]
-    #[regex(";.*\n", eol)]
     #[regex(r"[ \t]+", logos::skip)]
     Error,
 }
@@ -0,0 +1,261 @@
+//! Turns code back into instructions and prints them as a listing.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Write};
+use std::ops::Range;
+
+use s502_as::ir::{AddressMode, Mnemonic};
+use s502_as::opcodes::DECODE;
+use s502_ln::formats::ByteSelect;
+
+/// A place in the code that the linker fills in with the address of a label.
+pub struct Slot {
+    pub name: String,
+    pub which_byte: ByteSelect,
+    pub branch: bool,
+}
+
+/// Names to give to addresses.
+#[derive(Default)]
+pub struct Names {
+    /// Labels to put at the start of a line, by address.
+    pub labels: BTreeMap<usize, Vec<String>>,
+    /// Names to use in place of addresses in operands.
+    pub symbols: HashMap<usize, String>,
+}
+
+/// A block of code to disassemble.
+pub struct Code<'a> {
+    pub bytes: &'a [u8],
+    /// The address of the first byte.
+    pub base: usize,
+    /// Address ranges that hold data instead of instructions.
+    pub data: Vec<Range<usize>>,
+    /// Unresolved references by the address of their first byte.
+    pub slots: HashMap<usize, Slot>,
+    /// Addresses in data ranges that start a word instead of a byte.
+    pub words: HashSet<usize>,
+}
+
+/// A decoded line.
+pub enum Line {
+    Instruction {
+        mnem: Mnemonic,
+        operand: String,
+        size: usize,
+    },
+    Byte(String),
+    Word(String),
+}
+
+impl Line {
+    /// The number of bytes taken by the line.
+    pub fn size(&self) -> usize {
+        match self {
+            Line::Instruction { size, .. } => *size,
+            Line::Byte(_) => 1,
+            Line::Word(_) => 2,
+        }
+    }
+
+    /// The line as it would be written in source.
+    pub fn text(&self) -> String {
+        match self {
+            Line::Instruction { mnem, operand, .. } if operand.is_empty() => {
+                mnem.name().to_string()
+            }
+            Line::Instruction { mnem, operand, .. } => format!("{} {}", mnem.name(), operand),
+            Line::Byte(operand) => format!("dfb {}", operand),
+            Line::Word(operand) => format!("dfw {}", operand),
+        }
+    }
+}
+
+impl<'a> Code<'a> {
+    /// Checks if an address is inside of a data range.
+    pub fn is_data(&self, address: usize) -> bool {
+        self.data.iter().any(|r| r.contains(&address))
+    }
+
+    /// Decode the line at an address.
+    ///
+    /// Anything that can't be an instruction, like an unknown opcode, an instruction
+    /// running into data or a label, or a reference slot, becomes a data directive.
+    pub fn decode(&self, address: usize, names: &Names) -> Line {
+        let offset = address - self.base;
+        let byte = self.bytes[offset];
+
+        // references put in by data directives
+        if let Some(slot) = self.slots.get(&address) {
+            return if slot.which_byte == ByteSelect::Both && offset + 1 < self.bytes.len() {
+                Line::Word(slot.name.clone())
+            } else {
+                Line::Byte(slot_name(slot))
+            };
+        }
+        if self.words.contains(&address)
+            && offset + 1 < self.bytes.len()
+            && !names.labels.contains_key(&(address + 1))
+        {
+            let value = byte as usize | (self.bytes[offset + 1] as usize) << 8;
+            return Line::Word(name_or_word(value, names));
+        }
+        if self.is_data(address) {
+            return Line::Byte(format!("${:02x}", byte));
+        }
+
+        let (mnem, mode) = match DECODE[byte as usize] {
+            Some(decoded) => decoded,
+            None => return Line::Byte(format!("${:02x}", byte)),
+        };
+        let size = 1 + mode.operand_size();
+        // a reference must fill the operand exactly
+        let slot = self.slots.get(&(address + 1));
+        let slot_fits = match slot {
+            Some(slot) if slot.branch => mnem.is_branch(),
+            Some(slot) if slot.which_byte == ByteSelect::Both => size == 3,
+            Some(_) => size == 2,
+            None => true,
+        };
+        if offset + size > self.bytes.len()
+            || !slot_fits
+            || (1..size).any(|i| {
+                self.is_data(address + i)
+                    || names.labels.contains_key(&(address + i))
+                    || (i > 1 && self.slots.contains_key(&(address + i)))
+            })
+        {
+            return Line::Byte(format!("${:02x}", byte));
+        }
+
+        let low = *self.bytes.get(offset + 1).unwrap_or(&0);
+        let high = *self.bytes.get(offset + 2).unwrap_or(&0);
+        let value = match mode.operand_size() {
+            2 => low as usize | (high as usize) << 8,
+            _ => low as usize,
+        };
+        // a reference always replaces the whole value
+        let name = match slot {
+            Some(slot) => Some(slot_name(slot)),
+            // without a name the offset is kept as is
+            None if mnem.is_branch() => Some(
+                self.branch_target(address, low)
+                    .and_then(|target| names.symbols.get(&target).cloned())
+                    .unwrap_or_else(|| format!("${:02x}", low)),
+            ),
+            None => None,
+        };
+
+        use AddressMode::*;
+        let operand = match mode {
+            Acc => "a".to_string(),
+            Impl => String::new(),
+            Imme => format!("#{}", name.unwrap_or_else(|| format!("${:02x}", value))),
+            Zpg if mnem.is_branch() => name.unwrap(),
+            Zpg => name.unwrap_or_else(|| name_or_byte(value, names)),
+            ZpgX => format!("{},x", name.unwrap_or_else(|| name_or_byte(value, names))),
+            ZpgY => format!("{},y", name.unwrap_or_else(|| name_or_byte(value, names))),
+            Xind => format!("({},x)", name.unwrap_or_else(|| name_or_byte(value, names))),
+            IndY => format!("({}),y", name.unwrap_or_else(|| name_or_byte(value, names))),
+            Abs => name.unwrap_or_else(|| name_or_word(value, names)),
+            AbsX => format!("{},x", name.unwrap_or_else(|| name_or_word(value, names))),
+            AbsY => format!("{},y", name.unwrap_or_else(|| name_or_word(value, names))),
+            Ind => format!("({})", name.unwrap_or_else(|| name_or_word(value, names))),
+        };
+
+        Line::Instruction {
+            mnem,
+            operand,
+            size,
+        }
+    }
+
+    /// The address a branch at an address goes to, if it can be known.
+    pub fn branch_target(&self, address: usize, offset: u8) -> Option<usize> {
+        if self.slots.contains_key(&(address + 1)) {
+            return None;
+        }
+        let target = (address + 2) as isize + offset as i8 as isize;
+        if (0..0x10000).contains(&target) {
+            Some(target as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// The operand text for a reference.
+fn slot_name(slot: &Slot) -> String {
+    match (slot.branch, slot.which_byte) {
+        (false, ByteSelect::High) => format!("{}<", slot.name),
+        (false, ByteSelect::Low) => format!("{}>", slot.name),
+        _ => slot.name.clone(),
+    }
+}
+
+/// A zero page address, using the low byte of a name if there is one.
+fn name_or_byte(value: usize, names: &Names) -> String {
+    match names.symbols.get(&value) {
+        Some(name) => format!("{}>", name),
+        None => format!("${:02x}", value),
+    }
+}
+
+/// An absolute address.
+fn name_or_word(value: usize, names: &Names) -> String {
+    match names.symbols.get(&value) {
+        Some(name) => name.clone(),
+        None => format!("${:04x}", value),
+    }
+}
+
+/// Print a listing of addresses, bytes, labels and instructions.
+pub fn list<W: Write>(code: &Code, names: &Names, out: &mut W) -> io::Result<()> {
+    let mut address = code.base;
+    let end = code.base + code.bytes.len();
+    let mut slot_bytes = HashSet::with_capacity(code.slots.len() * 2);
+    for (&start, slot) in &code.slots {
+        slot_bytes.insert(start);
+        if slot.which_byte == ByteSelect::Both && !slot.branch {
+            slot_bytes.insert(start + 1);
+        }
+    }
+    while address < end {
+        let line = code.decode(address, names);
+        let size = line.size();
+
+        // reference slots are marked since their bytes aren't final
+        let hex = (address..(address + size))
+            .map(|a| {
+                if slot_bytes.contains(&a) {
+                    "rr".to_string()
+                } else {
+                    format!("{:02x}", code.bytes[a - code.base])
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // only the last label fits on the line with the instruction
+        let labels = names.labels.get(&address).map(Vec::as_slice).unwrap_or(&[]);
+        let (last, others) = match labels.split_last() {
+            Some((last, others)) => (last.as_str(), others),
+            None => ("", labels),
+        };
+        for other in others {
+            writeln!(out, "{:4}  {:8}  {}", "", "", other)?;
+        }
+        writeln!(
+            out,
+            "{:04x}  {:8}  {:16} {}",
+            address,
+            hex,
+            last,
+            line.text()
+        )?;
+
+        address += size;
+    }
+
+    Ok(())
+}
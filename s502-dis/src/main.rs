@@ -0,0 +1,388 @@
+mod listing;
+mod trace;
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::process::ExitCode;
+
+use listing::*;
+use s502_ln::formats::{Object, Visibility};
+use s502_ln::object::{read_object, read_symtab};
+use trace::{trace, write_source, Hints};
+
+fn main() -> ExitCode {
+    let arg_matches = clap::App::new("s502-dis 0.1")
+        .arg(
+            clap::Arg::with_name("base address")
+                .short("b")
+                .long("base")
+                .takes_value(true)
+                .help("Address a binary is loaded at (default 0)"),
+        )
+        .arg(
+            clap::Arg::with_name("symbol tables")
+                .short("s")
+                .long("symbols")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Symbol table (*.65s) to name addresses with"),
+        )
+        .arg(
+            clap::Arg::with_name("data")
+                .short("d")
+                .long("data")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Inclusive range of data, `start-end` or `section:start-end` in objects"),
+        )
+        .arg(
+            clap::Arg::with_name("trace")
+                .short("t")
+                .long("trace")
+                .help("Follow control flow from entry points and output source (*.65a)"),
+        )
+        .arg(
+            clap::Arg::with_name("entry")
+                .short("e")
+                .long("entry")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Address to start tracing from"),
+        )
+        .arg(
+            clap::Arg::with_name("vectors")
+                .long("vectors")
+                .help("Start tracing from the NMI, RESET and IRQ vectors"),
+        )
+        .arg(
+            clap::Arg::with_name("jump")
+                .short("j")
+                .long("jump")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Targets of an indirect jump, `address=target,target...`"),
+        )
+        .arg(
+            clap::Arg::with_name("linker script")
+                .short("l")
+                .long("linker-script")
+                .takes_value(true)
+                .help("Linker script to output when tracing (default <output>.65l)"),
+        )
+        .arg(
+            clap::Arg::with_name("output file")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Name for output file (default stdout)"),
+        )
+        .arg(
+            clap::Arg::with_name("input")
+                .required(true)
+                .help("The binary or object file (*.65o) to disassemble"),
+        )
+        .get_matches();
+
+    let input = arg_matches.value_of("input").unwrap();
+    let base = match arg_matches.value_of("base address").map(parse_number) {
+        None => 0,
+        Some(Some(base)) if base < 0x10000 => base,
+        Some(_) => {
+            eprintln!("invalid base address");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut data = Vec::new();
+    for range in arg_matches.values_of("data").into_iter().flatten() {
+        match parse_range(range) {
+            Some(range) => data.push(range),
+            None => {
+                eprintln!("invalid data range {}", range);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    // symbols from symbol tables name addresses everywhere
+    let mut names = Names::default();
+    for file in arg_matches.values_of("symbol tables").into_iter().flatten() {
+        match File::open(file).and_then(|f| read_symtab(BufReader::new(f))) {
+            Ok(symbols) => {
+                for (name, address) in symbols {
+                    names.symbols.insert(address, name);
+                }
+            }
+            Err(_) => {
+                eprintln!("error reading symbol table {}", file);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    if arg_matches.is_present("trace") {
+        let mut hints = Hints {
+            vectors: arg_matches.is_present("vectors"),
+            ..Default::default()
+        };
+        for entry in arg_matches.values_of("entry").into_iter().flatten() {
+            match parse_number(entry) {
+                Some(entry) => hints.entries.push(entry),
+                None => {
+                    eprintln!("invalid entry point {}", entry);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        for jump in arg_matches.values_of("jump").into_iter().flatten() {
+            match parse_jump(jump) {
+                Some((address, targets)) => {
+                    hints.jumps.entry(address).or_default().extend(targets);
+                }
+                None => {
+                    eprintln!("invalid jump hint {}", jump);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        let out_file = arg_matches.value_of("output file");
+        let script = match (arg_matches.value_of("linker script"), out_file) {
+            (Some(script), _) => Some(script.to_string()),
+            (None, Some(out)) => Some(
+                Path::new(out)
+                    .with_extension("65l")
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            (None, None) => None,
+        };
+        return match reassemble(input, base, &data, &hints, &names, out_file, script) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("error disassembling {}: {}", input, e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let result = match arg_matches.value_of("output file") {
+        Some(out) => File::create(out).and_then(|f| {
+            let mut out = BufWriter::new(f);
+            disassemble(input, base, &data, names, &mut out)?;
+            out.flush()
+        }),
+        None => disassemble(input, base, &data, names, &mut io::stdout().lock()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error disassembling {}: {}", input, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Trace a binary and output source and a linker script that rebuild it.
+fn reassemble(
+    input: &str,
+    base: usize,
+    data: &[(Option<String>, Range<usize>)],
+    hints: &Hints,
+    names: &Names,
+    out_file: Option<&str>,
+    script: Option<String>,
+) -> io::Result<()> {
+    let bytes = std::fs::read(input)?;
+    if base + bytes.len() > 0x10000 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "binary doesn't fit in the address space",
+        ));
+    }
+    let data = data
+        .iter()
+        .map(|(_, range)| range.clone())
+        .collect::<Vec<_>>();
+    let found = trace(&bytes, base, &data, hints);
+
+    let sections = match out_file {
+        Some(out) => {
+            let mut out = BufWriter::new(File::create(out)?);
+            let sections = write_source(&bytes, base, &found, &names.symbols, &mut out)?;
+            out.flush()?;
+            sections
+        }
+        None => write_source(
+            &bytes,
+            base,
+            &found,
+            &names.symbols,
+            &mut io::stdout().lock(),
+        )?,
+    };
+
+    // all sections follow each other from the base
+    if let Some(script) = script {
+        let mut script = File::create(script)?;
+        writeln!(script, "{:#06x} {}", base, sections.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Disassemble a binary or every section of an object.
+fn disassemble<W: Write>(
+    input: &str,
+    base: usize,
+    data: &[(Option<String>, Range<usize>)],
+    mut names: Names,
+    out: &mut W,
+) -> io::Result<()> {
+    if let Some("65o") = Path::new(input).extension().and_then(OsStr::to_str) {
+        let obj = read_object(input.to_string(), BufReader::new(File::open(input)?))?;
+        return list_object(&obj, data, names, out);
+    }
+
+    let bytes = std::fs::read(input)?;
+    if base + bytes.len() > 0x10000 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "binary doesn't fit in the address space",
+        ));
+    }
+    // only names inside of the binary can label a line
+    for (&address, name) in &names.symbols {
+        if (base..(base + bytes.len())).contains(&address) {
+            names.labels.entry(address).or_default().push(name.clone());
+        }
+    }
+    for labels in names.labels.values_mut() {
+        labels.sort();
+    }
+
+    let code = Code {
+        bytes: &bytes,
+        base,
+        data: data.iter().map(|(_, range)| range.clone()).collect(),
+        slots: HashMap::new(),
+        words: HashSet::new(),
+    };
+    list(&code, &names, out)
+}
+
+/// List each section of an object with its labels and references.
+///
+/// Sections aren't placed yet so addresses are offsets into each section.
+fn list_object<W: Write>(
+    obj: &Object,
+    data: &[(Option<String>, Range<usize>)],
+    names: Names,
+    out: &mut W,
+) -> io::Result<()> {
+    let mut sect_names = obj.sections.keys().collect::<Vec<_>>();
+    sect_names.sort();
+
+    for (idx, sect_name) in sect_names.into_iter().enumerate() {
+        let sect = &obj.sections[sect_name];
+        if idx != 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "{:4}  {:8}  {:16} sct {}", "", "", "", sect_name)?;
+
+        let mut sect_names = Names {
+            labels: Default::default(),
+            symbols: names.symbols.clone(),
+        };
+        for (name, lab) in &sect.labels {
+            // write labels the way they appear in source
+            let vis = match (lab.vis, name.contains('.')) {
+                (Visibility::Global, _) => "!!",
+                (Visibility::Weak, _) => "?",
+                (Visibility::Object, true) => "!",
+                _ => "",
+            };
+            let short = match name.find('.') {
+                Some(dot) => &name[dot..],
+                None => name.as_str(),
+            };
+            sect_names
+                .labels
+                .entry(lab.offset)
+                .or_default()
+                .push(format!("{}{}", vis, short));
+        }
+        // parents go before children
+        for labels in sect_names.labels.values_mut() {
+            labels.sort_by_key(|name| name.trim_start_matches('!').starts_with('.'));
+        }
+
+        let code = Code {
+            bytes: &sect.code[..sect.size],
+            base: 0,
+            data: data
+                .iter()
+                .filter(|(sect, _)| sect.as_ref().is_none_or(|s| s == sect_name))
+                .map(|(_, range)| range.clone())
+                .collect(),
+            slots: sect
+                .references
+                .iter()
+                .map(|rf| {
+                    (
+                        rf.offset,
+                        Slot {
+                            name: rf.referred.clone(),
+                            which_byte: rf.which_byte,
+                            branch: rf.branch,
+                        },
+                    )
+                })
+                .collect(),
+            words: HashSet::new(),
+        };
+        list(&code, &sect_names, out)?;
+    }
+
+    Ok(())
+}
+
+/// Parses a number as hex with `0x` or `$`, or as decimal.
+fn parse_number(s: &str) -> Option<usize> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Parses an inclusive data range with an optional section name.
+fn parse_range(s: &str) -> Option<(Option<String>, Range<usize>)> {
+    let (sect, range) = match s.find(':') {
+        Some(colon) => (Some(s[..colon].to_string()), &s[(colon + 1)..]),
+        None => (None, s),
+    };
+    let dash = range.find('-')?;
+    let start = parse_number(&range[..dash])?;
+    let end = parse_number(&range[(dash + 1)..])?;
+    if start > end {
+        return None;
+    }
+    Some((sect, start..(end + 1)))
+}
+
+/// Parses the targets of an indirect jump, `address=target,target...`.
+fn parse_jump(s: &str) -> Option<(usize, Vec<usize>)> {
+    let equals = s.find('=')?;
+    let address = parse_number(&s[..equals])?;
+    let targets = s[(equals + 1)..]
+        .split(',')
+        .map(parse_number)
+        .collect::<Option<Vec<_>>>()?;
+    Some((address, targets))
+}
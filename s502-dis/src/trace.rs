@@ -0,0 +1,302 @@
+//! Separates code from data by following control flow from entry points, then
+//! writes it as source that assembles and links back into the same image.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+use std::ops::Range;
+
+use super::listing::*;
+use s502_as::ir::{AddressMode, Mnemonic};
+use s502_as::opcodes::DECODE;
+
+/// The most labels the assembler allows in one section.
+const SECTION_LABELS: usize = 256;
+/// The most bytes put in one section before starting another.
+const SECTION_SIZE: usize = 0x8000;
+
+/// Where to start tracing and how to get through indirect jumps.
+#[derive(Default)]
+pub struct Hints {
+    /// Addresses that are known to be code.
+    pub entries: Vec<usize>,
+    /// Start at the NMI, RESET and IRQ vectors if the image covers them.
+    pub vectors: bool,
+    /// Possible targets of each `jmp (ind)` by the address of the instruction.
+    pub jumps: HashMap<usize, Vec<usize>>,
+}
+
+/// The result of tracing an image.
+pub struct Trace {
+    /// The size of each instruction found by its address.
+    pub instructions: BTreeMap<usize, usize>,
+    /// Addresses that control flow goes to.
+    pub targets: BTreeSet<usize>,
+    /// Words of data holding addresses, like the vectors.
+    pub words: HashSet<usize>,
+}
+
+/// Follow every path from the entry points, stopping at anything that can't be
+/// an instruction.
+pub fn trace(bytes: &[u8], base: usize, data: &[Range<usize>], hints: &Hints) -> Trace {
+    let end = base + bytes.len();
+    let in_image = |address: usize| (base..end).contains(&address);
+    let mut found = Trace {
+        instructions: BTreeMap::new(),
+        targets: BTreeSet::new(),
+        words: HashSet::new(),
+    };
+    // which instruction each byte belongs to
+    let mut owner = HashMap::<usize, usize>::with_capacity(bytes.len());
+
+    let mut pending = hints.entries.clone();
+    if hints.vectors && in_image(0xfffa) && in_image(0xffff) {
+        for vector in [0xfffa, 0xfffc, 0xfffe].iter() {
+            let offset = vector - base;
+            found.words.insert(*vector);
+            pending.push(bytes[offset] as usize | (bytes[offset + 1] as usize) << 8);
+        }
+    }
+    for targets in hints.jumps.values() {
+        pending.extend(targets);
+    }
+
+    while let Some(start) = pending.pop() {
+        if in_image(start) {
+            found.targets.insert(start);
+        }
+        let mut address = start;
+        loop {
+            if !in_image(address)
+                || data.iter().any(|r| r.contains(&address))
+                || owner.contains_key(&address)
+            {
+                break;
+            }
+            let offset = address - base;
+            let (mnem, mode) = match DECODE[bytes[offset] as usize] {
+                Some(decoded) => decoded,
+                None => break,
+            };
+            let size = 1 + mode.operand_size();
+            if offset + size > bytes.len()
+                || (address..(address + size)).any(|a| owner.contains_key(&a))
+            {
+                break;
+            }
+            found.instructions.insert(address, size);
+            for a in address..(address + size) {
+                owner.insert(a, address);
+            }
+
+            let operand = bytes[offset + 1..offset + size]
+                .iter()
+                .rev()
+                .fold(0, |acc, &b| acc << 8 | b as usize);
+            match (mnem, mode) {
+                (m, _) if m.is_branch() => {
+                    let target = (address + 2) as isize + operand as u8 as i8 as isize;
+                    if (0..0x10000).contains(&target) {
+                        pending.push(target as usize);
+                    }
+                }
+                (Mnemonic::Jsr, _) => pending.push(operand),
+                (Mnemonic::Jmp, AddressMode::Abs) => {
+                    pending.push(operand);
+                    break;
+                }
+                (Mnemonic::Jmp, _) => {
+                    if let Some(targets) = hints.jumps.get(&address) {
+                        pending.extend(targets);
+                    }
+                    break;
+                }
+                (Mnemonic::Rts, _) | (Mnemonic::Rti, _) | (Mnemonic::Brk, _) => break,
+                _ => (),
+            }
+            address += size;
+        }
+    }
+
+    found
+}
+
+/// Write a traced image as source, along with a linker script that places it
+/// back at the same address.
+pub fn write_source<W: Write>(
+    bytes: &[u8],
+    base: usize,
+    found: &Trace,
+    symbols: &HashMap<usize, String>,
+    out: &mut W,
+) -> io::Result<Vec<String>> {
+    let end = base + bytes.len();
+    let in_image = |address: &usize| (base..end).contains(address);
+
+    // everything that isn't an instruction is data
+    let mut data = Vec::new();
+    let mut address = base;
+    for (&start, &size) in &found.instructions {
+        if address < start {
+            data.push(address..start);
+        }
+        address = start + size;
+    }
+    if address < end {
+        data.push(address..end);
+    }
+
+    // name every target and every operand that points at the start of a line
+    let mut names = Names::default();
+    let mut named = found.targets.clone();
+    for (&start, &size) in &found.instructions {
+        let offset = start - base;
+        // only absolute operands can point anywhere in memory
+        if size == 3 {
+            named.insert(bytes[offset + 1] as usize | (bytes[offset + 2] as usize) << 8);
+        }
+    }
+    for word in &found.words {
+        let offset = word - base;
+        named.insert(bytes[offset] as usize | (bytes[offset + 1] as usize) << 8);
+    }
+    let line_start = |address: &usize| {
+        let in_instruction = found
+            .instructions
+            .range(..*address)
+            .next_back()
+            .is_some_and(|(start, size)| start + size > *address);
+        let in_word = address
+            .checked_sub(1)
+            .is_some_and(|a| found.words.contains(&a));
+        found.instructions.contains_key(address) || !(in_instruction || in_word)
+    };
+    for address in named.into_iter().filter(in_image).filter(line_start) {
+        // child names can't be written as a parent label
+        let name = symbols
+            .get(&address)
+            .filter(|name| !name.contains('.'))
+            .cloned()
+            .unwrap_or_else(|| format!("L{:04X}", address));
+        names.labels.insert(address, vec![name.clone()]);
+        names.symbols.insert(address, name);
+    }
+    // names from symbol tables are used for anything outside of the image too,
+    // inside of it only labels that are written out may be used
+    for (&address, name) in symbols {
+        if !in_image(&address) {
+            names.symbols.insert(address, name.clone());
+        }
+    }
+
+    let code = Code {
+        bytes,
+        base,
+        data,
+        slots: HashMap::new(),
+        words: found.words.clone(),
+    };
+
+    // split into sections so the assembler's limits aren't reached
+    let mut sections = Vec::new();
+    let mut section_labels = SECTION_LABELS;
+    let mut section_start = base;
+    let mut address = base;
+    while address < end {
+        let labels = names.labels.get(&address).map(Vec::as_slice).unwrap_or(&[]);
+        if section_labels + labels.len() > SECTION_LABELS || address - section_start >= SECTION_SIZE
+        {
+            let name = format!("rom{}", sections.len());
+            writeln!(out, " sct {}", name)?;
+            sections.push(name);
+            section_labels = 0;
+            section_start = address;
+        }
+        section_labels += labels.len();
+
+        let line = code.decode(address, &names);
+        // only the last label goes on the line with the instruction
+        let (last, others) = match labels.split_last() {
+            Some((last, others)) => (last.as_str(), others),
+            None => ("", labels),
+        };
+        for other in others {
+            writeln!(out, "{}", other)?;
+        }
+        writeln!(out, "{} {}", last, line.text())?;
+        address += line.size();
+    }
+
+    Ok(sections)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use s502_ln::object::read_object;
+    use s502_ln::script::read_script;
+    use s502_ln::{Image, Linker};
+
+    /// Assemble a source and link it with a layout.
+    fn build(script: &str, source: &str) -> Image {
+        let obj = s502_as::assemble(source, &s502_as::Options::default())
+            .unwrap_or_else(|e| panic!("{}\n{}", e[0], source));
+        let obj = read_object("rom".to_string(), &obj.to_bytes()[..]).unwrap();
+        let layout = read_script(script).unwrap();
+        Linker::new(layout, vec![obj], HashMap::new())
+            .link()
+            .unwrap_or_else(|e| panic!("{}", e[0]))
+    }
+
+    const ROM: &str = "sct text
+!!reset ldx #$00
+.loop lda table,x
+ sta $0200,x
+ inx
+ cpx #$04
+ bne reset.loop
+ jmp (vector)
+!!table dfb $01
+ dfb $02
+ dfb $03
+ dfb $04
+!!vector dfw handler
+!!handler lda #$ff
+ jmp reset
+!!nmi rti
+";
+
+    #[test]
+    fn round_trip() {
+        let image = build("0xf000 text\nentry reset\nvectors nmi=nmi irq=nmi\n", ROM);
+        assert_eq!((image.base, image.code.len()), (0xf000, 0x1000));
+        let handler = image.symbols["handler"];
+        let jump = image.symbols["table"] - 3;
+        let hints = Hints {
+            entries: Vec::new(),
+            vectors: true,
+            jumps: vec![(jump, vec![handler])].into_iter().collect(),
+        };
+        let found = trace(&image.code, image.base, &[], &hints);
+        // the table and the gap up to the vectors are data
+        assert!(found.instructions.contains_key(&image.symbols["nmi"]));
+        assert!(found.instructions.contains_key(&handler));
+        assert!(!found.instructions.contains_key(&image.symbols["table"]));
+        assert!(found.words.contains(&0xfffa));
+
+        let mut source = Vec::new();
+        let sections = write_source(
+            &image.code,
+            image.base,
+            &found,
+            &HashMap::new(),
+            &mut source,
+        )
+        .unwrap();
+        let source = String::from_utf8(source).unwrap();
+        assert!(source.contains(&format!("L{:04X} lda #$ff", handler)));
+        let script = format!("{:#06x} {}\n", image.base, sections.join(" "));
+        let rebuilt = build(&script, &source);
+        assert_eq!(rebuilt.base, image.base);
+        assert!(rebuilt.code == image.code);
+    }
+}
@@ -0,0 +1,229 @@
+//! Archives of objects, in the format described in `formats`.
+//!
+//! The linker only takes the members of an archive that define a label some object
+//! refers to but nothing else defines. Taking a member can leave new references
+//! undefined, so the archives are searched again until nothing more is taken. Every
+//! archive is searched each time no matter the order they're given in, and taken
+//! members are placed after all of the objects that were listed. Members are only
+//! taken once symbols have been defined and wrapped, so a defined symbol never takes
+//! a member, and a reference to a wrapped `sym` takes the member defining `__wrap_sym`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufWriter, Read, Write};
+
+use super::error::Error;
+use super::formats::*;
+use super::linker::find_label;
+use super::object::{read_name, read_object};
+
+/// The first bytes of every archive.
+pub const MAGIC: [u8; 4] = *b"65r\0";
+
+/// An object file in an archive.
+pub struct Member {
+    pub name: String,
+    /// The object file exactly as it was read.
+    pub data: Vec<u8>,
+}
+
+pub struct Archive {
+    pub members: Vec<Member>,
+    /// Every global label and the index of the member defining it.
+    pub symbols: Vec<(String, usize)>,
+}
+
+impl Archive {
+    /// Bundle object files into an archive and index their global labels. If more
+    /// than one member defines a label the first is indexed.
+    pub fn new(members: Vec<Member>) -> io::Result<Self> {
+        let mut symbols = Vec::with_capacity(members.len() * 8);
+        let mut indexed = HashSet::with_capacity(members.len() * 8);
+        for (idx, member) in members.iter().enumerate() {
+            let obj = read_object(member.name.clone(), &member.data[..])?;
+            let mut names = obj
+                .sections
+                .values()
+                .flat_map(|sect| &sect.labels)
+                .filter(|(_, lab)| matches!(lab.vis, Visibility::Global | Visibility::Weak))
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            names.sort();
+            for name in names {
+                if indexed.insert(name.clone()) {
+                    symbols.push((name, idx));
+                }
+            }
+        }
+        Ok(Archive { members, symbols })
+    }
+
+    /// Read one member as an object, named after the archive and the member like
+    /// `archive.65r(member.65o)`.
+    pub fn object(&self, archive: &str, idx: usize) -> io::Result<Object> {
+        let member = &self.members[idx];
+        read_object(format!("{}({})", archive, member.name), &member.data[..])
+    }
+}
+
+/// Reads an archive.
+pub fn read_archive<R: Read>(mut ar_file: R) -> io::Result<Archive> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "");
+    let mut u32_buffer = [0; 4];
+    let mut name_buffer = [0; 64];
+
+    ar_file.read_exact(&mut u32_buffer)?;
+    if u32_buffer != MAGIC {
+        return Err(invalid());
+    }
+    ar_file.read_exact(&mut u32_buffer)?;
+    let num_members = u32::from_le_bytes(u32_buffer) as usize;
+    ar_file.read_exact(&mut u32_buffer)?;
+    let num_symbols = u32::from_le_bytes(u32_buffer) as usize;
+
+    let mut members = Vec::with_capacity(num_members.min(0x1000));
+    let mut sizes = Vec::with_capacity(num_members.min(0x1000));
+    for _ in 0..num_members {
+        ar_file.read_exact(&mut name_buffer)?;
+        ar_file.read_exact(&mut u32_buffer)?;
+        members.push(Member {
+            name: read_name(&name_buffer),
+            data: Vec::new(),
+        });
+        sizes.push(u32::from_le_bytes(u32_buffer) as usize);
+    }
+
+    let mut symbols = Vec::with_capacity(num_symbols.min(0x1000));
+    for _ in 0..num_symbols {
+        ar_file.read_exact(&mut name_buffer)?;
+        ar_file.read_exact(&mut u32_buffer)?;
+        let member = u32::from_le_bytes(u32_buffer) as usize;
+        if member >= num_members {
+            return Err(invalid());
+        }
+        symbols.push((read_name(&name_buffer), member));
+    }
+
+    for (member, size) in members.iter_mut().zip(sizes) {
+        // pad to 4 bytes
+        let padded = size + ((4 - (size & 3)) & 3);
+        ar_file
+            .by_ref()
+            .take(padded as u64)
+            .read_to_end(&mut member.data)?;
+        if member.data.len() != padded {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, ""));
+        }
+        member.data.truncate(size);
+    }
+
+    Ok(Archive { members, symbols })
+}
+
+/// Writes an archive.
+pub fn write_archive<W: Write>(archive: &Archive, out: W) -> io::Result<()> {
+    let mut ar_file = BufWriter::with_capacity(0x10000, out);
+    // names always leave room for the null terminator
+    let write_name = |ar_file: &mut BufWriter<W>, name: &str| {
+        let mut name_buffer = [0; 64];
+        let len = name.len().min(63);
+        name_buffer[..len].copy_from_slice(&name.as_bytes()[..len]);
+        ar_file.write_all(&name_buffer)
+    };
+
+    ar_file.write_all(&MAGIC)?;
+    ar_file.write_all(&(archive.members.len() as u32).to_le_bytes())?;
+    ar_file.write_all(&(archive.symbols.len() as u32).to_le_bytes())?;
+    for member in &archive.members {
+        write_name(&mut ar_file, &member.name)?;
+        ar_file.write_all(&(member.data.len() as u32).to_le_bytes())?;
+    }
+    for (name, member) in &archive.symbols {
+        write_name(&mut ar_file, name)?;
+        ar_file.write_all(&(*member as u32).to_le_bytes())?;
+    }
+    for member in &archive.members {
+        let size = member.data.len();
+        ar_file.write_all(&member.data)?;
+        ar_file.write_all(&[0; 3][..((4 - (size & 3)) & 3)])?;
+    }
+
+    ar_file.flush()
+}
+
+/// Add the members of the archives needed to define everything the objects refer
+/// to, except what's in `symbols`, with references to `wrapped` symbols going where
+/// the link sends them.
+pub fn extract(
+    objects: &mut Vec<Object>,
+    symbols: &HashMap<String, usize>,
+    archives: &[(String, Archive)],
+    wrapped: &[String],
+) -> Result<(), Error> {
+    let mut taken = archives
+        .iter()
+        .map(|(_, archive)| vec![false; archive.members.len()])
+        .collect::<Vec<_>>();
+    let mut renames = HashMap::with_capacity(wrapped.len() * 2);
+    for symbol in wrapped {
+        renames.insert(symbol.clone(), format!("__wrap_{}", symbol));
+        renames.insert(format!("__real_{}", symbol), symbol.clone());
+    }
+    loop {
+        let undefined = undefined(objects, symbols, &renames);
+        let mut added = Vec::new();
+        for ((file, archive), taken) in archives.iter().zip(&mut taken) {
+            // members are taken in the order they're in the archive
+            let mut needed = archive
+                .symbols
+                .iter()
+                .filter(|(name, member)| !taken[*member] && undefined.contains(name))
+                .map(|&(_, member)| member)
+                .collect::<Vec<_>>();
+            needed.sort_unstable();
+            needed.dedup();
+            for member in needed {
+                taken[member] = true;
+                let obj = archive.object(file, member).map_err(|_| {
+                    Error::Read(format!("{}({})", file, archive.members[member].name))
+                })?;
+                added.push(obj);
+            }
+        }
+        if added.is_empty() {
+            return Ok(());
+        }
+        objects.append(&mut added);
+    }
+}
+
+/// Every label that's referred to but isn't in the object or defined globally, after
+/// renaming references that aren't found in their own object.
+fn undefined(
+    objects: &[Object],
+    symbols: &HashMap<String, usize>,
+    renames: &HashMap<String, String>,
+) -> HashSet<String> {
+    let defined = objects
+        .iter()
+        .flat_map(|obj| obj.sections.values())
+        .flat_map(|sect| &sect.labels)
+        .filter(|(_, lab)| matches!(lab.vis, Visibility::Global | Visibility::Weak))
+        .map(|(name, _)| name.as_str())
+        .collect::<HashSet<_>>();
+
+    let mut undefined = HashSet::new();
+    for obj in objects {
+        for (sect_name, sect) in &obj.sections {
+            for rf in &sect.references {
+                if find_label(obj, sect_name, rf).is_some() {
+                    continue;
+                }
+                let name = renames.get(&rf.referred).unwrap_or(&rf.referred);
+                if !defined.contains(name.as_str()) && !symbols.contains_key(name) {
+                    undefined.insert(name.clone());
+                }
+            }
+        }
+    }
+    undefined
+}
@@ -0,0 +1,197 @@
+//! Writes an image as a NES or C64 cartridge.
+//!
+//! An iNES file is a 16 byte header, the PRG ROM in 16K units and the CHR ROM in 8K
+//! units. The PRG ROM is every bank in order when there are banks, or else the image
+//! at the end of a window that ends at `$10000`, where the 6502 finds its vectors.
+//! The CHR ROM is the sections the layout lists with `chr`, each section from every
+//! object in order, one after another. Both are padded with zeros up to the size
+//! given in the settings, or to the next whole unit. The header has the mapper,
+//! mirroring and battery from the settings, and a NES 2.0 header with `nes2` also
+//! has the submapper, 8K of PRG-NVRAM with a battery and 8K of CHR-RAM without CHR
+//! ROM.
+//!
+//! A C64 `.crt` file is a 64 byte header followed by a CHIP packet for each bank, or
+//! for the whole image when there aren't banks, holding its bytes and the address
+//! they're at when the bank is switched in.
+
+use super::error::Error;
+use super::formats::*;
+use super::linker::Image;
+
+/// The size of a unit of PRG ROM.
+const PRG_UNIT: usize = 0x4000;
+
+/// The size of a unit of CHR ROM.
+const CHR_UNIT: usize = 0x2000;
+
+/// The shift of 8K of RAM in a NES 2.0 header, which is `64 << shift` bytes.
+const RAM_8K: u8 = 7;
+
+/// Take the CHR sections out of the objects, returning their bytes. They can't have
+/// references since they aren't placed.
+pub fn take_chr(layout: &Layout, objects: &mut [Object]) -> Result<Vec<u8>, Vec<Error>> {
+    let mut chr = Vec::new();
+    let mut errors = Vec::new();
+    for name in &layout.chr {
+        for obj in objects.iter_mut() {
+            let sect = match obj.sections.remove(name) {
+                Some(sect) => sect,
+                None => continue,
+            };
+            errors.extend(sect.references.iter().map(|rf| Error::ChrReference {
+                symbol: rf.referred.clone(),
+                object: obj.name.clone(),
+                section: name.clone(),
+            }));
+            chr.extend_from_slice(&sect.code[..sect.size]);
+        }
+    }
+    if errors.is_empty() {
+        Ok(chr)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The bytes of an iNES file for an image.
+pub fn ines(image: &Image) -> Result<Vec<u8>, Error> {
+    let settings = image.ines.clone().unwrap_or_default();
+    let invalid = |message: String| Error::Cartridge {
+        format: "iNES".to_string(),
+        message,
+    };
+    if settings.mapper > 0xFF && !settings.nes2 {
+        return Err(invalid(
+            "mappers above 255 need a NES 2.0 header".to_string(),
+        ));
+    }
+    if settings.submapper != 0 && !settings.nes2 {
+        return Err(invalid("submappers need a NES 2.0 header".to_string()));
+    }
+
+    // without banks the PRG ROM ends at 0x10000, with the image at the end of it
+    let needed = if !image.banks.is_empty() {
+        image.code.len()
+    } else if image.code.is_empty() {
+        0
+    } else if image.base < 0x8000 || image.base + image.code.len() > 0x10000 {
+        return Err(invalid(format!(
+            "the image is at {:#06x}-{:#06x}, outside of 0x8000-0xffff",
+            image.base,
+            image.base + image.code.len() - 1
+        )));
+    } else {
+        0x10000 - image.base
+    };
+    let prg_size = settings.prg.unwrap_or(needed.div_ceil(PRG_UNIT));
+    if needed > prg_size * PRG_UNIT {
+        return Err(invalid(format!(
+            "the PRG ROM is {:#x} bytes, more than {} units of 16K",
+            needed, prg_size
+        )));
+    }
+    let prg_offset = if image.banks.is_empty() {
+        prg_size * PRG_UNIT - needed
+    } else {
+        0
+    };
+    let chr_size = settings.chr.unwrap_or(image.chr.len().div_ceil(CHR_UNIT));
+    if image.chr.len() > chr_size * CHR_UNIT {
+        return Err(invalid(format!(
+            "the CHR ROM is {:#x} bytes, more than {} units of 8K",
+            image.chr.len(),
+            chr_size
+        )));
+    }
+    let limit = if settings.nes2 { 0xEFF } else { 0xFF };
+    if prg_size > limit || chr_size > limit {
+        return Err(invalid("the ROM is too large for the header".to_string()));
+    }
+
+    let mut bytes = Vec::with_capacity(16 + prg_size * PRG_UNIT + chr_size * CHR_UNIT);
+    bytes.extend_from_slice(b"NES\x1A");
+    bytes.push(prg_size as u8);
+    bytes.push(chr_size as u8);
+    let mut flags6 = (settings.mapper as u8 & 0x0F) << 4;
+    match settings.mirroring {
+        Mirroring::Horizontal => (),
+        Mirroring::Vertical => flags6 |= 0x01,
+        Mirroring::FourScreen => flags6 |= 0x08,
+    }
+    if settings.battery {
+        flags6 |= 0x02;
+    }
+    bytes.push(flags6);
+    let mut flags7 = settings.mapper as u8 & 0xF0;
+    if settings.nes2 {
+        flags7 |= 0x08;
+        bytes.push(flags7);
+        bytes.push((settings.submapper << 4 | (settings.mapper >> 8)) as u8);
+        bytes.push(((chr_size >> 8) << 4 | (prg_size >> 8)) as u8);
+        bytes.push(if settings.battery { RAM_8K << 4 } else { 0 });
+        bytes.push(if chr_size == 0 { RAM_8K } else { 0 });
+    } else {
+        bytes.push(flags7);
+    }
+    bytes.resize(16, 0);
+
+    let prg_start = bytes.len();
+    bytes.resize(prg_start + prg_offset, 0);
+    bytes.extend_from_slice(&image.code);
+    bytes.resize(prg_start + prg_size * PRG_UNIT, 0);
+    bytes.extend_from_slice(&image.chr);
+    bytes.resize(prg_start + prg_size * PRG_UNIT + chr_size * CHR_UNIT, 0);
+    Ok(bytes)
+}
+
+/// The bytes of a C64 cartridge file for an image.
+pub fn crt(image: &Image) -> Result<Vec<u8>, Error> {
+    let settings = image.crt.clone().unwrap_or_default();
+    // the bank number, address and bytes of each chip
+    let chips = if image.banks.is_empty() {
+        if image.code.len() > 0xFFFF {
+            return Err(Error::Cartridge {
+                format: "C64 cartridge".to_string(),
+                message: "the image is too large for one chip".to_string(),
+            });
+        }
+        vec![(0, image.base, &image.code[..])]
+    } else {
+        image
+            .banks
+            .iter()
+            .map(|bank| {
+                let code = &image.code[bank.offset..(bank.offset + bank.size)];
+                (bank.number, bank.start, code)
+            })
+            .collect()
+    };
+    let high = chips
+        .iter()
+        .any(|&(_, start, code)| start + code.len() > 0xA000);
+    let game = settings.game.unwrap_or(if high { 0 } else { 1 });
+
+    let mut bytes = Vec::with_capacity(0x40 + image.code.len() + chips.len() * 0x10);
+    bytes.extend_from_slice(b"C64 CARTRIDGE   ");
+    bytes.extend_from_slice(&0x40u32.to_be_bytes());
+    // version 1.0
+    bytes.extend_from_slice(&0x0100u16.to_be_bytes());
+    bytes.extend_from_slice(&(settings.hardware as u16).to_be_bytes());
+    bytes.push(settings.exrom);
+    bytes.push(game);
+    bytes.resize(0x20, 0);
+    bytes.extend_from_slice(settings.name.as_bytes());
+    bytes.resize(0x40, 0);
+
+    for (number, start, code) in chips.into_iter().filter(|(_, _, code)| !code.is_empty()) {
+        bytes.extend_from_slice(b"CHIP");
+        bytes.extend_from_slice(&(0x10 + code.len() as u32).to_be_bytes());
+        // ROM
+        bytes.extend_from_slice(&0u16.to_be_bytes());
+        bytes.extend_from_slice(&(number as u16).to_be_bytes());
+        bytes.extend_from_slice(&(start as u16).to_be_bytes());
+        bytes.extend_from_slice(&(code.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(code);
+    }
+    Ok(bytes)
+}
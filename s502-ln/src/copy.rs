@@ -0,0 +1,141 @@
+//! The copy table that startup code walks to set up RAM.
+//!
+//! Listing the `__copy_table` section in the linker script makes the linker fill it
+//! in, and it can be referred to by the global label `__copy_table`. All values are
+//! little endian words:
+//! ```text
+//! copy entries:
+//!     source: u16       load address of a section
+//!     destination: u16  run address of the section
+//!     length: u16
+//! terminated by an entry with a length of 0
+//!
+//! zero entries:
+//!     destination: u16  run address of a section in RAM
+//!     length: u16
+//! terminated by an entry with a length of 0
+//! ```
+//! There is a copy entry for every section with a load address and a zero entry for
+//! every other section placed in a RAM region. Empty sections are left out.
+
+use std::collections::HashMap;
+
+use super::formats::*;
+use super::linker::PlacedSection;
+
+/// The name of the section and label of the copy table.
+pub const COPY_TABLE: &str = "__copy_table";
+
+/// The sections the copy table has entries for.
+pub struct CopyTable {
+    copies: Vec<String>,
+    zeros: Vec<String>,
+}
+
+impl CopyTable {
+    /// Find which sections need to be copied or cleared, if the table is placed by
+    /// the layout and no object already has it.
+    pub fn new(layout: &Layout, objects: &[Object]) -> Option<Self> {
+        if !layout
+            .groups()
+            .any(|g| g.relocations.iter().any(|s| s == COPY_TABLE))
+            || objects
+                .iter()
+                .any(|obj| obj.sections.contains_key(COPY_TABLE))
+        {
+            return None;
+        }
+
+        let size = |name: &String| -> usize {
+            objects
+                .iter()
+                .filter_map(|obj| obj.sections.get(name))
+                .map(|part| part.size)
+                .sum()
+        };
+        let mut table = CopyTable {
+            copies: Vec::new(),
+            zeros: Vec::new(),
+        };
+        let ram = |name: &String| {
+            layout
+                .regions
+                .iter()
+                .find(|r| &r.name == name)
+                .is_some_and(|r| r.memory == Memory::Ram)
+        };
+        for stmt in &layout.statements {
+            match stmt {
+                Statement::Group(group) => {
+                    let ram = group.region.as_ref().is_some_and(ram);
+                    for name in group.relocations.iter().filter(|&name| size(name) != 0) {
+                        if group.load.is_some() {
+                            table.copies.push(name.clone());
+                        } else if ram {
+                            table.zeros.push(name.clone());
+                        }
+                    }
+                }
+                // packed sections are only known to be in RAM if every region is
+                Statement::Pack(pack) if pack.regions.iter().all(ram) => table.zeros.extend(
+                    pack.sections
+                        .iter()
+                        .map(|packed| &packed.name)
+                        .filter(|&name| size(name) != 0)
+                        .cloned(),
+                ),
+                _ => (),
+            }
+        }
+        Some(table)
+    }
+
+    /// The size of the table including both terminators.
+    pub fn size(&self) -> usize {
+        (self.copies.len() + 1) * 6 + (self.zeros.len() + 1) * 4
+    }
+
+    /// An empty section big enough to hold the table.
+    pub fn section(&self) -> Section {
+        let mut labels = HashMap::with_capacity(1);
+        labels.insert(
+            COPY_TABLE.to_string(),
+            Label {
+                vis: Visibility::Global,
+                offset: 0,
+            },
+        );
+        Section {
+            code: vec![0; self.size()],
+            labels,
+            references: Vec::new(),
+            base: 0,
+            load: 0,
+            bank: None,
+            size: self.size(),
+            align: 1,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Fill in the table once every section has been placed.
+    pub fn write(&self, sect: &mut Section, placed: &[PlacedSection]) {
+        let mut words = Vec::with_capacity(self.size() / 2);
+        let find = |name: &String| placed.iter().find(|s| &s.name == name).unwrap();
+        for name in &self.copies {
+            let sect = find(name);
+            words.extend_from_slice(&[sect.load, sect.base, sect.size]);
+        }
+        words.extend_from_slice(&[0, 0, 0]);
+        for name in &self.zeros {
+            let sect = find(name);
+            words.extend_from_slice(&[sect.base, sect.size]);
+        }
+        words.extend_from_slice(&[0, 0]);
+
+        for (idx, word) in words.into_iter().enumerate() {
+            sect.code[idx * 2] = word as u8;
+            sect.code[idx * 2 + 1] = (word >> 8) as u8;
+        }
+    }
+}
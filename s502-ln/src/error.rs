@@ -0,0 +1,361 @@
+//! Errors that can occur while reading inputs and linking.
+
+use std::fmt;
+
+/// Anything that can stop a link from succeeding.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A file couldn't be read or isn't in the expected format.
+    Read(String),
+    /// An input file has an extension that isn't `.65o`, `.65s` or `.65r`.
+    Extension(String),
+    /// An error in the linker script.
+    Script { line: usize, message: String },
+    /// An expression in the linker script couldn't be evaluated while placing.
+    Expression { line: usize, message: String },
+    /// A symbol defined on the command line isn't `NAME=VALUE` with a valid value.
+    Definition { definition: String, message: String },
+    /// A symbol is defined globally more than once.
+    DuplicateSymbol(String),
+    /// A symbol to keep sections from isn't a global label of any object.
+    UnknownRoot(String),
+    /// A section in an object isn't listed in the linker script.
+    UnplacedSection(String),
+    /// A section runs past the end of the address space.
+    AddressSpace { section: String, end: usize },
+    /// A group is larger than the maximum size given in the linker script.
+    GroupOverflow {
+        sections: Vec<String>,
+        size: usize,
+        max_size: usize,
+    },
+    /// Two sections from different groups share addresses, `end` is exclusive.
+    Overlap {
+        first: String,
+        second: String,
+        start: usize,
+        end: usize,
+    },
+    /// A packed section doesn't fit in any of the regions it may go in.
+    PackOverflow { section: String, size: usize },
+    /// A packed section must be with a section that isn't placed before it.
+    PackWith { section: String, with: String },
+    /// A group assigned to a region that isn't in the layout.
+    UnknownRegion {
+        region: String,
+        sections: Vec<String>,
+    },
+    /// A group starts outside of the region it's assigned to.
+    RegionStart {
+        region: String,
+        sections: Vec<String>,
+        address: usize,
+    },
+    /// A group runs past the end of the region it's assigned to.
+    RegionOverflow {
+        region: String,
+        sections: Vec<String>,
+        over: usize,
+    },
+    /// A section placed in RAM has something other than zeros in it.
+    RamData { region: String, section: String },
+    /// A reference to a label that couldn't be resolved.
+    Undefined {
+        symbol: String,
+        object: String,
+        section: String,
+        offset: usize,
+    },
+    /// A reference to a label in a bank that may not be switched in.
+    CrossBank {
+        symbol: String,
+        object: String,
+        section: String,
+        offset: usize,
+        bank: usize,
+    },
+    /// The trampoline template named in the linker script isn't in any object.
+    NoTemplate(String),
+    /// A call into a bank that may not be switched in comes from code that's always
+    /// switched in, but the linker script has no trampoline template for it.
+    FixedCall {
+        symbol: String,
+        object: String,
+        section: String,
+        offset: usize,
+        bank: usize,
+    },
+    /// A section given `page` in the layout crosses a page, `end` is exclusive.
+    PageCross {
+        section: String,
+        start: usize,
+        end: usize,
+    },
+    /// A block that must stay in one page crosses one, `end` is exclusive.
+    BlockPageCross {
+        label: String,
+        object: String,
+        section: String,
+        start: usize,
+        end: usize,
+    },
+    /// An assertion from an object is 0 once everything is placed.
+    Assertion {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    /// An assertion from an object couldn't be evaluated.
+    AssertionExpression {
+        file: String,
+        line: usize,
+        message: String,
+    },
+    /// The interrupt vector table is made without a handler for a vector.
+    MissingVector(String),
+    /// The entry or an interrupt handler isn't a global symbol.
+    NotGlobal { what: String, symbol: String },
+    /// A section is output while there are banks but isn't in one.
+    Unbanked(String),
+    /// The image has banks but is output in a format that can't hold them.
+    Banked(String),
+    /// Something output with the image needs an entry, but none was given.
+    NoEntry(String),
+    /// The image starts before the end of the BASIC stub in front of it.
+    StubOverlap { start: usize, end: usize },
+    /// The image can't be written as a cartridge with the settings given.
+    Cartridge { format: String, message: String },
+    /// A section of CHR data has a reference, which can't be resolved since it
+    /// isn't placed.
+    ChrReference {
+        symbol: String,
+        object: String,
+        section: String,
+    },
+    /// Cartridge settings from the command line couldn't be parsed.
+    Settings { what: String, message: String },
+    /// A branch whose target is too far away.
+    BranchRange {
+        symbol: String,
+        object: String,
+        section: String,
+        offset: usize,
+        distance: isize,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Read(file) => write!(f, "error reading file {}", file),
+            Extension(file) => write!(f, "file {} has wrong extension", file),
+            Script { line, message } => write!(f, "error on line {}: {}", line, message),
+            Expression { line, message } => write!(
+                f,
+                "error evaluating line {} of the linker script: {}",
+                line, message
+            ),
+            Definition {
+                definition,
+                message,
+            } => write!(f, "invalid symbol definition `{}`: {}", definition, message),
+            DuplicateSymbol(sym) => write!(f, "`{}` is defined multiple times", sym),
+            UnknownRoot(sym) => write!(f, "`{}` to keep is not a global label", sym),
+            UnplacedSection(sect) => {
+                write!(f, "section {} is not placed by the linker script", sect)
+            }
+            AddressSpace { section, end } => write!(
+                f,
+                "section {} ends at {:#06x}, past the end of the address space",
+                section, end
+            ),
+            GroupOverflow {
+                sections,
+                size,
+                max_size,
+            } => write!(
+                f,
+                "group `{}` is {:#x} bytes, larger than its maximum of {:#x}",
+                sections.join(" "),
+                size,
+                max_size
+            ),
+            Overlap {
+                first,
+                second,
+                start,
+                end,
+            } => write!(
+                f,
+                "sections {} and {} overlap at {:#06x}-{:#06x}",
+                first,
+                second,
+                start,
+                end - 1
+            ),
+            PackOverflow { section, size } => write!(
+                f,
+                "section {} ({:#x} bytes) doesn't fit in any of the regions it's packed into",
+                section, size
+            ),
+            PackWith { section, with } => write!(
+                f,
+                "section {} must be with section {}, which isn't placed before it",
+                section, with
+            ),
+            UnknownRegion { region, sections } => write!(
+                f,
+                "group `{}` is assigned to region {} which doesn't exist",
+                sections.join(" "),
+                region
+            ),
+            RegionStart {
+                region,
+                sections,
+                address,
+            } => write!(
+                f,
+                "group `{}` starts at {:#x}, outside of region {}",
+                sections.join(" "),
+                address,
+                region
+            ),
+            RegionOverflow {
+                region,
+                sections,
+                over,
+            } => write!(
+                f,
+                "group `{}` overflows region {} by {:#x} bytes",
+                sections.join(" "),
+                region,
+                over
+            ),
+            RamData { region, section } => write!(
+                f,
+                "section {} has data but is placed in RAM region {}",
+                section, region
+            ),
+            Undefined {
+                symbol,
+                object,
+                section,
+                offset,
+            } => write!(
+                f,
+                "undefined reference to `{}` in {} section {} at offset {:#x}",
+                symbol, object, section, offset
+            ),
+            CrossBank {
+                symbol,
+                object,
+                section,
+                offset,
+                bank,
+            } => write!(
+                f,
+                "reference to `{}` in {} section {} at offset {:#x} is to bank {}, \
+                 which may not be switched in",
+                symbol, object, section, offset, bank
+            ),
+            NoTemplate(sect) => write!(f, "no object has the trampoline template {}", sect),
+            FixedCall {
+                symbol,
+                object,
+                section,
+                offset,
+                bank,
+            } => write!(
+                f,
+                "call to `{}` in bank {} from {} section {} at offset {:#x} needs a second \
+                 trampoline template for code that's always switched in, or `{}` listed as `far`",
+                symbol, bank, object, section, offset, symbol
+            ),
+            PageCross {
+                section,
+                start,
+                end,
+            } => write!(
+                f,
+                "section {} at {:#06x}-{:#06x} crosses from page {:#04x} into page {:#04x}",
+                section,
+                start,
+                end - 1,
+                start >> 8,
+                (end - 1) >> 8
+            ),
+            BlockPageCross {
+                label,
+                object,
+                section,
+                start,
+                end,
+            } => write!(
+                f,
+                "`{}` in {} section {} at {:#06x}-{:#06x} crosses from page {:#04x} into page {:#04x}",
+                label,
+                object,
+                section,
+                start,
+                end - 1,
+                start >> 8,
+                (end - 1) >> 8
+            ),
+            Assertion {
+                file,
+                line,
+                message,
+            } => write!(
+                f,
+                "assertion failed in {} on line {}: {}",
+                file, line, message
+            ),
+            AssertionExpression {
+                file,
+                line,
+                message,
+            } => write!(
+                f,
+                "error evaluating assertion in {} on line {}: {}",
+                file, line, message
+            ),
+            MissingVector(vector) => write!(f, "no handler is given for the {} vector", vector),
+            NotGlobal { what, symbol } => {
+                write!(f, "{} `{}` is not a global symbol", what, symbol)
+            }
+            Unbanked(sect) => write!(f, "section {} is in ROM but not in a bank", sect),
+            Banked(format) => write!(f, "{} output can't have banks", format),
+            NoEntry(what) => write!(f, "{} needs an entry", what),
+            StubOverlap { start, end } => write!(
+                f,
+                "the image starts at {:#06x}, before the BASIC stub ends at {:#06x}",
+                start, end
+            ),
+            Cartridge { format, message } => write!(f, "can't write {}: {}", format, message),
+            ChrReference {
+                symbol,
+                object,
+                section,
+            } => write!(
+                f,
+                "reference to {} in CHR section {} of {}",
+                symbol, section, object
+            ),
+            Settings { what, message } => write!(f, "invalid {} settings: {}", what, message),
+            BranchRange {
+                symbol,
+                object,
+                section,
+                offset,
+                distance,
+            } => write!(
+                f,
+                "branch to `{}` in {} section {} at offset {:#x} is out of range ({})",
+                symbol, object, section, offset, distance
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
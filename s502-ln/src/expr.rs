@@ -0,0 +1,170 @@
+//! Expressions in the linker script and in assertions from objects.
+//!
+//! Expressions are evaluated while placing, in the order they appear in the script,
+//! so they may only use symbols assigned above them and sections placed before them.
+//! Assertions are evaluated once everything has been placed.
+
+use std::collections::HashMap;
+
+use super::formats::Object;
+use super::linker::{section_size, section_symbol, PlacedSection};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnOp {
+    Neg,
+    Not,
+    /// `!`, 1 if the value is 0 and 0 otherwise.
+    LogicalNot,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    // comparisons and logical operators are 1 when true and 0 when false
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Number(usize),
+    /// The location counter `.`, where the next group without an address goes.
+    Location,
+    Symbol(String),
+    /// `SIZEOF(sect)`, the combined size of a section from every object.
+    SizeOf(String),
+    /// `ADDR(sect)`, the address of a section that's already been placed.
+    Addr(String),
+    /// `ALIGN(align)` or `ALIGN(expr, align)`, the location counter or expression
+    /// rounded up to a multiple of the alignment.
+    Align(Option<Box<Expr>>, Box<Expr>),
+    Unary(UnOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+}
+
+/// Everything an expression may use.
+pub struct Context<'a> {
+    pub location: usize,
+    /// Symbols assigned in the script so far.
+    pub assigned: &'a HashMap<String, usize>,
+    /// Symbols from symbol tables.
+    pub symbols: &'a HashMap<String, usize>,
+    /// Sections placed so far.
+    pub placed: &'a [PlacedSection],
+    pub objects: &'a [Object],
+}
+
+impl Expr {
+    /// Call `f` on this expression and everything inside of it.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Expr::Align(value, align) => {
+                if let Some(value) = value {
+                    value.walk(f);
+                }
+                align.walk(f);
+            }
+            Expr::Unary(_, value) => value.walk(f),
+            Expr::Binary(_, left, right) => {
+                left.walk(f);
+                right.walk(f);
+            }
+            _ => (),
+        }
+    }
+
+    /// Evaluate an expression, the error is a message to report with the line.
+    pub fn eval(&self, ctx: &Context) -> Result<isize, String> {
+        use Expr::*;
+        Ok(match self {
+            Number(num) => *num as isize,
+            Location => ctx.location as isize,
+            Symbol(name) => match ctx
+                .assigned
+                .get(name)
+                .or_else(|| ctx.symbols.get(name))
+                .copied()
+                .or_else(|| section_symbol(name, ctx.placed))
+            {
+                Some(value) => value as isize,
+                None => return Err(format!("`{}` is not defined before it's used", name)),
+            },
+            SizeOf(sect) => {
+                if !ctx
+                    .objects
+                    .iter()
+                    .any(|obj| obj.sections.contains_key(sect))
+                {
+                    return Err(format!("no object has a section {}", sect));
+                }
+                section_size(ctx.objects, sect) as isize
+            }
+            Addr(sect) => match ctx.placed.iter().find(|s| &s.name == sect) {
+                Some(placed) => placed.base as isize,
+                None => return Err(format!("section {} is not placed before it's used", sect)),
+            },
+            Align(value, align) => {
+                let value = match value {
+                    Some(value) => value.eval(ctx)?,
+                    None => ctx.location as isize,
+                };
+                let align = align.eval(ctx)?;
+                if align <= 0 {
+                    return Err(format!("can't align to {}", align));
+                }
+                (value + align - 1).div_euclid(align) * align
+            }
+            Unary(op, value) => {
+                let value = value.eval(ctx)?;
+                match op {
+                    UnOp::Neg => -value,
+                    UnOp::Not => !value,
+                    UnOp::LogicalNot => (value == 0) as isize,
+                }
+            }
+            Binary(op, left, right) => {
+                let left = left.eval(ctx)?;
+                let right = right.eval(ctx)?;
+                match op {
+                    BinOp::Add => left.wrapping_add(right),
+                    BinOp::Sub => left.wrapping_sub(right),
+                    BinOp::Mul => left.wrapping_mul(right),
+                    BinOp::Div | BinOp::Mod if right == 0 => {
+                        return Err("division by zero".to_string())
+                    }
+                    BinOp::Div => left.wrapping_div(right),
+                    BinOp::Mod => left.wrapping_rem(right),
+                    BinOp::And => left & right,
+                    BinOp::Or => left | right,
+                    BinOp::Xor => left ^ right,
+                    BinOp::Shl => left.wrapping_shl(right as u32),
+                    BinOp::Shr => left.wrapping_shr(right as u32),
+                    BinOp::Eq => (left == right) as isize,
+                    BinOp::Ne => (left != right) as isize,
+                    BinOp::Lt => (left < right) as isize,
+                    BinOp::Le => (left <= right) as isize,
+                    BinOp::Gt => (left > right) as isize,
+                    BinOp::Ge => (left >= right) as isize,
+                    BinOp::LogicalAnd => (left != 0 && right != 0) as isize,
+                    BinOp::LogicalOr => (left != 0 || right != 0) as isize,
+                }
+            }
+        })
+    }
+}
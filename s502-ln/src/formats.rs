@@ -41,6 +41,7 @@
 //! ```text
 //! 1 -> from anywhere in this object
 //! 2 -> globally from any object
+//! 3 -> globally from any object, unless another object defines it with 2 (weak)
 //! ```
 //! After a parent label is a number of child labels with the form:
 //!
@@ -59,6 +60,7 @@
 //!   (parent <= ref < next) must hold in order to resolve the reference.
 //! 1 -> from anywhere in this object
 //! 2 -> globally from any object
+//! 3 -> weak, as with parent labels
 //! ```
 //! After the child labels of a parent is the next parent label.
 //!
@@ -88,6 +90,55 @@
 //! but it is padded to a 4 byte boundary. The number of bytes to read may be calculated
 //! `size + ((4 - (size & 3)) & 3)`.
 //!
+//! After all of the sections are the attributes of the sections that have any, and
+//! then the assertions. Objects from older assemblers may end before either of them,
+//! which is the same as having none.
+//!
+//! ### Attributes Header
+//! ```text
+//! num_attributes: u32
+//! ```
+//!
+//! ### Section Attributes
+//! ```text
+//! section: 32 ASCII bytes
+//! align: u32
+//! num_blocks: u32
+//! ```
+//! `section` is the name of a section above. Its part of the section must start at a
+//! multiple of `align`, a power of two. After it are the blocks of the section that
+//! must not cross a page:
+//!
+//! ### Block
+//! ```text
+//! label: 32 ASCII bytes
+//! offset: u32
+//! size: u32
+//! ```
+//! `label` is the parent label the block starts at and `offset` its offset into the
+//! section. The `size` bytes from it must all be in the same 256 byte page once placed.
+//!
+//! ### Assertions Header
+//! ```text
+//! num_assertions: u32
+//! ```
+//!
+//! ### Assertion
+//! ```text
+//! section: 32 ASCII bytes
+//! offset: u32
+//! line: u32
+//! file_size: u32
+//! expression_size: u32
+//! message_size: u32
+//! ```
+//! `section` and `offset` are where the assertion was made, which is what `.` in its
+//! expression refers to, and `line` is its line in the source file. After it are the
+//! name of the source file, which may be empty, the expression and the message, each
+//! `size` bytes of UTF-8 padded to a 4 byte boundary. The expression is in the syntax
+//! of linker script expressions, and the linker reports the message if it's 0 once
+//! everything has been placed.
+//!
 //! ## Symbol Table
 //! The linker may output symbol tables while linking objects together. This is convenient for
 //! resolving references to binaries that were linked separately and loaded elsewhere in memory
@@ -103,21 +154,95 @@
 //! name: 64 ASCII bytes
 //! address: u32
 //! ```
+//!
+//! ## Archive
+//! Archives bundle object files into a library with the extension `.65r`, from which
+//! the linker only takes the objects it needs. They're made by `s502-ar` and have the
+//! form:
+//!
+//! ### Archive Header
+//! ```text
+//! magic: 4 ASCII bytes "65r"
+//! num_members: u32
+//! num_symbols: u32
+//! ```
+//! It is followed by a header for each member:
+//!
+//! ### Member Header
+//! ```text
+//! name: 64 ASCII bytes
+//! size: u32
+//! ```
+//! `name` is the file name of the object without any directories, and `size` is the
+//! length of the object file. After the member headers is the symbol index:
+//!
+//! ### Indexed Symbol
+//! ```text
+//! name: 64 ASCII bytes
+//! member: u32
+//! ```
+//! `member` is the index of the member defining `name` as a global label. After the
+//! index is the object file of each member in order, padded to a 4 byte boundary.
 
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::ops::Range;
+
+use super::expr::Expr;
+
+/// An object as read from an object file.
+pub struct Object {
+    /// The name the object is known by in diagnostics, usually its file name.
+    pub name: String,
+    pub sections: HashMap<String, Section>,
+    pub assertions: Vec<Assertion>,
+}
+
+/// An expression from an object that must not be 0 once everything is placed.
+#[derive(Clone)]
+pub struct Assertion {
+    /// The section it was made in, where `.` is `offset` into.
+    pub section: String,
+    pub offset: usize,
+    /// The source file it came from, empty if it isn't known.
+    pub file: String,
+    pub line: usize,
+    pub expression: String,
+    pub message: String,
+}
 
 /// A section as read from the object file.
 pub struct Section {
-    pub code: [u8; 65536],
+    /// The section's payload, `size` bytes long.
+    pub code: Vec<u8>,
     pub labels: HashMap<String, Label>,
     pub references: Vec<Reference>,
-    /// The base address of this section.
+    /// The base address of this section once it has been placed.
     pub base: usize,
+    /// Where the section's bytes are in the output, usually the same as `base`.
+    pub load: usize,
+    /// The bank the section was placed in if it's in one.
+    pub bank: Option<usize>,
+    pub size: usize,
+    /// The section is placed at a multiple of this.
+    pub align: usize,
+    /// Parts of the section that must not cross a page.
+    pub blocks: Vec<Block>,
+}
+
+impl Section {
+    /// The address of an offset into the placed section, with its bank in bits
+    /// 16-23 like in a symbol table.
+    pub fn address(&self, offset: usize) -> usize {
+        self.bank.unwrap_or(0) << 16 | (self.base + offset)
+    }
+}
+
+/// A labeled part of a section that must stay inside of one page.
+#[derive(Clone)]
+pub struct Block {
+    pub label: String,
+    pub offset: usize,
     pub size: usize,
-    /// The objects from which this section was taken.
-    pub objects: HashMap<String, Range<usize>>,
 }
 
 #[derive(Clone)]
@@ -148,20 +273,202 @@ pub enum Visibility {
     Hidden = 0,
     Object = 1,
     Global = 2,
+    Weak = 3,
+}
+
+/// Where everything should be placed, as read from a linker script.
+#[derive(Default)]
+pub struct Layout {
+    pub regions: Vec<Region>,
+    /// Groups and assignments in the order they're placed.
+    pub statements: Vec<Statement>,
+    /// Symbols that may be referred to from outside of their bank.
+    pub far: Vec<String>,
+    /// The section to copy for each trampoline into another bank.
+    pub trampoline: Option<String>,
+    /// The section to copy instead for calls from code that's always switched in.
+    pub fixed_trampoline: Option<String>,
+    /// The multiple each section given `align=` starts at.
+    pub align: HashMap<String, usize>,
+    /// Sections given `page`, which must not cross a page.
+    pub pages: Vec<String>,
+    /// The symbol the program starts at.
+    pub entry: Option<String>,
+    /// The handlers for the interrupt vector table if the linker makes one.
+    pub vectors: Option<Vectors>,
+    /// Sections holding CHR data, which are output after the PRG ROM in an iNES file
+    /// instead of being placed.
+    pub chr: Vec<String>,
+    /// Settings for the header of an iNES file.
+    pub ines: Option<Ines>,
+    /// Settings for the header of a C64 cartridge.
+    pub crt: Option<Crt>,
+}
+
+impl Layout {
+    /// Every group in the order they're placed.
+    pub fn groups(&self) -> impl Iterator<Item = &RelocGroup> {
+        self.statements.iter().filter_map(|stmt| match stmt {
+            Statement::Group(group) => Some(group),
+            _ => None,
+        })
+    }
+
+    /// Every group of packed sections in the order they're placed.
+    pub fn packs(&self) -> impl Iterator<Item = &PackGroup> {
+        self.statements.iter().filter_map(|stmt| match stmt {
+            Statement::Pack(pack) => Some(pack),
+            _ => None,
+        })
+    }
+}
+
+/// Handlers given for the interrupt vector table, see the `vectors` module.
+#[derive(Clone, Default)]
+pub struct Vectors {
+    pub nmi: Option<String>,
+    /// The entry is the reset handler if this isn't given.
+    pub reset: Option<String>,
+    pub irq: Option<String>,
+}
+
+/// Settings for an iNES or NES 2.0 header, see the `cart` module.
+#[derive(Clone, Default)]
+pub struct Ines {
+    pub mapper: usize,
+    /// Only in a NES 2.0 header.
+    pub submapper: usize,
+    /// The size of PRG ROM in 16K units, just large enough for the image if not given.
+    pub prg: Option<usize>,
+    /// The size of CHR ROM in 8K units, just large enough for the CHR data if not given.
+    pub chr: Option<usize>,
+    pub mirroring: Mirroring,
+    /// The cartridge has battery-backed RAM.
+    pub battery: bool,
+    /// Write a NES 2.0 header instead of an iNES one.
+    pub nes2: bool,
+}
+
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Mirroring {
+    #[default]
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+/// Settings for the header of a C64 cartridge, see the `cart` module.
+#[derive(Clone, Default)]
+pub struct Crt {
+    /// The hardware type, 0 for a normal cartridge.
+    pub hardware: usize,
+    /// The EXROM line.
+    pub exrom: u8,
+    /// The GAME line, 0 if not given and anything is placed from `$A000` and 1
+    /// otherwise.
+    pub game: Option<u8>,
+    pub name: String,
+}
+
+/// One line of a layout.
+pub enum Statement {
+    Group(RelocGroup),
+    Pack(PackGroup),
+    /// `name = value`, or `. = value` which moves the location counter when `name`
+    /// is `None`. The line is kept for reporting errors.
+    Assign {
+        name: Option<String>,
+        value: Expr,
+        line: usize,
+    },
+}
+
+/// Sections that are each placed in whichever of a set of regions they fit in.
+pub struct PackGroup {
+    pub sections: Vec<Packed>,
+    /// The regions to choose from in the order they're tried.
+    pub regions: Vec<String>,
+    pub fit: Fit,
+}
+
+/// A section in a [`PackGroup`].
+#[derive(Clone)]
+pub struct Packed {
+    pub name: String,
+    /// The section must be in the same bank as this one, or the same region if it
+    /// isn't in a bank.
+    pub with: Option<String>,
+}
+
+/// How a region is chosen for a packed section.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Fit {
+    /// The first region with room.
+    First,
+    /// The region with the least room left after it.
+    Best,
+}
+
+/// What a region of memory holds.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Memory {
+    /// Contents are written to the output.
+    Rom,
+    /// Space is only reserved, nothing placed here is written to the output.
+    Ram,
+}
+
+/// A named range of memory that groups may be placed in.
+#[derive(Clone)]
+pub struct Region {
+    pub name: String,
+    pub start: usize,
+    pub size: usize,
+    pub memory: Memory,
+    /// The region must be inside of the zero page.
+    pub zero_page: bool,
+    /// Byte to fill the unused parts of the region with. A ROM region with a fill
+    /// byte is always output in full.
+    pub fill: Option<u8>,
+    /// The bank the region is, banks may share addresses with each other.
+    pub bank: Option<usize>,
+}
+
+impl Region {
+    /// The address just past the end of the region.
+    pub fn end(&self) -> usize {
+        self.start + self.size
+    }
+}
+
+/// Where a group is loaded from.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Load {
+    Address(usize),
+    /// After the last group in the region.
+    Region(String),
 }
 
 /// A group of section relocations.
 ///
 /// All sections listed in the same line gets put into one group. Groups without
-/// a unique offset will have the same offset as the previous group and it will be
-/// adjusted later.
+/// an explicit address immediately follow the previous group, or the previous group
+/// in the same region if they're assigned to one.
 pub struct RelocGroup {
     /// The sections in this group in the order they're listed.
     pub relocations: Vec<String>,
-    // The offset the group starts at.
-    pub address: usize,
+    /// The address the group starts at if given.
+    pub address: Option<usize>,
     /// Maximum allowed size of the group if specified.
     pub max_size: Option<usize>,
+    /// The region the group must fit in if it's assigned to one.
+    pub region: Option<String>,
+    /// Where the group's bytes go in the output if it isn't where it runs from.
+    pub load: Option<Load>,
+    /// The group may overlap other groups.
+    pub overlay: bool,
+    /// The group's sections are never removed by garbage collection.
+    pub keep: bool,
 }
 
 impl PartialEq for RelocGroup {
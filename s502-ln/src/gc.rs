@@ -0,0 +1,163 @@
+//! Removes the parts of sections that nothing refers to.
+//!
+//! Every object's part of a section is kept separately, so a library routine that is
+//! never called is dropped even if other routines in the same section are used. The
+//! parts that are always kept are:
+//! - the parts defining the root symbols, such as the entry point
+//! - every part of a section in a `keep` group of the layout
+//! - every part of a section in a group placed at or above `0xFFFA`, the vectors
+//! - every part of a section or the label of a symbol used in a layout expression
+//! - the trampoline templates and sections made by the linker
+//!
+//! Anything a kept part refers to is kept as well, along with anything its assertions
+//! use, so they can still be checked. Referring to one of the symbols
+//! provided for a section, like `__data_start`, keeps every part of that section.
+
+use std::collections::{HashMap, HashSet};
+
+use super::error::Error;
+use super::expr::Expr;
+use super::formats::*;
+use super::linker::{find_label, split_section_symbol, LINKER_OBJECT};
+use super::script::parse_expr;
+
+/// The lowest address of the vectors, groups placed here are always kept.
+const VECTORS: usize = 0xFFFA;
+
+/// An object's part of a section that was removed.
+pub struct Removed {
+    pub object: String,
+    pub section: String,
+    pub size: usize,
+}
+
+/// Remove every part of a section that can't be reached from the roots, returning
+/// what was removed in the order of the objects.
+pub fn collect(
+    layout: &Layout,
+    objects: &mut [Object],
+    roots: &[String],
+) -> Result<Vec<Removed>, Vec<Error>> {
+    let mut errors = Vec::new();
+    // which part defines each global label, and every part of each section
+    let objs = &*objects;
+    let mut globals = HashMap::new();
+    let mut parts = HashMap::<&str, Vec<_>>::new();
+    for (idx, obj) in objs.iter().enumerate() {
+        for (name, sect) in &obj.sections {
+            parts.entry(name).or_default().push((idx, name));
+            for (label, lab) in &sect.labels {
+                if lab.vis == Visibility::Global {
+                    globals.insert(label.as_str(), (idx, name));
+                }
+            }
+        }
+    }
+
+    let mut keep = Vec::new();
+    let keep_section =
+        |keep: &mut Vec<_>, sect: &str| keep.extend(parts.get(sect).into_iter().flatten().copied());
+    for root in roots {
+        match globals.get(root.as_str()) {
+            Some(&part) => keep.push(part),
+            None => errors.push(Error::UnknownRoot(root.clone())),
+        }
+    }
+    for stmt in &layout.statements {
+        match stmt {
+            Statement::Group(group) if group.keep || group.address >= Some(VECTORS) => {
+                for sect in &group.relocations {
+                    keep_section(&mut keep, sect);
+                }
+            }
+            Statement::Assign { value, .. } => value.walk(&mut |expr| match expr {
+                Expr::Symbol(name) => keep.extend(globals.get(name.as_str())),
+                Expr::SizeOf(sect) | Expr::Addr(sect) => keep_section(&mut keep, sect),
+                _ => (),
+            }),
+            _ => (),
+        }
+    }
+    for template in layout.trampoline.iter().chain(&layout.fixed_trampoline) {
+        keep_section(&mut keep, template);
+    }
+    for (idx, obj) in objs.iter().enumerate() {
+        if obj.name == LINKER_OBJECT {
+            keep.extend(obj.sections.keys().map(|name| (idx, name)));
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // follow the references of everything kept
+    let mut reached = HashSet::new();
+    while let Some((idx, name)) = keep.pop() {
+        if !reached.insert((idx, name)) {
+            continue;
+        }
+        let obj = &objs[idx];
+        for rf in &obj.sections[name].references {
+            if let Some((target, _)) = find_label(obj, name, rf) {
+                keep.push((idx, target));
+            } else if let Some(&part) = globals.get(rf.referred.as_str()) {
+                keep.push(part);
+            } else if let Some((sect, _)) =
+                split_section_symbol(&rf.referred, |sect| parts.contains_key(sect))
+            {
+                keep_section(&mut keep, sect);
+            }
+        }
+        // an invalid expression is reported when the assertion is checked
+        let exprs = obj
+            .assertions
+            .iter()
+            .filter(|assertion| &assertion.section == name)
+            .filter_map(|assertion| parse_expr(&assertion.expression).ok());
+        for expr in exprs {
+            expr.walk(&mut |expr| match expr {
+                Expr::Symbol(symbol) => {
+                    let own = obj
+                        .sections
+                        .iter()
+                        .find(|(_, sect)| sect.labels.contains_key(symbol));
+                    if let Some((sect, _)) = own {
+                        keep.push((idx, sect));
+                    } else if let Some(&part) = globals.get(symbol.as_str()) {
+                        keep.push(part);
+                    } else if let Some((sect, _)) =
+                        split_section_symbol(symbol, |sect| parts.contains_key(sect))
+                    {
+                        keep_section(&mut keep, sect);
+                    }
+                }
+                Expr::SizeOf(sect) | Expr::Addr(sect) => keep_section(&mut keep, sect),
+                _ => (),
+            });
+        }
+    }
+
+    let reached = reached
+        .into_iter()
+        .map(|(idx, name)| (idx, name.clone()))
+        .collect::<HashSet<_>>();
+    let mut removed = Vec::new();
+    for (idx, obj) in objects.iter_mut().enumerate() {
+        let mut names = obj
+            .sections
+            .keys()
+            .filter(|&name| !reached.contains(&(idx, name.clone())))
+            .cloned()
+            .collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            let sect = obj.sections.remove(&name).unwrap();
+            removed.push(Removed {
+                object: obj.name.clone(),
+                section: name,
+                size: sect.size,
+            });
+        }
+    }
+    Ok(removed)
+}
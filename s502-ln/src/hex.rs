@@ -0,0 +1,122 @@
+//! Writes an image as Intel HEX or Motorola S-records, for EPROM programmers and
+//! bootloaders.
+//!
+//! Only the image's segments are written, each at the address it's loaded at, so
+//! the gaps between sections are left out. With banks the bank is the top byte of a
+//! 24-bit address, like in a symbol table. The entry, if there is one, is given as
+//! the start address.
+//!
+//! Intel HEX files have data records of up to 16 bytes, an extended linear address
+//! record whenever the top of the address changes, a start linear address record and
+//! an end of file record. S-record files have a header, S1 data records or S2 if any
+//! address needs 24 bits, an S5 count of the data records, and an S9 or S8 start
+//! address, which is 0 without an entry.
+
+use std::io::{self, Write};
+
+use super::linker::Image;
+
+/// The most data bytes in one record.
+const RECORD_SIZE: usize = 16;
+
+/// Write an image as Intel HEX.
+pub fn write_intel_hex<W: Write>(image: &Image, mut out: W) -> io::Result<()> {
+    let mut upper = 0;
+    for (address, data) in records(image) {
+        if address >> 16 != upper {
+            upper = address >> 16;
+            intel_record(&mut out, 0, 0x04, &(upper as u16).to_be_bytes())?;
+        }
+        intel_record(&mut out, address as u16, 0x00, data)?;
+    }
+    if let Some((_, entry)) = image.entry {
+        intel_record(&mut out, 0, 0x05, &(entry as u32).to_be_bytes())?;
+    }
+    intel_record(&mut out, 0, 0x01, &[])?;
+    out.flush()
+}
+
+/// Write an image as S-records.
+pub fn write_srec<W: Write>(image: &Image, mut out: W) -> io::Result<()> {
+    let wide = image
+        .segments
+        .iter()
+        .any(|seg| seg.address + seg.size > 0x10000);
+    let (data_type, start_type, address_size) = if wide { (2, 8, 3) } else { (1, 9, 2) };
+
+    srec_record(&mut out, 0, 0, 2, &[])?;
+    let mut count = 0;
+    for (address, data) in records(image) {
+        srec_record(&mut out, data_type, address, address_size, data)?;
+        count += 1;
+    }
+    if count <= 0xFFFF {
+        srec_record(&mut out, 5, count, 2, &[])?;
+    } else {
+        srec_record(&mut out, 6, count, 3, &[])?;
+    }
+    let start = image.entry.as_ref().map_or(0, |&(_, entry)| entry);
+    srec_record(&mut out, start_type, start, address_size, &[])?;
+    out.flush()
+}
+
+/// Every segment in pieces small enough for a record, which don't cross a 64K
+/// boundary.
+fn records(image: &Image) -> impl Iterator<Item = (usize, &[u8])> {
+    image.segments.iter().flat_map(move |seg| {
+        let mut address = seg.address;
+        let mut data = &image.code[seg.offset..(seg.offset + seg.size)];
+        std::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let len = data
+                .len()
+                .min(RECORD_SIZE)
+                .min(0x10000 - (address & 0xFFFF));
+            let (record, rest) = data.split_at(len);
+            let item = (address, record);
+            address += len;
+            data = rest;
+            Some(item)
+        })
+    })
+}
+
+/// Write one Intel HEX record, whose checksum makes all of its bytes add up to 0.
+fn intel_record<W: Write>(out: &mut W, address: u16, kind: u8, data: &[u8]) -> io::Result<()> {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(sum.wrapping_neg());
+    write!(out, ":")?;
+    write_hex(out, &bytes)
+}
+
+/// Write one S-record, whose checksum is the complement of the sum of its count,
+/// address and data.
+fn srec_record<W: Write>(
+    out: &mut W,
+    kind: u8,
+    address: usize,
+    address_size: usize,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut bytes = vec![(address_size + data.len() + 1) as u8];
+    bytes.extend_from_slice(&(address as u32).to_be_bytes()[(4 - address_size)..]);
+    bytes.extend_from_slice(data);
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(!sum);
+    write!(out, "S{}", kind)?;
+    write_hex(out, &bytes)
+}
+
+/// Write bytes in uppercase hex and end the line.
+fn write_hex<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    for byte in bytes {
+        write!(out, "{:02X}", byte)?;
+    }
+    writeln!(out)
+}
@@ -0,0 +1,57 @@
+//! The S502 linker.
+//!
+//! Objects may be linked entirely in memory by giving a [`Linker`] a layout, the
+//! objects, and any symbols that were linked elsewhere:
+//!
+//! ```text
+//! let obj = object::read_object("main".to_string(), &bytes[..])?;
+//! let layout = script::read_script("0x8000 text data\n")?;
+//! let image = Linker::new(layout, vec![obj], HashMap::new()).link()?;
+//! ```
+//!
+//! The layout is a [`formats::Layout`] of regions and groups which may also be built
+//! directly.
+//! The `s502-ln` binary reads everything from files and writes the image and
+//! symbol tables back out, or with `-r` merges the objects into one object with
+//! [`partial::merge`].
+//!
+//! Every placed section `sect` gets the symbols `__sect_start`, `__sect_end` (one
+//! past its last byte), `__sect_size` and `__sect_load` (where its bytes are in the
+//! output) unless something else in the link defines them. A section whose name
+//! already starts with `__`, like the generated `__vectors`, isn't prefixed again, so
+//! its symbols are `__vectors_start` and so on. Those from a symbol table of another
+//! link give way to them, and they aren't written to symbol tables. They may be
+//! referred to like any global label and used in linker script expressions.
+//!
+//! A weak label, marked with `?` in assembly, is a global label that gives way to a
+//! global label of the same name defined anywhere else, such as a default interrupt
+//! handler. If there are only weak labels of a name the first object's is used, but
+//! defining a global label more than once is an error.
+//!
+//! Symbols may also be defined with [`Linker::define`], like `--defsym` does, and a
+//! symbol may be wrapped with [`Linker::wrap`]: references to `sym` from outside of
+//! the object defining it go to `__wrap_sym`, and references to `__real_sym` go to
+//! `sym`, so a stub can stand in for a routine and still call it.
+
+// logos generates its impls inside of an anonymous const
+#![allow(non_local_definitions)]
+
+pub mod archive;
+pub mod cart;
+pub mod copy;
+pub mod error;
+pub mod expr;
+pub mod formats;
+pub mod gc;
+pub mod hex;
+pub mod linker;
+pub mod map;
+pub mod object;
+pub mod partial;
+pub mod prg;
+pub mod script;
+pub mod trampoline;
+pub mod vectors;
+
+pub use error::Error;
+pub use linker::{Image, Linker, PlacedBank, PlacedGroup, PlacedSection, Segment};
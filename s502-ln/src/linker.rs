@@ -1,31 +1,1502 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::read_to_string;
 use std::ops::Range;
 
+use super::archive::{extract, Archive};
+use super::cart::take_chr;
+use super::copy::{CopyTable, COPY_TABLE};
+use super::error::Error;
+use super::expr::Context;
 use super::formats::*;
+use super::gc::{self, Removed};
 use super::object::read_objects;
-use super::script::read_script;
+use super::script::{parse_crt, parse_expr, parse_ines, read_script};
+use super::trampoline::Trampolines;
+use super::vectors::{VectorTable, VECTORS};
 
+/// The name of the object holding sections made by the linker, like the copy table.
+pub const LINKER_OBJECT: &str = "<linker>";
+
+/// Places the sections of objects according to a layout and resolves the
+/// references between them.
 pub struct Linker {
-    relocations: Vec<RelocGroup>,
-    sections: HashMap<String, Section>,
+    layout: Layout,
+    objects: Vec<Object>,
     symbols: HashMap<String, usize>,
+    /// The symbols in `symbols` that were defined with `define` rather than
+    /// linked elsewhere.
+    defined: HashSet<String>,
+    /// Symbols assigned in the layout, known once everything is placed.
+    assigned: HashMap<String, usize>,
+    /// The symbols to start garbage collection from if it's enabled.
+    roots: Option<Vec<String>>,
+    /// Symbols whose references go to `__wrap_` versions of them.
+    wrapped: Vec<String>,
+    /// Archives to take the members needed from when linking.
+    archives: Vec<(String, Archive)>,
+}
+
+/// Where a section was placed and which objects contributed to it.
+pub struct PlacedSection {
+    pub name: String,
+    pub base: usize,
+    /// Where the section's bytes are in the output.
+    pub load: usize,
+    pub size: usize,
+    /// The bank the section runs from if it's in one.
+    pub bank: Option<usize>,
+    /// The bank the section's bytes are in if it's in one.
+    pub load_bank: Option<usize>,
+    /// The objects from which this section was taken and the addresses they occupy.
+    pub objects: Vec<(String, Range<usize>)>,
+}
+
+/// Where a group from the layout was placed.
+pub struct PlacedGroup {
+    pub base: usize,
+    /// Where the group's bytes are in the output if it isn't `base`.
+    pub load: Option<usize>,
+    pub size: usize,
+    /// Maximum allowed size of the group if specified.
+    pub max_size: Option<usize>,
+    /// The sections in this group in the order they were placed.
+    pub sections: Vec<String>,
+    /// The region the group was placed in if it was assigned to one.
+    pub region: Option<String>,
+    /// The bank the group runs from if it's in one.
+    pub bank: Option<usize>,
+    /// The group may overlap other groups.
+    pub overlay: bool,
+}
+
+/// Where a bank of ROM is in the output.
+pub struct PlacedBank {
+    pub number: usize,
+    pub region: String,
+    /// The address of the start of the bank when it's switched in.
+    pub start: usize,
+    pub size: usize,
+    /// The offset of the bank into the output.
+    pub offset: usize,
+}
+
+/// A run of output bytes that go at consecutive addresses.
+pub struct Segment {
+    /// Where the bytes are loaded, with the bank in bits 16-23 like in a symbol
+    /// table if there are banks.
+    pub address: usize,
+    /// The offset of the bytes into the output.
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// The result of a successful link.
+pub struct Image {
+    /// The address of the first byte of `code`, 0 if there are banks.
+    pub base: usize,
+    /// Everything from the lowest to the highest placed byte, gaps are filled with 0.
+    /// If there are banks it's every bank in order instead.
+    pub code: Vec<u8>,
+    /// Every bank of ROM in the order it's output, empty if there aren't any.
+    pub banks: Vec<PlacedBank>,
+    /// Where the bytes of `code` go in order of address, without the gaps between
+    /// sections. Filled regions are included in full.
+    pub segments: Vec<Segment>,
+    /// The CHR data for an iNES file.
+    pub chr: Vec<u8>,
+    /// The settings for an iNES header if any were given.
+    pub ines: Option<Ines>,
+    /// The settings for a C64 cartridge header if any were given.
+    pub crt: Option<Crt>,
+    /// The entry symbol and its address if one was given.
+    pub entry: Option<(String, usize)>,
+    /// The final address of every global symbol, including those from symbol tables
+    /// and the layout.
+    pub symbols: HashMap<String, usize>,
+    /// Symbols assigned in the layout.
+    pub assigned: HashMap<String, usize>,
+    /// Symbols the linker provided for each section.
+    pub provided: HashMap<String, usize>,
+    /// Every region in the layout.
+    pub regions: Vec<Region>,
+    /// Every group in the order it appears in the layout.
+    pub groups: Vec<PlacedGroup>,
+    /// Every section in the order it was placed.
+    pub sections: Vec<PlacedSection>,
+    /// The linked objects with the base of each section filled in.
+    pub objects: Vec<Object>,
+    /// The parts of sections removed by garbage collection.
+    pub removed: Vec<Removed>,
+}
+
+impl Image {
+    /// How much of a region is taken up by groups, where they run and where
+    /// they're loaded from.
+    pub fn used(&self, region: &Region) -> usize {
+        self.groups
+            .iter()
+            .filter(|g| g.region.as_ref() == Some(&region.name))
+            .chain(self.groups.iter().filter(|g| {
+                g.load
+                    .is_some_and(|load| (region.start..region.end()).contains(&load))
+            }))
+            .map(|g| g.size)
+            .sum()
+    }
+
+    /// Every symbol to write to a symbol table for other programs. The symbols
+    /// provided for sections are left out since they belong to this layout.
+    pub fn exported_symbols(&self) -> HashMap<String, usize> {
+        self.symbols
+            .iter()
+            .filter(|(name, _)| !self.provided.contains_key(*name))
+            .map(|(name, &value)| (name.clone(), value))
+            .collect()
+    }
+}
+
+/// A resolved value to put into a section.
+struct Patch {
+    object: usize,
+    section: String,
+    offset: usize,
+    bytes: Vec<u8>,
 }
 
 impl Linker {
-    pub fn new<'a>(script: &'a str, objects: Vec<String>) -> Result<Self, String> {
-        let (sections, symbols) = read_objects(objects)?;
-        Ok(Linker {
-            relocations: read_script(
-                read_to_string(script).map_err(|_| "error reading linker script".to_string())?,
-            )
-            .map_err(|(line, err)| format!("error on line {}: {}", line, err))?,
-            sections: sections,
-            symbols: symbols,
+    /// Create a linker from a layout, objects in the order they should be placed,
+    /// and symbols that were linked elsewhere.
+    pub fn new(layout: Layout, objects: Vec<Object>, symbols: HashMap<String, usize>) -> Self {
+        Linker {
+            layout,
+            objects,
+            symbols,
+            defined: HashSet::new(),
+            assigned: HashMap::new(),
+            roots: None,
+            wrapped: Vec::new(),
+            archives: Vec::new(),
+        }
+    }
+
+    /// Remove every part of a section that can't be reached from the given symbols
+    /// or the sections the `gc` module always keeps.
+    pub fn gc_sections(&mut self, roots: Vec<String>) {
+        self.roots = Some(roots);
+    }
+
+    /// Start the program at a symbol, in place of the layout's entry.
+    pub fn entry(&mut self, symbol: String) {
+        self.layout.entry = Some(symbol);
+    }
+
+    /// Make the interrupt vector table, with the handlers given here in place of
+    /// the layout's.
+    pub fn vectors(&mut self, nmi: Option<String>, irq: Option<String>) {
+        let vectors = self.layout.vectors.get_or_insert_with(Vectors::default);
+        vectors.nmi = nmi.or(vectors.nmi.take());
+        vectors.irq = irq.or(vectors.irq.take());
+    }
+
+    /// Define a symbol from `NAME=VALUE`, where the value is an expression that may
+    /// use symbols from symbol tables and symbols defined before it.
+    pub fn define(&mut self, definition: &str) -> Result<(), Error> {
+        let invalid = |message: &str| Error::Definition {
+            definition: definition.to_string(),
+            message: message.to_string(),
+        };
+        let (name, value) = match definition.split_once('=') {
+            Some((name, value)) => (name.trim(), value),
+            None => return Err(invalid("expected `NAME=VALUE`")),
+        };
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return Err(invalid("expected a symbol name before `=`"));
+        }
+        let ctx = Context {
+            location: 0,
+            assigned: &HashMap::new(),
+            symbols: &self.symbols,
+            placed: &[],
+            objects: &self.objects,
+        };
+        let value = parse_expr(value)
+            .and_then(|expr| expr.eval(&ctx))
+            .map_err(|message| invalid(&message))?;
+        if !(0..=0xFFFFFF).contains(&value) {
+            return Err(invalid("value is outside of the address space"));
+        }
+        if self
+            .symbols
+            .insert(name.to_string(), value as usize)
+            .is_some()
+        {
+            return Err(Error::DuplicateSymbol(name.to_string()));
+        }
+        self.defined.insert(name.to_string());
+        Ok(())
+    }
+
+    /// Send references to a symbol from outside of the object defining it to
+    /// `__wrap_symbol` instead, and references to `__real_symbol` to the symbol.
+    pub fn wrap(&mut self, symbol: String) {
+        self.wrapped.push(symbol);
+    }
+
+    /// Take the members of an archive that define what the objects need once the
+    /// symbols have been defined and wrapped.
+    pub fn archive(&mut self, file: String, archive: Archive) {
+        self.archives.push((file, archive));
+    }
+
+    /// Apply iNES settings from the command line over those in the layout.
+    pub fn ines(&mut self, settings: &str) -> Result<(), Error> {
+        let ines = self.layout.ines.get_or_insert_with(Ines::default);
+        parse_ines(settings, ines).map_err(|message| Error::Settings {
+            what: "iNES".to_string(),
+            message,
+        })
+    }
+
+    /// Apply C64 cartridge settings from the command line over those in the layout.
+    pub fn crt(&mut self, settings: &str) -> Result<(), Error> {
+        let crt = self.layout.crt.get_or_insert_with(Crt::default);
+        parse_crt(settings, crt).map_err(|message| Error::Settings {
+            what: "cartridge".to_string(),
+            message,
         })
     }
 
-    pub fn link(self) -> Result<(), String> {
+    /// Create a linker from a linker script and object and symbol table files.
+    pub fn from_files(script: &str, files: Vec<String>) -> Result<Self, Error> {
+        let layout =
+            read_script(&read_to_string(script).map_err(|_| Error::Read(script.to_string()))?)?;
+        let (objects, symbols, archives) = read_objects(files)?;
+        let mut linker = Linker::new(layout, objects, symbols);
+        linker.archives = archives;
+        Ok(linker)
+    }
+
+    /// Link everything into one image.
+    pub fn link(mut self) -> Result<Image, Vec<Error>> {
+        extract(
+            &mut self.objects,
+            &self.symbols,
+            &self.archives,
+            &self.wrapped,
+        )
+        .map_err(|e| vec![e])?;
+        self.settle_weak();
+        self.wrap_references();
+        let chr = take_chr(&self.layout, &mut self.objects)?;
+        let vectors = VectorTable::new(&self.layout).map_err(|e| vec![e])?;
+        let removed = match &self.roots {
+            Some(roots) => {
+                // the entry and the interrupt handlers are always roots
+                let roots = roots
+                    .iter()
+                    .chain(&self.layout.entry)
+                    .chain(vectors.iter().flat_map(|v| v.handlers()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                gc::collect(&self.layout, &mut self.objects, &roots)?
+            }
+            None => Vec::new(),
+        };
+        let switched = self.switched_banks();
+        let trampolines =
+            Trampolines::new(&self.layout, &mut self.objects, &self.symbols, &switched)?;
+        let table = CopyTable::new(&self.layout, &self.objects);
+        if let Some(table) = &table {
+            linker_object(&mut self.objects)
+                .sections
+                .insert(COPY_TABLE.to_string(), table.section());
+        }
+        if let Some(vectors) = &vectors {
+            linker_object(&mut self.objects)
+                .sections
+                .insert(VECTORS.to_string(), vectors.section());
+            if let Some(group) = vectors.group(&self.layout) {
+                self.layout.statements.push(Statement::Group(group));
+            }
+        }
+        self.drop_linked_section_symbols();
+        let (groups, sections) = self.place()?;
+        if let Some(table) = &table {
+            let sect = linker_object(&mut self.objects)
+                .sections
+                .get_mut(COPY_TABLE)
+                .unwrap();
+            table.write(sect, &sections);
+        }
+        let mut symbols = self.global_symbols()?;
+        // anything else defined in the link takes the place of a provided symbol
+        let provided = provided_symbols(&sections)
+            .filter(|(name, _)| !symbols.contains_key(name))
+            .collect::<HashMap<_, _>>();
+        symbols.extend(provided.iter().map(|(name, &value)| (name.clone(), value)));
+        let entry = self.find_entry(&symbols, vectors.as_ref())?;
+        if let Some(trampolines) = &trampolines {
+            trampolines.write(&mut self.objects, &symbols);
+        }
+        self.resolve(&symbols, &self.symbol_banks(&symbols, &provided, &sections))?;
+        self.check_assertions(&symbols, &sections)?;
+
+        // only what's placed in ROM or loaded from somewhere else is output
+        let rom = |group: &PlacedGroup| match &group.region {
+            Some(name) => self.region(name).unwrap().memory == Memory::Rom,
+            None => true,
+        };
+        self.check_ram(&groups)?;
+        let output = groups
+            .iter()
+            .filter(|g| g.load.is_some() || rom(g))
+            .flat_map(|g| &g.sections)
+            .map(|name| sections.iter().find(|s| &s.name == name).unwrap())
+            .collect::<Vec<_>>();
+        let banks = self.rom_banks();
+        let (start, mut code, offsets) = if banks.is_empty() {
+            self.flat_output(&output)
+        } else {
+            self.banked_output(&banks, &output)?
+        };
+        let segments = self.segments(start, &banks, &output, &offsets);
+        // copy in placement order so later sections overwrite earlier ones
+        for (placed, offset) in output.into_iter().zip(offsets) {
+            for obj in &self.objects {
+                if let Some(sect) = obj.sections.get(&placed.name) {
+                    let offset = offset + (sect.load - placed.load);
+                    code[offset..(offset + sect.size)].copy_from_slice(&sect.code);
+                }
+            }
+        }
+
+        Ok(Image {
+            base: start,
+            code,
+            banks,
+            segments,
+            chr,
+            ines: self.layout.ines,
+            crt: self.layout.crt,
+            entry,
+            symbols,
+            assigned: self.assigned,
+            provided,
+            regions: self.layout.regions,
+            groups,
+            sections,
+            objects: self.objects,
+            removed,
+        })
+    }
+
+    /// Assign a base address to every section of every object.
+    fn place(&mut self) -> Result<(Vec<PlacedGroup>, Vec<PlacedSection>), Vec<Error>> {
+        let mut errors = Vec::new();
+        let mut groups = Vec::with_capacity(self.layout.statements.len());
+        let mut placed = Vec::with_capacity(self.layout.statements.len() * 2);
+        let mut assigned = HashMap::new();
+        let mut address = 0;
+        // where the next group goes in each region
+        let regions = &self.layout.regions;
+        let mut next = regions
+            .iter()
+            .map(|r| (r.name.as_str(), r.start))
+            .collect::<HashMap<_, _>>();
+
+        for stmt in &self.layout.statements {
+            let group = match stmt {
+                Statement::Group(group) => group,
+                Statement::Pack(pack) => {
+                    errors.extend(pack_sections(
+                        pack,
+                        regions,
+                        &self.layout.align,
+                        &mut self.objects,
+                        &mut next,
+                        &mut groups,
+                        &mut placed,
+                    ));
+                    continue;
+                }
+                Statement::Assign { name, value, line } => {
+                    let ctx = Context {
+                        location: address,
+                        assigned: &assigned,
+                        symbols: &self.symbols,
+                        placed: &placed,
+                        objects: &self.objects,
+                    };
+                    match value.eval(&ctx) {
+                        Ok(value) if (0..0x10000).contains(&value) => match name {
+                            Some(name) => {
+                                assigned.insert(name.clone(), value as usize);
+                            }
+                            None => address = value as usize,
+                        },
+                        Ok(value) => errors.push(Error::Expression {
+                            line: *line,
+                            message: format!(
+                                "{}{:#x} is outside of the address space",
+                                if value < 0 { "-" } else { "" },
+                                value.unsigned_abs()
+                            ),
+                        }),
+                        Err(message) => errors.push(Error::Expression {
+                            line: *line,
+                            message,
+                        }),
+                    }
+                    continue;
+                }
+            };
+            let find = |name: &String| {
+                regions
+                    .iter()
+                    .find(|r| &r.name == name)
+                    .ok_or_else(|| Error::UnknownRegion {
+                        region: name.clone(),
+                        sections: group.relocations.clone(),
+                    })
+            };
+            let load_region = match &group.load {
+                Some(Load::Region(name)) => Some(name),
+                _ => None,
+            };
+            let (region, load_region) = match (
+                group.region.as_ref().map(find).transpose(),
+                load_region.map(find).transpose(),
+            ) {
+                (Ok(region), Ok(load_region)) => (region, load_region),
+                (region, load_region) => {
+                    errors.extend(region.err().into_iter().chain(load_region.err()));
+                    continue;
+                }
+            };
+
+            match (group.address, region) {
+                (Some(start), _) => address = start,
+                (None, Some(region)) => address = next[region.name.as_str()],
+                (None, None) => (),
+            }
+            let start = address;
+            let load = match (&group.load, load_region) {
+                (Some(Load::Address(load)), _) => *load,
+                (_, Some(region)) => next[region.name.as_str()],
+                _ => start,
+            };
+            let bank = region.and_then(|r| r.bank);
+            let load_bank = match &group.load {
+                Some(_) => load_region.and_then(|r| r.bank),
+                None => bank,
+            };
+            for name in &group.relocations {
+                let sect = place_section(
+                    &mut self.objects,
+                    name,
+                    address,
+                    load + (address - start),
+                    bank,
+                    load_bank,
+                    self.layout.align.get(name).copied().unwrap_or(1),
+                );
+                address = sect.base + sect.size;
+                if address > 0x10000 {
+                    errors.push(Error::AddressSpace {
+                        section: name.clone(),
+                        end: address,
+                    });
+                }
+                placed.push(sect);
+            }
+            if let Some(max_size) = group.max_size {
+                if address - start > max_size {
+                    errors.push(Error::GroupOverflow {
+                        sections: group.relocations.clone(),
+                        size: address - start,
+                        max_size,
+                    });
+                }
+            }
+            let size = address - start;
+            if group.load.is_some() && load + size > 0x10000 {
+                errors.push(Error::AddressSpace {
+                    section: group.relocations.last().cloned().unwrap_or_default(),
+                    end: load + size,
+                });
+            }
+            // the group takes up room in both regions
+            for (region, start) in region
+                .zip(Some(start))
+                .into_iter()
+                .chain(load_region.zip(Some(load)))
+            {
+                errors.extend(fit_region(region, &group.relocations, start, start + size));
+                let next = next.get_mut(region.name.as_str()).unwrap();
+                *next = (*next).max(start + size);
+            }
+            groups.push(PlacedGroup {
+                base: start,
+                load: group.load.as_ref().map(|_| load),
+                size: address - start,
+                max_size: group.max_size,
+                sections: group.relocations.clone(),
+                region: group.region.clone(),
+                bank,
+                overlay: group.overlay,
+            });
+        }
+
+        errors.extend(overlaps(&groups, &placed));
+        errors.extend(self.crossed_pages(&placed));
+        self.assigned = assigned;
+
+        // every section must be placed somewhere, packed ones that didn't fit are
+        // already reported
+        let packed = |name: &String| {
+            self.layout
+                .packs()
+                .any(|pack| pack.sections.iter().any(|s| &s.name == name))
+        };
+        let mut unplaced = Vec::new();
+        for obj in &self.objects {
+            for name in obj.sections.keys() {
+                if !placed.iter().any(|s| &s.name == name)
+                    && !packed(name)
+                    && !unplaced.contains(name)
+                {
+                    unplaced.push(name.clone());
+                }
+            }
+        }
+        unplaced.sort();
+        errors.extend(unplaced.into_iter().map(Error::UnplacedSection));
+
+        if errors.is_empty() {
+            Ok((groups, placed))
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Find every section given `page` in the layout and every block of an object
+    /// that crosses a page.
+    fn crossed_pages(&self, placed: &[PlacedSection]) -> Vec<Error> {
+        let crosses =
+            |start: usize, size: usize| size != 0 && start >> 8 != (start + size - 1) >> 8;
+        let mut errors = placed
+            .iter()
+            .filter(|sect| self.layout.pages.contains(&sect.name) && crosses(sect.base, sect.size))
+            .map(|sect| Error::PageCross {
+                section: sect.name.clone(),
+                start: sect.base,
+                end: sect.base + sect.size,
+            })
+            .collect::<Vec<_>>();
+        for obj in &self.objects {
+            for (name, sect) in &obj.sections {
+                for block in &sect.blocks {
+                    let start = sect.base + block.offset;
+                    if crosses(start, block.size) {
+                        errors.push(Error::BlockPageCross {
+                            label: block.label.clone(),
+                            object: obj.name.clone(),
+                            section: name.clone(),
+                            start,
+                            end: start + block.size,
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    /// Lay out everything from the lowest to the highest address it's loaded at,
+    /// returning the first address, the output and the offset of each section.
+    fn flat_output(&self, output: &[&PlacedSection]) -> (usize, Vec<u8>, Vec<usize>) {
+        // filled regions are output in full
+        let filled = self
+            .layout
+            .regions
+            .iter()
+            .filter(|r| r.memory == Memory::Rom && r.fill.is_some())
+            .collect::<Vec<_>>();
+
+        // get the extent of everything that was placed
+        let start = output
+            .iter()
+            .filter(|s| s.size != 0)
+            .map(|s| s.load)
+            .chain(filled.iter().map(|r| r.start))
+            .min()
+            .unwrap_or(0);
+        let end = output
+            .iter()
+            .map(|s| s.load + s.size)
+            .chain(filled.iter().map(|r| r.end()))
+            .max()
+            .unwrap_or(0)
+            .max(start);
+        let mut code = vec![0; end - start];
+        for region in filled {
+            code[(region.start - start)..(region.end() - start)].fill(region.fill.unwrap());
+        }
+        let offsets = output.iter().map(|s| s.load.max(start) - start).collect();
+        (start, code, offsets)
+    }
+
+    /// The runs of output bytes from each output section, at `offsets` into the
+    /// output, and each filled region.
+    fn segments(
+        &self,
+        start: usize,
+        banks: &[PlacedBank],
+        output: &[&PlacedSection],
+        offsets: &[usize],
+    ) -> Vec<Segment> {
+        let mut segments = output
+            .iter()
+            .zip(offsets)
+            .map(|(sect, &offset)| Segment {
+                address: if banks.is_empty() {
+                    sect.load
+                } else {
+                    sect.load_bank.unwrap_or(0) << 16 | sect.load
+                },
+                offset,
+                size: sect.size,
+            })
+            .collect::<Vec<_>>();
+        let filled = |name: &str| self.region(name).unwrap().fill.is_some();
+        if banks.is_empty() {
+            segments.extend(
+                self.layout
+                    .regions
+                    .iter()
+                    .filter(|r| r.memory == Memory::Rom && filled(&r.name))
+                    .map(|r| Segment {
+                        address: r.start,
+                        offset: r.start - start,
+                        size: r.size,
+                    }),
+            );
+        } else {
+            segments.extend(banks.iter().filter(|b| filled(&b.region)).map(|b| Segment {
+                address: b.number << 16 | b.start,
+                offset: b.offset,
+                size: b.size,
+            }));
+        }
+
+        // join the ones that overlap or touch
+        segments.retain(|seg| seg.size != 0);
+        segments.sort_by_key(|seg| (seg.address, seg.offset));
+        let mut joined: Vec<Segment> = Vec::with_capacity(segments.len());
+        for seg in segments {
+            match joined.last_mut() {
+                Some(last)
+                    if seg.address <= last.address + last.size
+                        && seg.offset.wrapping_sub(seg.address)
+                            == last.offset.wrapping_sub(last.address) =>
+                {
+                    last.size = last.size.max(seg.address + seg.size - last.address);
+                }
+                _ => joined.push(seg),
+            }
+        }
+        joined
+    }
+
+    /// Every bank of ROM in order of its number, following the last.
+    fn rom_banks(&self) -> Vec<PlacedBank> {
+        let mut regions = self
+            .layout
+            .regions
+            .iter()
+            .filter(|r| r.memory == Memory::Rom && r.bank.is_some())
+            .collect::<Vec<_>>();
+        regions.sort_by_key(|r| r.bank);
+        let mut offset = 0;
+        regions
+            .into_iter()
+            .map(|region| {
+                let bank = PlacedBank {
+                    number: region.bank.unwrap(),
+                    region: region.name.clone(),
+                    start: region.start,
+                    size: region.size,
+                    offset,
+                };
+                offset += region.size;
+                bank
+            })
+            .collect()
+    }
+
+    /// The bank of every symbol known to be in one.
+    fn symbol_banks(
+        &self,
+        symbols: &HashMap<String, usize>,
+        provided: &HashMap<String, usize>,
+        placed: &[PlacedSection],
+    ) -> HashMap<String, usize> {
+        // only symbols from symbol tables outside of bank 0 have their bank encoded
+        let mut banks = symbols
+            .iter()
+            .filter(|&(_, &value)| value > 0xFFFF)
+            .map(|(name, &value)| (name.clone(), value >> 16))
+            .collect::<HashMap<_, _>>();
+        for sect in self.objects.iter().flat_map(|obj| obj.sections.values()) {
+            for (name, lab) in &sect.labels {
+                if let (Visibility::Global, Some(bank)) = (lab.vis, sect.bank) {
+                    banks.insert(name.clone(), bank);
+                }
+            }
+        }
+        for name in provided.keys() {
+            let (sect, which) =
+                split_section_symbol(name, |sect| placed.iter().any(|s| s.name == sect)).unwrap();
+            let sect = placed.iter().find(|s| s.name == sect).unwrap();
+            let bank = match which {
+                "load" => sect.load_bank,
+                "size" => None,
+                _ => sect.bank,
+            };
+            if let Some(bank) = bank {
+                banks.insert(name.clone(), bank);
+            }
+        }
+        banks
+    }
+
+    /// Forget the symbols provided for a section by another link when there's a
+    /// section of the same name here, whose own symbols take their place.
+    fn drop_linked_section_symbols(&mut self) {
+        let (objects, defined) = (&self.objects, &self.defined);
+        self.symbols.retain(|name, _| {
+            defined.contains(name)
+                || split_section_symbol(name, |sect| {
+                    objects.iter().any(|obj| obj.sections.contains_key(sect))
+                })
+                .is_none()
+        });
+    }
+
+    /// Every bank that shares addresses with another bank, so it isn't always
+    /// switched in.
+    fn switched_banks(&self) -> HashSet<usize> {
+        let banks = self
+            .layout
+            .regions
+            .iter()
+            .filter(|r| r.bank.is_some())
+            .collect::<Vec<_>>();
+        banks
+            .iter()
+            .filter(|r| {
+                banks.iter().any(|other| {
+                    other.bank != r.bank && other.start < r.end() && r.start < other.end()
+                })
+            })
+            .filter_map(|r| r.bank)
+            .collect()
+    }
+
+    /// Lay out every bank in full, returning the offset of each section in the output.
+    fn banked_output(
+        &self,
+        banks: &[PlacedBank],
+        output: &[&PlacedSection],
+    ) -> Result<(usize, Vec<u8>, Vec<usize>), Vec<Error>> {
+        let mut errors = Vec::new();
+        let mut offsets = Vec::with_capacity(output.len());
+        for sect in output {
+            match banks.iter().find(|b| Some(b.number) == sect.load_bank) {
+                Some(bank) => offsets.push(bank.offset + (sect.load - bank.start)),
+                None if sect.size == 0 => offsets.push(0),
+                None => errors.push(Error::Unbanked(sect.name.clone())),
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut code = vec![0; banks.last().map(|b| b.offset + b.size).unwrap_or(0)];
+        for bank in banks {
+            let fill = self.region(&bank.region).unwrap().fill.unwrap_or(0);
+            code[bank.offset..(bank.offset + bank.size)].fill(fill);
+        }
+        Ok((0, code, offsets))
+    }
+
+    /// Find a region in the layout by name.
+    fn region(&self, name: &str) -> Option<&Region> {
+        self.layout.regions.iter().find(|r| r.name == name)
+    }
+
+    /// Make sure nothing but zeros is placed in RAM unless it's loaded from
+    /// somewhere else, since it won't be output.
+    fn check_ram(&self, groups: &[PlacedGroup]) -> Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+        for group in groups {
+            let region = match group.region.as_ref().and_then(|name| self.region(name)) {
+                Some(region) if region.memory == Memory::Ram && group.load.is_none() => region,
+                _ => continue,
+            };
+            for name in &group.sections {
+                if self.objects.iter().any(|obj| {
+                    obj.sections
+                        .get(name)
+                        .is_some_and(|sect| sect.code[..sect.size].iter().any(|&b| b != 0))
+                }) {
+                    errors.push(Error::RamData {
+                        region: region.name.clone(),
+                        section: name.clone(),
+                    });
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Find the address of the entry, making sure it and every interrupt handler are
+    /// global symbols.
+    fn find_entry(
+        &self,
+        symbols: &HashMap<String, usize>,
+        vectors: Option<&VectorTable>,
+    ) -> Result<Option<(String, usize)>, Vec<Error>> {
+        let mut errors = vectors.map(|v| v.check(symbols)).unwrap_or_default();
+        let entry = match &self.layout.entry {
+            Some(entry) => match symbols.get(entry) {
+                Some(&address) => Some((entry.clone(), address)),
+                None => {
+                    errors.push(Error::NotGlobal {
+                        what: "entry".to_string(),
+                        symbol: entry.clone(),
+                    });
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if errors.is_empty() {
+            Ok(entry)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Evaluate the assertions of every object once everything is placed. An object's
+    /// own labels may be used along with every global symbol, and assertions made in
+    /// parts of sections removed by garbage collection are dropped with them.
+    fn check_assertions(
+        &self,
+        symbols: &HashMap<String, usize>,
+        placed: &[PlacedSection],
+    ) -> Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+        for obj in self.objects.iter().filter(|obj| !obj.assertions.is_empty()) {
+            let labels = obj
+                .sections
+                .values()
+                .flat_map(|sect| {
+                    sect.labels
+                        .iter()
+                        .filter(|(_, lab)| lab.vis != Visibility::Hidden)
+                        .map(move |(name, lab)| (name.clone(), sect.address(lab.offset)))
+                })
+                .collect::<HashMap<_, _>>();
+            for assertion in &obj.assertions {
+                let sect = match obj.sections.get(&assertion.section) {
+                    Some(sect) => sect,
+                    None => continue,
+                };
+                let ctx = Context {
+                    location: sect.base + assertion.offset,
+                    assigned: &labels,
+                    symbols,
+                    placed,
+                    objects: &self.objects,
+                };
+                let file = match assertion.file.as_str() {
+                    "" => obj.name.clone(),
+                    file => file.to_string(),
+                };
+                match parse_expr(&assertion.expression).and_then(|expr| expr.eval(&ctx)) {
+                    Ok(0) => errors.push(Error::Assertion {
+                        file,
+                        line: assertion.line,
+                        message: assertion.message.clone(),
+                    }),
+                    Ok(_) => (),
+                    Err(message) => errors.push(Error::AssertionExpression {
+                        file,
+                        line: assertion.line,
+                        message,
+                    }),
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Keep one definition of each weak label: the global one if there is one,
+    /// otherwise the first weak one, which becomes global. References from the
+    /// objects of the others go to the one kept, so weak children are removed and
+    /// weak parents are hidden, which only keeps their children reachable.
+    fn settle_weak(&mut self) {
+        let mut defined = self
+            .objects
+            .iter()
+            .flat_map(|obj| obj.sections.values())
+            .flat_map(|sect| &sect.labels)
+            .filter(|(_, lab)| lab.vis == Visibility::Global)
+            .map(|(name, _)| name.clone())
+            .chain(self.symbols.keys().cloned())
+            .chain(self.layout.statements.iter().filter_map(|stmt| match stmt {
+                Statement::Assign { name, .. } => name.clone(),
+                _ => None,
+            }))
+            .collect::<HashSet<_>>();
+        for obj in &mut self.objects {
+            for sect in obj.sections.values_mut() {
+                sect.labels.retain(|name, lab| {
+                    if lab.vis != Visibility::Weak {
+                        true
+                    } else if defined.insert(name.clone()) {
+                        lab.vis = Visibility::Global;
+                        true
+                    } else {
+                        lab.vis = Visibility::Hidden;
+                        !name.contains('.')
+                    }
+                });
+            }
+        }
+    }
+
+    /// Rename the references to wrapped symbols that aren't found in their own
+    /// object.
+    fn wrap_references(&mut self) {
+        if self.wrapped.is_empty() {
+            return;
+        }
+        let mut renames = HashMap::with_capacity(self.wrapped.len() * 2);
+        for symbol in &self.wrapped {
+            renames.insert(symbol.clone(), format!("__wrap_{}", symbol));
+            renames.insert(format!("__real_{}", symbol), symbol.clone());
+        }
+        for obj in &mut self.objects {
+            let mut renamed = Vec::new();
+            for (sect_name, sect) in &obj.sections {
+                for (idx, rf) in sect.references.iter().enumerate() {
+                    match renames.get(&rf.referred) {
+                        Some(to) if find_label(obj, sect_name, rf).is_none() => {
+                            renamed.push((sect_name.clone(), idx, to.clone()))
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            for (sect_name, idx, to) in renamed {
+                obj.sections.get_mut(&sect_name).unwrap().references[idx].referred = to;
+            }
+        }
+    }
+
+    /// Collect the addresses of all global labels and symbol table entries.
+    fn global_symbols(&self) -> Result<HashMap<String, usize>, Vec<Error>> {
+        let mut errors = Vec::new();
+        let mut symbols = self.symbols.clone();
+        for (name, &value) in &self.assigned {
+            if symbols.insert(name.clone(), value).is_some() {
+                errors.push(Error::DuplicateSymbol(name.clone()));
+            }
+        }
+
+        for obj in &self.objects {
+            for sect in obj.sections.values() {
+                for (name, lab) in &sect.labels {
+                    if lab.vis == Visibility::Global
+                        && symbols
+                            .insert(name.clone(), sect.address(lab.offset))
+                            .is_some()
+                    {
+                        errors.push(Error::DuplicateSymbol(name.clone()));
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(symbols)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Fill in every reference with the address of the label it refers to, making
+    /// sure the label's bank is switched in.
+    fn resolve(
+        &mut self,
+        symbols: &HashMap<String, usize>,
+        banks: &HashMap<String, usize>,
+    ) -> Result<(), Vec<Error>> {
+        let mut errors = Vec::new();
+        let mut patches = Vec::new();
+        let switched = self.switched_banks();
+
+        for (idx, obj) in self.objects.iter().enumerate() {
+            for (sect_name, sect) in &obj.sections {
+                for rf in &sect.references {
+                    let found = match find_label(obj, sect_name, rf) {
+                        Some((name, lab)) => {
+                            let target = &obj.sections[name];
+                            Some((target.base + lab.offset, target.bank))
+                        }
+                        None => symbols
+                            .get(&rf.referred)
+                            .map(|&value| (value & 0xFFFF, banks.get(&rf.referred).copied())),
+                    };
+                    let target = match found {
+                        Some((_, Some(bank)))
+                            if sect.bank != Some(bank)
+                                && switched.contains(&bank)
+                                && !self.layout.far.contains(&rf.referred) =>
+                        {
+                            errors.push(Error::CrossBank {
+                                symbol: rf.referred.clone(),
+                                object: obj.name.clone(),
+                                section: sect_name.clone(),
+                                offset: rf.offset,
+                                bank,
+                            });
+                            continue;
+                        }
+                        Some((target, _)) => target,
+                        None => {
+                            errors.push(Error::Undefined {
+                                symbol: rf.referred.clone(),
+                                object: obj.name.clone(),
+                                section: sect_name.clone(),
+                                offset: rf.offset,
+                            });
+                            continue;
+                        }
+                    };
+
+                    let bytes = if rf.branch {
+                        // relative to the address after the operand
+                        let distance = target as isize - (sect.base + rf.offset + 1) as isize;
+                        if !(-128..=127).contains(&distance) {
+                            errors.push(Error::BranchRange {
+                                symbol: rf.referred.clone(),
+                                object: obj.name.clone(),
+                                section: sect_name.clone(),
+                                offset: rf.offset,
+                                distance,
+                            });
+                            continue;
+                        }
+                        vec![distance as u8]
+                    } else {
+                        match rf.which_byte {
+                            ByteSelect::Both => vec![target as u8, (target >> 8) as u8],
+                            ByteSelect::High => vec![(target >> 8) as u8],
+                            ByteSelect::Low => vec![target as u8],
+                        }
+                    };
+                    patches.push(Patch {
+                        object: idx,
+                        section: sect_name.clone(),
+                        offset: rf.offset,
+                        bytes,
+                    });
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for patch in patches {
+            let sect = self.objects[patch.object]
+                .sections
+                .get_mut(&patch.section)
+                .unwrap();
+            sect.code[patch.offset..(patch.offset + patch.bytes.len())]
+                .copy_from_slice(&patch.bytes);
+        }
         Ok(())
     }
 }
+
+/// Place every object's part of a section one after another from the first multiple
+/// of `align` at or after `address`, leaving gaps between parts that are aligned.
+fn place_section(
+    objects: &mut [Object],
+    name: &str,
+    address: usize,
+    load: usize,
+    bank: Option<usize>,
+    load_bank: Option<usize>,
+    align: usize,
+) -> PlacedSection {
+    let align = section_align(objects, name, align);
+    let base = address.div_ceil(align) * align;
+    let mut sect = PlacedSection {
+        name: name.to_string(),
+        base,
+        load: load + (base - address),
+        size: 0,
+        bank,
+        load_bank,
+        objects: Vec::with_capacity(objects.len()),
+    };
+    // each object contributes its part in the order they were given
+    for obj in objects {
+        if let Some(part) = obj.sections.get_mut(name) {
+            let offset = sect.size.div_ceil(part.align) * part.align;
+            part.base = sect.base + offset;
+            part.load = sect.load + offset;
+            part.bank = bank;
+            sect.objects
+                .push((obj.name.clone(), part.base..(part.base + part.size)));
+            sect.size = offset + part.size;
+        }
+    }
+    sect
+}
+
+/// The multiple a section starts at so that it's a multiple of `align` and every
+/// object's part of it is aligned as the object asks.
+fn section_align(objects: &[Object], name: &str, align: usize) -> usize {
+    objects
+        .iter()
+        .filter_map(|obj| obj.sections.get(name))
+        .fold(align, |align, part| num::integer::lcm(align, part.align))
+}
+
+/// The size of a section from every object combined, including the gaps left to
+/// align each part.
+pub fn section_size(objects: &[Object], name: &str) -> usize {
+    objects
+        .iter()
+        .filter_map(|obj| obj.sections.get(name))
+        .fold(0, |size, part| {
+            size.div_ceil(part.align) * part.align + part.size
+        })
+}
+
+/// Place each packed section as a group of its own in the region chosen for it,
+/// largest first.
+fn pack_sections(
+    pack: &PackGroup,
+    regions: &[Region],
+    align: &HashMap<String, usize>,
+    objects: &mut [Object],
+    next: &mut HashMap<&str, usize>,
+    groups: &mut Vec<PlacedGroup>,
+    placed: &mut Vec<PlacedSection>,
+) -> Vec<Error> {
+    let mut errors = Vec::new();
+    let mut todo = pack
+        .sections
+        .iter()
+        .map(|packed| (packed, section_size(objects, &packed.name)))
+        .collect::<Vec<_>>();
+    todo.sort_by_key(|&(_, size)| std::cmp::Reverse(size));
+
+    // sections that must be with another wait until it's placed
+    while !todo.is_empty() {
+        let ready = todo.iter().position(|(packed, _)| {
+            packed
+                .with
+                .as_ref()
+                .is_none_or(|with| placed.iter().any(|s| &s.name == with))
+        });
+        let (packed, size) = match ready {
+            Some(idx) => todo.remove(idx),
+            None => {
+                errors.extend(todo.drain(..).map(|(packed, _)| Error::PackWith {
+                    section: packed.name.clone(),
+                    with: packed.with.clone().unwrap(),
+                }));
+                break;
+            }
+        };
+
+        // the regions it may go in, with where it would start in each
+        let with = packed.with.as_ref().map(|with| {
+            let group = groups.iter().find(|g| g.sections.contains(with)).unwrap();
+            (group.bank, group.region.clone())
+        });
+        let script_align = align.get(&packed.name).copied().unwrap_or(1);
+        let align = section_align(objects, &packed.name, script_align);
+        let mut candidates = pack
+            .regions
+            .iter()
+            .map(|name| regions.iter().find(|r| &r.name == name).unwrap())
+            .filter(|region| match &with {
+                Some((Some(bank), _)) => region.bank == Some(*bank),
+                Some((None, with)) => with.as_ref() == Some(&region.name),
+                None => true,
+            })
+            .map(|region| {
+                let start = next[region.name.as_str()].div_ceil(align) * align;
+                (region, start)
+            })
+            .filter(|&(region, start)| start + size <= region.end());
+        let chosen = match pack.fit {
+            Fit::First => candidates.next(),
+            Fit::Best => candidates.min_by_key(|&(region, start)| region.end() - (start + size)),
+        };
+        let (region, start) = match chosen {
+            Some(chosen) => chosen,
+            None => {
+                errors.push(Error::PackOverflow {
+                    section: packed.name.clone(),
+                    size,
+                });
+                continue;
+            }
+        };
+
+        let sect = place_section(
+            objects,
+            &packed.name,
+            start,
+            start,
+            region.bank,
+            region.bank,
+            align,
+        );
+        *next.get_mut(region.name.as_str()).unwrap() = start + size;
+        groups.push(PlacedGroup {
+            base: start,
+            load: None,
+            size,
+            max_size: None,
+            sections: vec![packed.name.clone()],
+            region: Some(region.name.clone()),
+            bank: region.bank,
+            overlay: false,
+        });
+        placed.push(sect);
+    }
+    errors
+}
+
+/// Check that a group placed from `start` to `end` is inside of a region.
+fn fit_region(region: &Region, sections: &[String], start: usize, end: usize) -> Option<Error> {
+    if !(region.start..region.end()).contains(&start) {
+        Some(Error::RegionStart {
+            region: region.name.clone(),
+            sections: sections.to_vec(),
+            address: start,
+        })
+    } else if end > region.end() {
+        Some(Error::RegionOverflow {
+            region: region.name.clone(),
+            sections: sections.to_vec(),
+            over: end - region.end(),
+        })
+    } else {
+        None
+    }
+}
+
+/// The suffixes of the symbols provided for every section.
+const SECTION_SYMBOLS: [&str; 4] = ["start", "end", "size", "load"];
+
+/// The value of a symbol the linker provides for a placed section, `__<sect>_start`,
+/// `__<sect>_end`, `__<sect>_size` or `__<sect>_load`.
+///
+/// The load address is where the section's bytes are in the output, which is its
+/// start unless the layout gives it a separate load address. The end is one past
+/// the last byte, so it wraps around to 0 for a section that ends the address
+/// space, like the vectors. Addresses in a bank have the bank in bits 16-23.
+pub fn section_symbol(name: &str, placed: &[PlacedSection]) -> Option<usize> {
+    let (sect, which) = split_section_symbol(name, |sect| placed.iter().any(|s| s.name == sect))?;
+    let sect = placed.iter().find(|s| s.name == sect)?;
+    let bank = sect.bank.unwrap_or(0) << 16;
+    match which {
+        "start" => Some(bank | sect.base),
+        "load" => Some(sect.load_bank.unwrap_or(0) << 16 | sect.load),
+        "end" => Some(bank | ((sect.base + sect.size) & 0xFFFF)),
+        "size" => Some(sect.size),
+        _ => None,
+    }
+}
+
+/// Split the name of a provided symbol into its section and suffix, if `exists`
+/// says there's such a section. The section of `__sect_start` is `sect`, or
+/// `__sect` if there's no `sect` since names starting with `__` aren't prefixed
+/// again.
+pub fn split_section_symbol(name: &str, exists: impl Fn(&str) -> bool) -> Option<(&str, &str)> {
+    let (prefixed, which) = name.rsplit_once('_')?;
+    let sect = prefixed.strip_prefix("__")?;
+    if !SECTION_SYMBOLS.contains(&which) {
+        None
+    } else if exists(sect) {
+        Some((sect, which))
+    } else if exists(prefixed) {
+        Some((prefixed, which))
+    } else {
+        None
+    }
+}
+
+/// The name of a symbol provided for a section.
+fn section_symbol_name(sect: &str, which: &str) -> String {
+    if sect.starts_with("__") {
+        format!("{}_{}", sect, which)
+    } else {
+        format!("__{}_{}", sect, which)
+    }
+}
+
+/// Every symbol provided for the placed sections.
+fn provided_symbols(placed: &[PlacedSection]) -> impl Iterator<Item = (String, usize)> + '_ {
+    placed.iter().flat_map(move |sect| {
+        SECTION_SYMBOLS.iter().map(move |which| {
+            let name = section_symbol_name(&sect.name, which);
+            let value = section_symbol(&name, placed).unwrap();
+            (name, value)
+        })
+    })
+}
+
+/// Find every pair of sections from different groups that share addresses, either
+/// where they run or where they're loaded, unless one of the groups is an overlay.
+fn overlaps(groups: &[PlacedGroup], placed: &[PlacedSection]) -> Vec<Error> {
+    let mut errors = Vec::new();
+    // each section with the group it's in
+    let sections = groups
+        .iter()
+        .enumerate()
+        .filter(|(_, group)| !group.overlay)
+        .flat_map(|(idx, group)| group.sections.iter().map(move |name| (idx, name)))
+        .filter_map(|(idx, name)| placed.iter().find(|s| &s.name == name).map(|s| (idx, s)))
+        .filter(|(_, sect)| sect.size != 0)
+        .collect::<Vec<_>>();
+
+    for (i, (first_group, first)) in sections.iter().enumerate() {
+        for (second_group, second) in &sections[(i + 1)..] {
+            if first_group == second_group {
+                continue;
+            }
+            // where they run, and where they are in the output if that's different,
+            // unless they're in different banks
+            let mut ranges = Vec::with_capacity(2);
+            if same_bank(first.bank, second.bank) {
+                ranges.push((first.base, second.base));
+            }
+            if (first.load != first.base || second.load != second.base)
+                && same_bank(first.load_bank, second.load_bank)
+            {
+                ranges.push((first.load, second.load));
+            }
+            for (first_start, second_start) in ranges {
+                let start = first_start.max(second_start);
+                let end = (first_start + first.size).min(second_start + second.size);
+                if start < end {
+                    errors.push(Error::Overlap {
+                        first: first.name.clone(),
+                        second: second.name.clone(),
+                        start,
+                        end,
+                    });
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+/// Anything outside of a bank shares addresses with every bank.
+fn same_bank(first: Option<usize>, second: Option<usize>) -> bool {
+    first.is_none() || second.is_none() || first == second
+}
+
+/// The object holding the sections the linker makes, created if needed.
+pub fn linker_object(objects: &mut Vec<Object>) -> &mut Object {
+    if !objects.iter().any(|obj| obj.name == LINKER_OBJECT) {
+        objects.push(Object {
+            name: LINKER_OBJECT.to_string(),
+            sections: HashMap::new(),
+            assertions: Vec::new(),
+        });
+    }
+    objects
+        .iter_mut()
+        .find(|obj| obj.name == LINKER_OBJECT)
+        .unwrap()
+}
+
+/// Find the address of a label referred to from inside an object.
+///
+/// Hidden labels may only be referred to from under the same parent and object
+/// labels from anywhere in the object. Global labels are found here too when they
+/// are in the same object. A hidden parent label, left by a weak label that gave
+/// way, isn't found itself but still lets its children be found.
+pub fn lookup(obj: &Object, sect_name: &str, rf: &Reference) -> Option<usize> {
+    find_label(obj, sect_name, rf).map(|(name, lab)| obj.sections[name].base + lab.offset)
+}
+
+/// Find the section and label referred to from inside an object, following the same
+/// rules as [`lookup`].
+pub fn find_label<'o>(
+    obj: &'o Object,
+    sect_name: &str,
+    rf: &Reference,
+) -> Option<(&'o String, &'o Label)> {
+    for (name, sect) in &obj.sections {
+        let lab = match sect.labels.get(&rf.referred) {
+            Some(lab) => lab,
+            None => continue,
+        };
+        if lab.vis != Visibility::Hidden {
+            return Some((name, lab));
+        }
+        if name != sect_name || !rf.referred.contains('.') {
+            continue;
+        }
+
+        // the reference must be between the parent and the next parent
+        let parent = rf.referred.split('.').next().unwrap();
+        let start = match sect.labels.get(parent) {
+            Some(lab) => lab.offset,
+            None => continue,
+        };
+        let next = sect
+            .labels
+            .iter()
+            .filter(|(name, lab)| !name.contains('.') && lab.offset > start)
+            .map(|(_, lab)| lab.offset)
+            .min()
+            .unwrap_or(usize::MAX);
+        if start <= rf.offset && rf.offset < next {
+            return Some((name, lab));
+        }
+    }
+
+    None
+}
@@ -1,67 +1,386 @@
-#![feature(process_exitcode_placeholder)]
-
-mod formats;
-mod linker;
-mod object;
-mod script;
-
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
 use std::process::ExitCode;
 
-use linker::Linker;
+use s502_ln::archive::extract;
+use s502_ln::cart::{crt, ines};
+use s502_ln::formats::Visibility;
+use s502_ln::hex::{write_intel_hex, write_srec};
+use s502_ln::linker::LINKER_OBJECT;
+use s502_ln::map::write_map;
+use s502_ln::object::{read_objects, write_object, write_symtab};
+use s502_ln::partial::merge;
+use s502_ln::prg::prg;
+use s502_ln::{Image, Linker};
 
 fn main() -> ExitCode {
-    let arg_matches = clap::App::new("s502-as 0.1")
+    // with -r every positional is an object, so the script isn't taken from them
+    let args = std::env::args_os().collect::<Vec<_>>();
+    let relocatable = app(false)
+        .get_matches_from_safe(&args)
+        .is_ok_and(|matches| matches.is_present("relocatable"));
+    let arg_matches = app(relocatable).get_matches_from(&args);
+
+    if relocatable {
+        return partial_link(
+            arg_matches.values_of_lossy("objects").unwrap(),
+            arg_matches.value_of("output file").unwrap(),
+        );
+    }
+
+    let script = match arg_matches.value_of("linker script") {
+        Some(script) => script,
+        None => return ExitCode::FAILURE,
+    };
+    let mut linker =
+        match Linker::from_files(script, arg_matches.values_of_lossy("objects").unwrap()) {
+            Ok(linker) => linker,
+            Err(e) => {
+                println!("{}", e);
+                return ExitCode::FAILURE;
+            }
+        };
+    for definition in arg_matches.values_of("define symbol").into_iter().flatten() {
+        if let Err(e) = linker.define(definition) {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    for symbol in arg_matches.values_of("wrap symbol").into_iter().flatten() {
+        linker.wrap(symbol.to_string());
+    }
+    if let Some(settings) = arg_matches.value_of("ines settings") {
+        if let Err(e) = linker.ines(settings) {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(settings) = arg_matches.value_of("crt settings") {
+        if let Err(e) = linker.crt(settings) {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(entry) = arg_matches.value_of("entry") {
+        linker.entry(entry.to_string());
+    }
+    if arg_matches.is_present("nmi") || arg_matches.is_present("irq") {
+        linker.vectors(
+            arg_matches.value_of("nmi").map(str::to_string),
+            arg_matches.value_of("irq").map(str::to_string),
+        );
+    }
+    if arg_matches.is_present("gc sections") {
+        linker.gc_sections(
+            arg_matches
+                .values_of_lossy("keep symbol")
+                .unwrap_or_default(),
+        );
+    }
+    let image = match linker.link() {
+        Ok(image) => image,
+        Err(errors) => {
+            for e in errors {
+                println!("{}", e);
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+    if arg_matches.is_present("gc sections") {
+        for removed in &image.removed {
+            println!(
+                "removed section {} from {} ({} bytes)",
+                removed.section, removed.object, removed.size
+            );
+        }
+        println!(
+            "removed {} bytes",
+            image.removed.iter().map(|r| r.size).sum::<usize>()
+        );
+    }
+    if arg_matches.is_present("print regions") {
+        for region in &image.regions {
+            let used = image.used(region);
+            println!(
+                "{:24} {:#06x} of {:#06x} bytes used ({}%)",
+                region.name,
+                used,
+                region.size,
+                (used * 100).checked_div(region.size).unwrap_or(100)
+            );
+        }
+    }
+
+    // write the output
+    let format = arg_matches.value_of("format").unwrap();
+    let out_file = match arg_matches.value_of("output file") {
+        Some(out) => out.to_string(),
+        None => Path::new(script)
+            .with_extension(match format {
+                "ihex" => "hex",
+                "srec" => "srec",
+                "prg" => "prg",
+                "ines" => "nes",
+                "crt" => "crt",
+                _ => "bin",
+            })
+            .to_string_lossy()
+            .into_owned(),
+    };
+    // binary formats are made first so that an error doesn't leave a file behind
+    let binary = match format {
+        "ihex" | "srec" => Ok(None),
+        "prg" => prg(&image, arg_matches.is_present("basic stub")).map(Some),
+        "ines" => ines(&image).map(Some),
+        "crt" => crt(&image).map(Some),
+        _ => Ok(Some(image.code.clone())),
+    };
+    let binary = match binary {
+        Ok(binary) => binary,
+        Err(e) => {
+            println!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    if File::create(&out_file)
+        .and_then(|mut f| match (&binary, format) {
+            (Some(bytes), _) => f.write_all(bytes),
+            (None, "ihex") => write_intel_hex(&image, BufWriter::new(f)),
+            (None, _) => write_srec(&image, BufWriter::new(f)),
+        })
+        .is_err()
+    {
+        eprintln!("error writing output file {}", out_file);
+        return ExitCode::FAILURE;
+    }
+
+    // write the symbol tables
+    if let Some(combined) = arg_matches.value_of("output combined symbol table") {
+        if File::create(combined)
+            .and_then(|f| write_symtab(&image.exported_symbols(), f))
+            .is_err()
+        {
+            eprintln!("error writing symbol table {}", combined);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(map) = arg_matches.value_of("map file") {
+        if File::create(map)
+            .and_then(|f| write_map(&image, BufWriter::new(f)))
+            .is_err()
+        {
+            eprintln!("error writing map file {}", map);
+            return ExitCode::FAILURE;
+        }
+    }
+    if arg_matches.is_present("output symbol tables") && !write_object_symtabs(&image) {
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// The command line arguments, where the linker script is left out with -r.
+fn app(relocatable: bool) -> clap::App<'static, 'static> {
+    let app = clap::App::new("s502-ln 0.1")
         .arg(
             clap::Arg::with_name("output symbol tables")
                 .short("s")
                 .long("symbols")
-                .help("Output a symbol table for each source file"),
+                .help("Output a symbol table for each object file"),
         )
         .arg(
             clap::Arg::with_name("output combined symbol table")
                 .short("c")
                 .long("combined-symbols")
                 .takes_value(true)
-                .help("Output a single symbol table of all source files combined"),
+                .help("Output a single symbol table of all object files combined"),
         )
         .arg(
-            clap::Arg::with_name("output file")
-                .short("o")
-                .long("output")
+            clap::Arg::with_name("map file")
+                .short("m")
+                .long("map")
                 .takes_value(true)
-                .help("Name for output file (default <source>.65o)"),
+                .help("Output a map of where each group, section and symbol was placed"),
         )
         .arg(
-            clap::Arg::with_name("linker script")
-                .required(true)
-                .help("Linker script to pass to the linker (ignored if only assembling)"),
+            clap::Arg::with_name("print regions")
+                .long("regions")
+                .help("Print how full each region is"),
+        )
+        .arg(
+            clap::Arg::with_name("relocatable")
+                .short("r")
+                .long("relocatable")
+                .requires("output file")
+                .help("Merge the objects into one object to link later, without a linker script"),
+        )
+        .arg(
+            clap::Arg::with_name("gc sections")
+                .long("gc-sections")
+                .help("Remove sections that can't be reached from the entry or kept symbols"),
+        )
+        .arg(
+            clap::Arg::with_name("entry")
+                .short("e")
+                .long("entry")
+                .takes_value(true)
+                .help("Symbol the program starts at, the reset handler if there are vectors"),
         )
         .arg(
-            clap::Arg::with_name("objects")
+            clap::Arg::with_name("nmi")
+                .long("nmi")
+                .takes_value(true)
+                .help("Generate the vector table with this NMI handler"),
+        )
+        .arg(
+            clap::Arg::with_name("irq")
+                .long("irq")
+                .takes_value(true)
+                .help("Generate the vector table with this IRQ handler"),
+        )
+        .arg(
+            clap::Arg::with_name("keep symbol")
+                .short("k")
+                .long("keep")
+                .takes_value(true)
                 .multiple(true)
-                .required(true)
-                .help("The object file(*.65o) and symbol table files (*.65s)"),
+                .number_of_values(1)
+                .help("Keep the section defining a symbol with --gc-sections"),
+        )
+        .arg(
+            clap::Arg::with_name("define symbol")
+                .long("defsym")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Define a symbol as NAME=VALUE"),
+        )
+        .arg(
+            clap::Arg::with_name("wrap symbol")
+                .long("wrap")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Send references to a symbol to __wrap_<symbol>, which may call __real_<symbol>"),
+        )
+        .arg(
+            clap::Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["bin", "ihex", "srec", "prg", "ines", "crt"])
+                .default_value("bin")
+                .help("Format of the output file, a raw binary, Intel HEX, S-records, a Commodore program, an iNES file or a C64 cartridge"),
+        )
+        .arg(
+            clap::Arg::with_name("ines settings")
+                .long("ines")
+                .takes_value(true)
+                .help("Settings for the iNES header, like \"mapper=1 mirroring=vertical battery\""),
+        )
+        .arg(
+            clap::Arg::with_name("crt settings")
+                .long("crt")
+                .takes_value(true)
+                .help("Settings for the C64 cartridge header, like \"type=0 name=GAME\""),
+        )
+        .arg(
+            clap::Arg::with_name("basic stub")
+                .long("basic")
+                .help("Start a Commodore program with a BASIC line at $0801 that calls the entry"),
+        )
+        .arg(
+            clap::Arg::with_name("output file")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Name for output file (default <script> with the format's extension)"),
+        );
+    let app = if relocatable {
+        app
+    } else {
+        app.arg(
+            clap::Arg::with_name("linker script")
+                .required_unless("relocatable")
+                .help("Linker script describing where to place each section"),
         )
-        .get_matches();
-
-    let script = match arg_matches.value_of("linker script") {
-        Some(script) => script,
-        None => return ExitCode::FAILURE,
     };
+    let objects = clap::Arg::with_name("objects")
+        .multiple(true)
+        .help("The object (*.65o), symbol table (*.65s) and archive (*.65r) files");
+    app.arg(if relocatable {
+        objects.required(true)
+    } else {
+        objects.required_unless("relocatable")
+    })
+}
 
-    match match Linker::new(script, arg_matches.values_of_lossy("objects").unwrap()) {
-        Ok(linker) => linker,
+/// Merge objects into one object file.
+fn partial_link(files: Vec<String>, out_file: &str) -> ExitCode {
+    let objects = match read_objects(files) {
+        Ok((_, symbols, _)) if !symbols.is_empty() => {
+            println!("symbol tables can't be used in a partial link");
+            return ExitCode::FAILURE;
+        }
+        Ok((mut objects, symbols, archives)) => {
+            if let Err(e) = extract(&mut objects, &symbols, &archives, &[]) {
+                println!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            objects
+        }
         Err(e) => {
             println!("{}", e);
             return ExitCode::FAILURE;
         }
+    };
+    let merged = match merge(out_file.to_string(), &objects) {
+        Ok(merged) => merged,
+        Err(errors) => {
+            for e in errors {
+                println!("{}", e);
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+    if File::create(out_file)
+        .and_then(|f| write_object(&merged, f))
+        .is_err()
+    {
+        eprintln!("error writing output file {}", out_file);
+        return ExitCode::FAILURE;
     }
-    .link()
+    ExitCode::SUCCESS
+}
+
+/// Write the global labels of each object to a symbol table next to it. Members of
+/// archives, named like `archive.65r(member.65o)`, don't have a file of their own.
+fn write_object_symtabs(image: &Image) -> bool {
+    for obj in image
+        .objects
+        .iter()
+        .filter(|obj| obj.name != LINKER_OBJECT && !obj.name.ends_with(')'))
     {
-        Ok(_) => ExitCode::SUCCESS,
-        Err(e) => {
-            println!("{}", e);
-            ExitCode::FAILURE
+        let mut symbols = HashMap::with_capacity(32);
+        for sect in obj.sections.values() {
+            for (name, lab) in &sect.labels {
+                if lab.vis == Visibility::Global {
+                    symbols.insert(name.clone(), sect.address(lab.offset));
+                }
+            }
+        }
+
+        let name = Path::new(&obj.name).with_extension("65s");
+        if File::create(&name)
+            .and_then(|f| write_symtab(&symbols, f))
+            .is_err()
+        {
+            eprintln!("error writing symbol table {}", name.to_string_lossy());
+            return false;
         }
     }
+    true
 }
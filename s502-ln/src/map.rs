@@ -0,0 +1,210 @@
+//! Writes a map of where everything in an image ended up.
+//!
+//! The map lists each group from the layout with how much room it has left, each
+//! section with the part every object contributed to it, and every symbol sorted by
+//! address and again by name, after the entry if there is one. All addresses are in hex and every end is exclusive,
+//! so an empty section starts and ends at the same address. Symbols in a bank other
+//! than 0 are shown as `bank:address`.
+
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use super::formats::{Memory, Visibility};
+use super::linker::Image;
+
+/// A symbol as it appears in the map.
+struct MapSymbol<'a> {
+    name: &'a str,
+    address: usize,
+    vis: &'static str,
+    /// The object the symbol was defined in, empty if it wasn't from an object.
+    object: &'a str,
+}
+
+/// Write the map of an image.
+pub fn write_map<W: Write>(image: &Image, mut out: W) -> io::Result<()> {
+    if let Some((name, entry)) = &image.entry {
+        writeln!(out, "Entry {} at {}", name, address(*entry))?;
+        writeln!(out)?;
+    }
+    if !image.regions.is_empty() {
+        writeln!(out, "Regions")?;
+        writeln!(
+            out,
+            "  {:24} {:6} {:6} {:6} {:6} {:6} attributes",
+            "name", "start", "end", "size", "used", "free"
+        )?;
+    }
+    for region in &image.regions {
+        let used = image.used(region);
+        let mut attributes = vec![match region.memory {
+            Memory::Rom => "rom".to_string(),
+            Memory::Ram => "ram".to_string(),
+        }];
+        if region.zero_page {
+            attributes.push("zp".to_string());
+        }
+        if let Some(fill) = region.fill {
+            attributes.push(format!("fill={:#04x}", fill));
+        }
+        if let Some(bank) = region.bank {
+            attributes.push(format!("bank={}", bank));
+        }
+        writeln!(
+            out,
+            "  {:24} {:04x}   {:04x}   {:04x}   {:04x}   {:04x}   {}",
+            region.name,
+            region.start,
+            region.end(),
+            region.size,
+            used,
+            region.size.saturating_sub(used),
+            attributes.join(" ")
+        )?;
+    }
+    if !image.regions.is_empty() {
+        writeln!(out)?;
+    }
+
+    writeln!(out, "Groups")?;
+    writeln!(
+        out,
+        "  {:6} {:6} {:6} {:6} {:6} {:24} sections",
+        "start", "end", "size", "max", "free", "region"
+    )?;
+    for group in &image.groups {
+        let (max, free) = match group.max_size {
+            Some(max) => (
+                format!("{:04x}", max),
+                format!("{:04x}", max.saturating_sub(group.size)),
+            ),
+            None => (String::new(), String::new()),
+        };
+        writeln!(
+            out,
+            "  {:04x}   {:04x}   {:04x}   {:6} {:6} {:24} {}",
+            group.base,
+            group.base + group.size,
+            group.size,
+            max,
+            free,
+            group.region.as_deref().unwrap_or(""),
+            group.sections.join(" ")
+        )?;
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Sections")?;
+    writeln!(
+        out,
+        "  {:24} {:6} {:6} {:6} load",
+        "name", "start", "end", "size"
+    )?;
+    for sect in &image.sections {
+        // the load address is only shown if it's different
+        let load = if sect.load != sect.base {
+            format!("{:04x}", sect.load)
+        } else {
+            String::new()
+        };
+        let line = format!(
+            "  {:24} {:04x}   {:04x}   {:04x}   {}",
+            sect.name,
+            sect.base,
+            sect.base + sect.size,
+            sect.size,
+            load
+        );
+        writeln!(out, "{}", line.trim_end())?;
+        for (obj, range) in &sect.objects {
+            writeln!(
+                out,
+                "    {:22} {:04x}   {:04x}   {:04x}",
+                obj,
+                range.start,
+                range.end,
+                range.len()
+            )?;
+        }
+    }
+
+    // every label of every object, then everything else
+    let mut symbols = Vec::with_capacity(image.symbols.len() * 2);
+    let mut defined = HashSet::with_capacity(image.symbols.len());
+    for obj in &image.objects {
+        for sect in obj.sections.values() {
+            for (name, lab) in &sect.labels {
+                if lab.vis == Visibility::Global {
+                    defined.insert(name.as_str());
+                }
+                symbols.push(MapSymbol {
+                    name,
+                    address: sect.address(lab.offset),
+                    vis: match lab.vis {
+                        Visibility::Hidden => "hidden",
+                        Visibility::Object => "object",
+                        Visibility::Global => "global",
+                        Visibility::Weak => "weak",
+                    },
+                    object: &obj.name,
+                });
+            }
+        }
+    }
+    for (name, &address) in &image.symbols {
+        if !defined.contains(name.as_str()) {
+            symbols.push(MapSymbol {
+                name,
+                address,
+                vis: if image.assigned.contains_key(name) {
+                    "script"
+                } else if image.provided.contains_key(name) {
+                    "linker"
+                } else {
+                    "symtab"
+                },
+                object: "",
+            });
+        }
+    }
+
+    symbols.sort_by(|a, b| (a.address, a.name, a.object).cmp(&(b.address, b.name, b.object)));
+    writeln!(out)?;
+    writeln!(out, "Symbols by address")?;
+    write_symbols(&symbols, &mut out)?;
+
+    symbols.sort_by(|a, b| (a.name, a.object).cmp(&(b.name, b.object)));
+    writeln!(out)?;
+    writeln!(out, "Symbols by name")?;
+    write_symbols(&symbols, &mut out)?;
+
+    out.flush()
+}
+
+/// An address of a symbol, written `bank:address` if it's in a bank.
+fn address(address: usize) -> String {
+    match address >> 16 {
+        0 => format!("{:04x}", address),
+        bank => format!("{:02x}:{:04x}", bank, address & 0xFFFF),
+    }
+}
+
+fn write_symbols<W: Write>(symbols: &[MapSymbol], out: &mut W) -> io::Result<()> {
+    let width = symbols
+        .iter()
+        .map(|sym| address(sym.address).len())
+        .max()
+        .unwrap_or(4);
+    for sym in symbols {
+        let line = format!(
+            "  {:width$}  {:6}  {:32} {}",
+            address(sym.address),
+            sym.vis,
+            sym.name,
+            sym.object,
+            width = width
+        );
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
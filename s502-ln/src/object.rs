@@ -1,149 +1,117 @@
 use std::collections::HashMap;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{self, BufReader, Read};
+use std::io::{self, BufReader, BufWriter, Read, Write};
 use std::path::Path;
 
+use super::archive::{read_archive, Archive};
+use super::error::Error;
 use super::formats::*;
 
-/// Reads all object files, preserving the order in which everything is read.
-///
-/// This also accumulates the size of each section for use in calculating the
-/// base addresses of each section.
-pub fn read_objects(
-    files: Vec<String>,
-) -> Result<(HashMap<String, Section>, HashMap<String, usize>), String> {
-    // TODO hashmap string -> usize section names to size
-    let mut all_sections = HashMap::<String, Section>::with_capacity(5);
+/// The objects, symbols and archives read from the input files.
+pub type Inputs = (Vec<Object>, HashMap<String, usize>, Vec<(String, Archive)>);
+
+/// Reads all object files, symbol tables and archives, preserving the order in which
+/// the objects are listed. Members of the archives are taken later with
+/// [`extract`](super::archive::extract).
+pub fn read_objects(files: Vec<String>) -> Result<Inputs, Error> {
+    let mut objects = Vec::with_capacity(files.len());
     let mut symbols = HashMap::with_capacity(128);
+    let mut archives = Vec::new();
     for file in files {
-        match Path::new(&file)
-            .extension()
-            .and_then(OsStr::to_str)
-            .filter(|&s| s == "65o")
-            .filter(|&s| s == "65s")
-        {
+        match Path::new(&file).extension().and_then(OsStr::to_str) {
             Some("65s") => {
-                for (sym, addr) in read_symtab(&file)? {
+                let table = File::open(&file)
+                    .and_then(|f| read_symtab(BufReader::with_capacity(0x1000, f)))
+                    .map_err(|_| Error::Read(file.clone()))?;
+                for (sym, addr) in table {
                     if symbols.contains_key(&sym) {
-                        return Err(format!(
-                            "`{}` appears multiple times in the symbol tables",
-                            sym
-                        ));
+                        return Err(Error::DuplicateSymbol(sym));
                     }
                     let _ = symbols.insert(sym, addr);
                 }
             }
             Some("65o") => {
-                let obj_sections = read_object(&file)
-                    .map_err(|_| format!("error reading object file {}", file))?;
-                // merge new,y read sections with existing sections
-                for (sect_name, mut sect) in obj_sections {
-                    match all_sections.get_mut(&sect_name) {
-                        Some(existing) => {
-                            // ensure not too big
-                            if existing.size + sect.size >= 0x10000 {
-                                return Err(format!("section {} is too large", sect_name));
-                            }
-                            for i in 0..sect.size {
-                                existing.code[i + existing.size] = sect.code[i];
-                            }
-                            // since sect will be appended to existing, add its offset
-                            for (_, lab) in &mut sect.labels {
-                                lab.offset += existing.size;
-                            }
-                            for rf in &mut sect.references {
-                                rf.offset += existing.size;
-                            }
-                            // associate an object with which bytes it contributed to the section
-                            existing
-                                .objects
-                                .insert(file.clone(), existing.size..(existing.size + sect.size));
-                            existing.labels.extend(sect.labels);
-                            existing.references.extend(sect.references);
-                            existing.size += sect.size;
-                        }
-                        None => {
-                            let _ = all_sections.insert(sect_name, sect);
-                        }
-                    }
-                }
+                let obj = File::open(&file)
+                    .and_then(|f| read_object(file.clone(), BufReader::with_capacity(0x10000, f)))
+                    .map_err(|_| Error::Read(file.clone()))?;
+                objects.push(obj);
             }
-            _ => {
-                return Err(format!("file {} has wrong extension", file));
+            Some("65r") => {
+                let archive = File::open(&file)
+                    .and_then(|f| read_archive(BufReader::with_capacity(0x10000, f)))
+                    .map_err(|_| Error::Read(file.clone()))?;
+                archives.push((file, archive));
             }
+            _ => return Err(Error::Extension(file)),
         }
     }
 
-    Ok((all_sections, symbols))
+    Ok((objects, symbols, archives))
+}
+
+/// Turns a null-padded name into a string.
+pub(crate) fn read_name(buffer: &[u8]) -> String {
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..len]).into_owned()
 }
 
 /// Reads sections from one object.
-fn read_object(file: &String) -> io::Result<HashMap<String, Section>> {
+pub fn read_object<R: Read>(name: String, mut obj_file: R) -> io::Result<Object> {
     let mut sections = HashMap::with_capacity(5);
-    let mut obj_file = BufReader::with_capacity(0x10000, File::open(&file)?);
     let mut u16_buffer = [0; 2];
     let mut u32_buffer = [0; 4];
     let mut name_buffer = [0; 32];
     let mut ref_buffer = [0; 64];
 
     // read num_sections
-    obj_file.read(&mut u32_buffer)?;
+    obj_file.read_exact(&mut u32_buffer)?;
     for _ in 0..u32::from_le_bytes(u32_buffer) {
         // read section header
-        obj_file.read(&mut name_buffer)?;
-        let sect_name = unsafe { String::from(std::str::from_utf8_unchecked(&name_buffer)) };
-        obj_file.read(&mut u32_buffer)?;
+        obj_file.read_exact(&mut name_buffer)?;
+        let sect_name = read_name(&name_buffer);
+        obj_file.read_exact(&mut u32_buffer)?;
         let sect_size = u32::from_le_bytes(u32_buffer) as usize;
-        obj_file.read(&mut u32_buffer)?;
+        obj_file.read_exact(&mut u32_buffer)?;
         let num_labels = u32::from_le_bytes(u32_buffer);
-        obj_file.read(&mut u32_buffer)?;
+        obj_file.read_exact(&mut u32_buffer)?;
         let num_references = u32::from_le_bytes(u32_buffer);
+        if sect_size > 0x10000 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, ""));
+        }
 
         let mut labels = HashMap::with_capacity(64);
         let mut references = Vec::with_capacity(64);
         // iterate over root labels
         for _ in 0..num_labels {
             // root label has name, num_children, offset, then visibility
-            obj_file.read(&mut name_buffer)?;
-            let lab_name = unsafe { String::from(std::str::from_utf8_unchecked(&name_buffer)) };
-            obj_file.read(&mut u32_buffer)?;
+            obj_file.read_exact(&mut name_buffer)?;
+            let lab_name = read_name(&name_buffer);
+            obj_file.read_exact(&mut u32_buffer)?;
             let num_children = u32::from_le_bytes(u32_buffer);
-            obj_file.read(&mut u32_buffer)?;
+            obj_file.read_exact(&mut u32_buffer)?;
             let offset = u32::from_le_bytes(u32_buffer) as usize;
-            obj_file.read(&mut u32_buffer)?;
+            obj_file.read_exact(&mut u32_buffer)?;
             let vis = num::FromPrimitive::from_u32(u32::from_le_bytes(u32_buffer))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, ""))?;
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, ""))?;
 
-            labels.insert(
-                lab_name.clone(),
-                Label {
-                    vis: vis,
-                    offset: offset,
-                },
-            );
+            labels.insert(lab_name.clone(), Label { vis, offset });
 
             for _ in 0..num_children {
                 // each child has name, offset, and visiobillity
-                obj_file.read(&mut name_buffer)?;
+                obj_file.read_exact(&mut name_buffer)?;
                 let mut child_name = String::with_capacity(64);
                 child_name.push_str(lab_name.as_str());
                 child_name.push('.');
-                child_name.push_str(unsafe { std::str::from_utf8_unchecked(&name_buffer) });
+                child_name.push_str(&read_name(&name_buffer));
 
-                obj_file.read(&mut u32_buffer)?;
+                obj_file.read_exact(&mut u32_buffer)?;
                 let offset = u32::from_le_bytes(u32_buffer) as usize;
-                obj_file.read(&mut u32_buffer)?;
+                obj_file.read_exact(&mut u32_buffer)?;
                 let vis = num::FromPrimitive::from_u32(u32::from_le_bytes(u32_buffer))
-                    .ok_or(io::Error::new(io::ErrorKind::InvalidData, ""))?;
-
-                labels.insert(
-                    child_name,
-                    Label {
-                        vis: vis,
-                        offset: offset,
-                    },
-                );
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+
+                labels.insert(child_name, Label { vis, offset });
             }
         }
 
@@ -151,74 +119,283 @@ fn read_object(file: &String) -> io::Result<HashMap<String, Section>> {
             // reference has fully-qualified name, offset to put into,
             // which byte of the label address to take, and if the preceding byte
             // is a branch instruction
-            obj_file.read(&mut ref_buffer)?;
-            let lab_name = unsafe { String::from(std::str::from_utf8_unchecked(&ref_buffer)) };
-            obj_file.read(&mut u32_buffer)?;
+            obj_file.read_exact(&mut ref_buffer)?;
+            let lab_name = read_name(&ref_buffer);
+            obj_file.read_exact(&mut u32_buffer)?;
             let offset = u32::from_le_bytes(u32_buffer) as usize;
-            obj_file.read(&mut u16_buffer)?;
+            obj_file.read_exact(&mut u16_buffer)?;
             let which = num::FromPrimitive::from_u16(u16::from_le_bytes(u16_buffer))
-                .ok_or(io::Error::new(io::ErrorKind::InvalidData, ""))?;
-            obj_file.read(&mut u16_buffer)?;
-            let branch = match u16::from_le_bytes(u16_buffer) {
-                1 => true,
-                _ => false,
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, ""))?;
+            obj_file.read_exact(&mut u16_buffer)?;
+            let branch = u16::from_le_bytes(u16_buffer) == 1;
+            // the value put in must be inside of the section
+            let width = if !branch && which == ByteSelect::Both {
+                2
+            } else {
+                1
             };
+            if offset + width > sect_size {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, ""));
+            }
 
             references.push(Reference {
                 referred: lab_name,
-                offset: offset,
+                offset,
                 which_byte: which,
-                branch: branch,
+                branch,
             })
         }
 
-        let mut sect = Section {
-            labels: labels,
-            references: references,
+        // pad to 4 bytes
+        let mut code = vec![0; sect_size + ((4 - (sect_size & 3)) & 3)];
+        obj_file.read_exact(&mut code)?;
+        code.truncate(sect_size);
+        let sect = Section {
+            labels,
+            references,
             base: 0,
+            load: 0,
+            bank: None,
             size: sect_size,
-            code: [0; 65536],
-            objects: HashMap::with_capacity(3),
+            code,
+            align: 1,
+            blocks: Vec::new(),
         };
-
-        // pad to 4 bytes
-        obj_file.read(&mut sect.code[0..(sect_size + ((4 - (sect_size & 3)) & 3))])?;
         sections.insert(sect_name, sect);
     }
 
-    Ok(sections)
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "");
+    for _ in 0..read_count(&mut obj_file)? {
+        obj_file.read_exact(&mut name_buffer)?;
+        let sect = sections
+            .get_mut(&read_name(&name_buffer))
+            .ok_or_else(invalid)?;
+        obj_file.read_exact(&mut u32_buffer)?;
+        sect.align = u32::from_le_bytes(u32_buffer) as usize;
+        if !sect.align.is_power_of_two() {
+            return Err(invalid());
+        }
+        obj_file.read_exact(&mut u32_buffer)?;
+        for _ in 0..u32::from_le_bytes(u32_buffer) {
+            obj_file.read_exact(&mut name_buffer)?;
+            let label = read_name(&name_buffer);
+            obj_file.read_exact(&mut u32_buffer)?;
+            let offset = u32::from_le_bytes(u32_buffer) as usize;
+            obj_file.read_exact(&mut u32_buffer)?;
+            let size = u32::from_le_bytes(u32_buffer) as usize;
+            if offset + size > sect.size {
+                return Err(invalid());
+            }
+            sect.blocks.push(Block {
+                label,
+                offset,
+                size,
+            });
+        }
+    }
+
+    let mut assertions = Vec::new();
+    for _ in 0..read_count(&mut obj_file)? {
+        obj_file.read_exact(&mut name_buffer)?;
+        let section = read_name(&name_buffer);
+        obj_file.read_exact(&mut u32_buffer)?;
+        let offset = u32::from_le_bytes(u32_buffer) as usize;
+        obj_file.read_exact(&mut u32_buffer)?;
+        let line = u32::from_le_bytes(u32_buffer) as usize;
+        if sections.get(&section).is_none_or(|sect| offset > sect.size) {
+            return Err(invalid());
+        }
+        let mut sizes = [0; 3];
+        for size in &mut sizes {
+            obj_file.read_exact(&mut u32_buffer)?;
+            *size = u32::from_le_bytes(u32_buffer) as usize;
+        }
+        let mut strings = Vec::with_capacity(3);
+        for size in sizes {
+            // pad to 4 bytes
+            let padded = size + ((4 - (size & 3)) & 3);
+            let mut bytes = Vec::with_capacity(padded.min(0x1000));
+            obj_file
+                .by_ref()
+                .take(padded as u64)
+                .read_to_end(&mut bytes)?;
+            if bytes.len() != padded {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, ""));
+            }
+            bytes.truncate(size);
+            strings.push(String::from_utf8(bytes).map_err(|_| invalid())?);
+        }
+        let message = strings.pop().unwrap();
+        let expression = strings.pop().unwrap();
+        let file = strings.pop().unwrap();
+        assertions.push(Assertion {
+            section,
+            offset,
+            file,
+            line,
+            expression,
+            message,
+        });
+    }
+
+    Ok(Object {
+        name,
+        sections,
+        assertions,
+    })
 }
 
-/// Reads a symbol table file.
-fn read_symtab(file: &String) -> Result<HashMap<String, usize>, String> {
+/// Writes an object in the same format the assembler does, such as one merged by a
+/// partial link. Every child label must be in the same section as its parent.
+pub fn write_object<W: Write>(obj: &Object, out: W) -> io::Result<()> {
+    let mut obj_file = BufWriter::with_capacity(0x10000, out);
+    let mut sections = obj.sections.iter().collect::<Vec<_>>();
+    sections.sort_by_key(|&(name, _)| name);
+
+    // object header
+    obj_file.write_all(&(sections.len() as u32).to_le_bytes())?;
+    for &(name, sect) in &sections {
+        // the label tree, parents in order of their offsets with their children after
+        let mut parents = sect
+            .labels
+            .iter()
+            .filter(|(name, _)| !name.contains('.'))
+            .collect::<Vec<_>>();
+        parents.sort_by_key(|&(name, lab)| (lab.offset, name));
+        let mut children = HashMap::<&str, Vec<_>>::with_capacity(parents.len());
+        for (name, lab) in &sect.labels {
+            if let Some((parent, child)) = name.split_once('.') {
+                if !sect.labels.contains_key(parent) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, ""));
+                }
+                children.entry(parent).or_default().push((child, lab));
+            }
+        }
+
+        // section header
+        obj_file.write_all(&name_bytes::<32>(name)?)?;
+        obj_file.write_all(&(sect.size as u32).to_le_bytes())?;
+        obj_file.write_all(&(parents.len() as u32).to_le_bytes())?;
+        obj_file.write_all(&(sect.references.len() as u32).to_le_bytes())?;
+
+        // label block
+        for (parent, lab) in parents {
+            let mut children = children.remove(parent.as_str()).unwrap_or_default();
+            children.sort_by_key(|&(name, lab)| (lab.offset, name));
+            obj_file.write_all(&name_bytes::<32>(parent)?)?;
+            obj_file.write_all(&(children.len() as u32).to_le_bytes())?;
+            obj_file.write_all(&(lab.offset as u32).to_le_bytes())?;
+            obj_file.write_all(&(lab.vis as u32).to_le_bytes())?;
+            for (child, lab) in children {
+                obj_file.write_all(&name_bytes::<32>(child)?)?;
+                obj_file.write_all(&(lab.offset as u32).to_le_bytes())?;
+                obj_file.write_all(&(lab.vis as u32).to_le_bytes())?;
+            }
+        }
+        // reference block
+        for rf in &sect.references {
+            obj_file.write_all(&name_bytes::<64>(&rf.referred)?)?;
+            obj_file.write_all(&(rf.offset as u32).to_le_bytes())?;
+            obj_file.write_all(&(rf.which_byte as u16).to_le_bytes())?;
+            obj_file.write_all(&(rf.branch as u16).to_le_bytes())?;
+        }
+
+        // pad code to 4 bytes
+        obj_file.write_all(&sect.code[..sect.size])?;
+        obj_file.write_all(&[0; 3][..((4 - (sect.size & 3)) & 3)])?;
+    }
+
+    // section attributes, only for the sections that have any
+    let attributed = sections
+        .iter()
+        .filter(|(_, sect)| sect.align > 1 || !sect.blocks.is_empty())
+        .collect::<Vec<_>>();
+    obj_file.write_all(&(attributed.len() as u32).to_le_bytes())?;
+    for &&(name, sect) in &attributed {
+        obj_file.write_all(&name_bytes::<32>(name)?)?;
+        obj_file.write_all(&(sect.align as u32).to_le_bytes())?;
+        obj_file.write_all(&(sect.blocks.len() as u32).to_le_bytes())?;
+        for block in &sect.blocks {
+            obj_file.write_all(&name_bytes::<32>(&block.label)?)?;
+            obj_file.write_all(&(block.offset as u32).to_le_bytes())?;
+            obj_file.write_all(&(block.size as u32).to_le_bytes())?;
+        }
+    }
+
+    // assertions
+    obj_file.write_all(&(obj.assertions.len() as u32).to_le_bytes())?;
+    for assertion in &obj.assertions {
+        obj_file.write_all(&name_bytes::<32>(&assertion.section)?)?;
+        obj_file.write_all(&(assertion.offset as u32).to_le_bytes())?;
+        obj_file.write_all(&(assertion.line as u32).to_le_bytes())?;
+        let strings = [&assertion.file, &assertion.expression, &assertion.message];
+        for string in &strings {
+            obj_file.write_all(&(string.len() as u32).to_le_bytes())?;
+        }
+        // pad each to 4 bytes
+        for string in &strings {
+            obj_file.write_all(string.as_bytes())?;
+            obj_file.write_all(&[0; 3][..((4 - (string.len() & 3)) & 3)])?;
+        }
+    }
+
+    obj_file.flush()
+}
+
+/// Turns a name into null-padded bytes, leaving room for the null terminator.
+fn name_bytes<const N: usize>(name: &str) -> io::Result<[u8; N]> {
+    if name.len() >= N {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, ""));
+    }
+    let mut buffer = [0; N];
+    buffer[..name.len()].copy_from_slice(name.as_bytes());
+    Ok(buffer)
+}
+
+/// Reads the count of one of the lists after the sections, which is 0 if the object
+/// ends before it.
+fn read_count<R: Read>(obj_file: &mut R) -> io::Result<u32> {
+    let mut u32_buffer = [0; 4];
+    match obj_file.read_exact(&mut u32_buffer) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+        result => result.map(|()| u32::from_le_bytes(u32_buffer)),
+    }
+}
+
+/// Reads a symbol table.
+pub fn read_symtab<R: Read>(mut sym_file: R) -> io::Result<Vec<(String, usize)>> {
     let mut u32_buffer = [0; 4];
     let mut name_buffer = [0; 64];
-    let mut symbols = HashMap::with_capacity(32);
-    let mut sym_file = BufReader::with_capacity(
-        0x1000,
-        File::open(&file).map_err(|_| format!("error reading symbol file {}", file))?,
-    );
-
-    sym_file
-        .read(&mut u32_buffer)
-        .map_err(|_| format!("error reading symbol file {}", file))?;
-    for _ in 0..u32::from_le_bytes(u32_buffer) {
-        sym_file
-            .read(&mut name_buffer)
-            .map_err(|_| format!("error reading symbol file {}", file))?;
-        sym_file
-            .read(&mut u32_buffer)
-            .map_err(|_| format!("error reading symbol file {}", file))?;
-        let label = unsafe { String::from(std::str::from_utf8_unchecked(&name_buffer)) };
+
+    sym_file.read_exact(&mut u32_buffer)?;
+    let num_symbols = u32::from_le_bytes(u32_buffer) as usize;
+    let mut symbols = Vec::with_capacity(num_symbols.min(0x1000));
+    for _ in 0..num_symbols {
+        sym_file.read_exact(&mut name_buffer)?;
+        sym_file.read_exact(&mut u32_buffer)?;
+        let label = read_name(&name_buffer);
         let address = u32::from_le_bytes(u32_buffer) as usize;
-        if symbols.contains_key(&label) {
-            return Err(format!(
-                "`{}` appears multiple times in the symbol tables",
-                label
-            ));
-        }
-        symbols.insert(label, address);
+        symbols.push((label, address));
     }
 
     Ok(symbols)
 }
+
+/// Writes a symbol table, sorted by name.
+pub fn write_symtab<W: Write>(symbols: &HashMap<String, usize>, out: W) -> io::Result<()> {
+    let mut sym_file = BufWriter::with_capacity(0x1000, out);
+    let mut sorted = symbols.iter().collect::<Vec<_>>();
+    sorted.sort();
+
+    sym_file.write_all(&(sorted.len() as u32).to_le_bytes())?;
+    for (name, &address) in sorted {
+        let mut name_buffer = [0; 64];
+        // always leave room for the null terminator
+        let len = name.len().min(63);
+        name_buffer[..len].copy_from_slice(&name.as_bytes()[..len]);
+        sym_file.write_all(&name_buffer)?;
+        sym_file.write_all(&(address as u32).to_le_bytes())?;
+    }
+
+    sym_file.flush()
+}
@@ -0,0 +1,305 @@
+//! Partial linking, which merges objects into one object to be linked later.
+//!
+//! Sections with the same name are put one after another, each object's part aligned
+//! as it was, and the merged object has every label and reference of the objects.
+//! References that aren't found in their own object stay for the final link. Branches
+//! to a label in the same section are filled in now, since their distance doesn't
+//! depend on where the section is placed.
+//!
+//! Object and hidden labels may only be referred to from their own object, so one is
+//! renamed to `<label>_<n>` if another object uses the same name for a label or a
+//! reference that it doesn't define itself. A weak label gives way to a global one of
+//! the same name like it does in a full link, so it keeps both the same way.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::error::Error;
+use super::formats::*;
+use super::linker::find_label;
+
+/// Merge the objects, in order, into one object named `name`.
+pub fn merge(name: String, objects: &[Object]) -> Result<Object, Vec<Error>> {
+    // which objects define each name globally or need it from another object
+    let mut claims = HashMap::<String, HashSet<usize>>::new();
+    let mut used = HashSet::new();
+    let mut strong = HashSet::new();
+    let mut weak = HashMap::new();
+    for (idx, obj) in objects.iter().enumerate() {
+        for (sect_name, sect) in &obj.sections {
+            for (name, lab) in &sect.labels {
+                used.insert(root(name).to_string());
+                match lab.vis {
+                    Visibility::Global => {
+                        strong.insert(name.as_str());
+                    }
+                    Visibility::Weak => {
+                        weak.entry(name.as_str()).or_insert(idx);
+                    }
+                    _ => continue,
+                }
+                claims
+                    .entry(root(name).to_string())
+                    .or_default()
+                    .insert(idx);
+            }
+            for rf in &sect.references {
+                if find_label(obj, sect_name, rf).is_none() {
+                    claims
+                        .entry(root(&rf.referred).to_string())
+                        .or_default()
+                        .insert(idx);
+                }
+            }
+        }
+        for assertion in &obj.assertions {
+            rename_symbols(&assertion.expression, |symbol| {
+                if !object_label(obj, symbol) {
+                    claims.entry(symbol.to_string()).or_default().insert(idx);
+                }
+                None
+            });
+        }
+    }
+    used.extend(claims.keys().cloned());
+    // a weak label that gives way to a global one or an earlier weak one
+    let gives_way = |name: &str, idx: usize| strong.contains(name) || weak[name] != idx;
+
+    // rename the labels that another object might reach otherwise
+    let mut renames = vec![HashMap::new(); objects.len()];
+    let mut kept = HashSet::new();
+    for (idx, obj) in objects.iter().enumerate() {
+        // whether any label of each name stays global
+        let mut roots = BTreeMap::new();
+        for (name, lab) in obj.sections.values().flat_map(|sect| &sect.labels) {
+            let global = match lab.vis {
+                Visibility::Global => true,
+                Visibility::Weak => !gives_way(name, idx),
+                _ => false,
+            };
+            *roots.entry(root(name)).or_insert(false) |= global;
+        }
+        for (root, global) in roots {
+            if global {
+                continue;
+            }
+            let claimed = claims
+                .get(root)
+                .is_some_and(|objs| objs.iter().any(|&other| other != idx));
+            if !claimed && kept.insert(root) {
+                continue;
+            }
+            let renamed = fresh_name(root, idx, objects.len(), &used);
+            used.insert(renamed.clone());
+            renames[idx].insert(root.to_string(), renamed);
+        }
+    }
+
+    let mut merged = Object {
+        name,
+        sections: HashMap::new(),
+        assertions: Vec::new(),
+    };
+    let mut errors = Vec::new();
+    let mut defined = HashSet::new();
+    for (idx, obj) in objects.iter().enumerate() {
+        let renames = &renames[idx];
+        let mut starts = HashMap::with_capacity(obj.sections.len());
+        let mut sect_names = obj.sections.keys().collect::<Vec<_>>();
+        sect_names.sort();
+        for sect_name in sect_names {
+            let sect = &obj.sections[sect_name];
+            let part = merged
+                .sections
+                .entry(sect_name.clone())
+                .or_insert_with(|| Section {
+                    code: Vec::new(),
+                    labels: HashMap::new(),
+                    references: Vec::new(),
+                    base: 0,
+                    load: 0,
+                    bank: None,
+                    size: 0,
+                    align: 1,
+                    blocks: Vec::new(),
+                });
+            let start = part.size.div_ceil(sect.align) * sect.align;
+            part.align = part.align.max(sect.align);
+            part.code.resize(start, 0);
+            part.code.extend_from_slice(&sect.code[..sect.size]);
+            part.size = start + sect.size;
+            if part.size > 0x10000 {
+                errors.push(Error::AddressSpace {
+                    section: sect_name.clone(),
+                    end: part.size,
+                });
+            }
+            starts.insert(sect_name.as_str(), start);
+
+            for (name, lab) in &sect.labels {
+                // a weak label that gives way only scopes its children, like in a link
+                let vis = match lab.vis {
+                    Visibility::Weak if gives_way(name, idx) => {
+                        if name.contains('.') {
+                            continue;
+                        }
+                        Visibility::Hidden
+                    }
+                    vis => vis,
+                };
+                let name = rename(name, renames);
+                if !defined.insert(name.clone()) {
+                    errors.push(Error::DuplicateSymbol(name));
+                    continue;
+                }
+                part.labels.insert(
+                    name,
+                    Label {
+                        vis,
+                        offset: start + lab.offset,
+                    },
+                );
+            }
+
+            for rf in &sect.references {
+                let referred = match find_label(obj, sect_name, rf) {
+                    // a weak label may still give way to a global one later
+                    Some((target, lab))
+                        if rf.branch && target == sect_name && lab.vis != Visibility::Weak =>
+                    {
+                        // relative to the address after the operand
+                        let distance = lab.offset as isize - (rf.offset + 1) as isize;
+                        if !(-128..=127).contains(&distance) {
+                            errors.push(Error::BranchRange {
+                                symbol: rf.referred.clone(),
+                                object: obj.name.clone(),
+                                section: sect_name.clone(),
+                                offset: rf.offset,
+                                distance,
+                            });
+                        }
+                        part.code[start + rf.offset] = distance as u8;
+                        continue;
+                    }
+                    // the ones to a weak label that gives way go to the one kept
+                    Some((_, lab))
+                        if lab.vis != Visibility::Weak || !gives_way(&rf.referred, idx) =>
+                    {
+                        rename(&rf.referred, renames)
+                    }
+                    _ => rf.referred.clone(),
+                };
+                part.references.push(Reference {
+                    referred,
+                    offset: start + rf.offset,
+                    ..rf.clone()
+                });
+            }
+
+            part.blocks.extend(sect.blocks.iter().map(|block| Block {
+                label: rename(&block.label, renames),
+                offset: start + block.offset,
+                size: block.size,
+            }));
+        }
+
+        for assertion in &obj.assertions {
+            let expression = rename_symbols(&assertion.expression, |symbol| {
+                match obj
+                    .sections
+                    .values()
+                    .find_map(|sect| sect.labels.get(symbol))
+                {
+                    Some(lab) if lab.vis == Visibility::Weak && gives_way(symbol, idx) => None,
+                    Some(lab) if lab.vis != Visibility::Hidden => renames.get(symbol).cloned(),
+                    _ => None,
+                }
+            });
+            merged.assertions.push(Assertion {
+                section: assertion.section.clone(),
+                offset: starts[assertion.section.as_str()] + assertion.offset,
+                // the merged object's name wouldn't say where it came from
+                file: match assertion.file.as_str() {
+                    "" => obj.name.clone(),
+                    file => file.to_string(),
+                },
+                line: assertion.line,
+                expression,
+                message: assertion.message.clone(),
+            });
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(merged)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The parent label's name of a label.
+fn root(name: &str) -> &str {
+    name.split('.').next().unwrap()
+}
+
+/// A label's name with its parent renamed.
+fn rename(name: &str, renames: &HashMap<String, String>) -> String {
+    match renames.get(root(name)) {
+        Some(renamed) => format!("{}{}", renamed, &name[root(name).len()..]),
+        None => name.to_string(),
+    }
+}
+
+/// A name that isn't used yet for a label, short enough for the object format.
+fn fresh_name(root: &str, idx: usize, num_objects: usize, used: &HashSet<String>) -> String {
+    let mut n = idx;
+    loop {
+        let suffix = format!("_{}", n);
+        let len = root.len().min(31 - suffix.len());
+        let name = format!("{}{}", &root[..len], suffix);
+        if !used.contains(&name) {
+            return name;
+        }
+        n += num_objects;
+    }
+}
+
+/// Whether a symbol in an assertion refers to a label of the object.
+fn object_label(obj: &Object, symbol: &str) -> bool {
+    obj.sections
+        .values()
+        .filter_map(|sect| sect.labels.get(symbol))
+        .any(|lab| lab.vis != Visibility::Hidden)
+}
+
+/// Call `f` on every symbol in an expression, replacing it with what `f` returns,
+/// if anything. Functions and the sections they're given aren't symbols.
+fn rename_symbols(expression: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut renamed = String::with_capacity(expression.len());
+    let mut rest = expression;
+    let mut section = false;
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let (symbol, after) = rest.split_at(len);
+            let call = after.trim_start().starts_with('(');
+            let new = if call || section { None } else { f(symbol) };
+            renamed.push_str(new.as_deref().unwrap_or(symbol));
+            section = call && (symbol == "SIZEOF" || symbol == "ADDR");
+            rest = after;
+            continue;
+        } else if c.is_ascii_digit() || c == '$' {
+            // numbers, with the letters of hexadecimal digits
+            1 + rest[1..]
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len() - 1)
+        } else {
+            section &= c == '(' || c.is_whitespace();
+            c.len_utf8()
+        };
+        renamed.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    renamed
+}
@@ -0,0 +1,65 @@
+//! Writes an image as a Commodore program file.
+//!
+//! A `.prg` is the address it's loaded at as a little endian word followed by the
+//! bytes to load there, so the image must not have banks. With a BASIC stub the file
+//! is loaded at `$0801` instead, the start of BASIC on the C64, and begins with the
+//! tokenized line `10 SYS <entry>` so that `RUN` starts the program. Everything must
+//! then be placed after the stub, which ends at `$080D` when the entry is a four
+//! digit address like 2061.
+
+use super::error::Error;
+use super::linker::Image;
+
+/// Where BASIC programs are loaded.
+const BASIC_START: usize = 0x0801;
+
+/// The token of `SYS`.
+const SYS: u8 = 0x9E;
+
+/// The bytes of the program file for an image, with a BASIC stub starting it if
+/// `basic` is set.
+pub fn prg(image: &Image, basic: bool) -> Result<Vec<u8>, Error> {
+    if !image.banks.is_empty() {
+        return Err(Error::Banked("PRG".to_string()));
+    }
+    if !basic {
+        let mut bytes = Vec::with_capacity(image.code.len() + 2);
+        bytes.extend_from_slice(&(image.base as u16).to_le_bytes());
+        bytes.extend_from_slice(&image.code);
+        return Ok(bytes);
+    }
+
+    let entry = match &image.entry {
+        Some((_, entry)) => entry & 0xFFFF,
+        None => return Err(Error::NoEntry("the BASIC stub".to_string())),
+    };
+    let stub = basic_stub(entry);
+    let end = BASIC_START + stub.len();
+    if !image.code.is_empty() && image.base < end {
+        return Err(Error::StubOverlap {
+            start: image.base,
+            end,
+        });
+    }
+    let mut bytes = Vec::with_capacity(2 + image.base.max(end) - BASIC_START + image.code.len());
+    bytes.extend_from_slice(&(BASIC_START as u16).to_le_bytes());
+    bytes.extend_from_slice(&stub);
+    // anything between the stub and the image is 0
+    if !image.code.is_empty() {
+        bytes.resize(2 + image.base - BASIC_START, 0);
+        bytes.extend_from_slice(&image.code);
+    }
+    Ok(bytes)
+}
+
+/// The BASIC program `10 SYS <entry>` as it's stored at `BASIC_START`.
+fn basic_stub(entry: usize) -> Vec<u8> {
+    // the link to the next line, the line number, the line and the end of the program
+    let mut line = vec![0, 0, 10, 0, SYS];
+    line.extend_from_slice(entry.to_string().as_bytes());
+    line.push(0);
+    let next = BASIC_START + line.len();
+    line[..2].copy_from_slice(&(next as u16).to_le_bytes());
+    line.extend_from_slice(&[0, 0]);
+    line
+}
@@ -20,39 +20,237 @@
 //! ```text
 //! 0x0f00 something
 //! ```
-//! If the `something` section is larger than `0x100` bytes then it will overwrite the
-//! beginning of `text` because it is listed later in the script. You can set a size
-//! limit as in the `data` section if you want to prevent that. Because it is at the
-//! earliest address, it will be at offset 0 in the output binary file.
-
-// NOTE probably not going to introduce this features
-// You may also align a section:
-// ```text
-// // align address to next 0x200 byte boundary (pad with 0's)
-// align 0x200 some_other_section
-// ```
-// `align` is a context-sensitive keyword, so it's still a valid
-// section name.
+//! If the `something` section is larger than `0x100` bytes then it would overlap the
+//! beginning of `text`, which is an error. You can set a size limit as in the `data`
+//! section to catch that earlier. Because it is at the earliest address, it will be at
+//! offset 0 in the output binary file.
+//! ```text
+//! overlay 0x1000 mirror
+//! ```
+//! A group starting with `overlay` is allowed to overlap other groups, for overlays
+//! or mirrored windows of ROM. Where sections overlap in the output, the one listed
+//! later in the script wins. `overlay` is only a keyword at the start of a line.
+//! ```text
+//! keep 0xFFFA vectors
+//! keep overlay 0x1000 mirror
+//! ```
+//! A group starting with `keep` is never removed by `--gc-sections`, see the `gc`
+//! module. `keep` and `overlay` may be given in either order.
+//!
+//! ## Alignment and pages
+//! ```text
+//! 0x8000 text tables(align=0x100, page) data
+//! ```
+//! Options in parentheses after a section's name change how it's placed. `align=`
+//! makes it start at a multiple of a size, leaving a gap before it in its group, and
+//! with `page` it's an error for the section to cross a 256 byte page once placed.
+//! Objects can ask the same of their own part of a section with the assembler's
+//! `aln` directive, and keep labeled blocks of it inside of one page with `pag`.
+//! Every page crossed is reported with the section or label that crossed it.
+//!
+//! ## Regions
+//! ```text
+//! region ROM 0x8000 0x8000 rom fill=0xFF
+//! region ZP 0x0080 0x80 ram zp
+//! ```
+//! A region names a range of memory with a start address and a size. It holds either
+//! `rom`, which is written to the output, or `ram`, which is only reserved so sections
+//! placed there may not contain anything but zeros. `rom` is the default. `zp` requires
+//! the region to be in the zero page. Unused space in a ROM region with a `fill` byte
+//! is filled with it, and the whole region is written to the output even if it isn't
+//! full. `region` is only a keyword at the start of a line.
+//! ```text
+//! text > ROM
+//! 0x9000 tables 0x100 > ROM
+//! vars > ZP
+//! ```
+//! A group is assigned to a region by ending its line with `>` and the region's name.
+//! Without an explicit address it follows the last group placed in that region, or
+//! starts at the beginning of the region if it's the first. The group must fit inside
+//! of the region. Regions must be declared before any group is assigned to them.
+//!
+//! ## Packing
+//! ```text
+//! pack best music(align=0x100) sfx(with=music) levels > BANK0 BANK1 BANK2
+//! ```
+//! A line starting with `pack` lists sections that may go in any of the regions after
+//! the `>`. Each section is placed on its own after the last group in the region it
+//! goes in, largest first. With `first`, the default, it goes in the first region
+//! listed that has room, and with `best` in the region with the least room left after
+//! it. Packed sections take the same options as sections in a group, and `with=` puts
+//! one in the same bank as another section placed above it, or the same region if
+//! that section isn't in a bank. The map and `--regions` show how full each region ended up.
+//! `pack` is only a keyword at the start of a line, and `first` and `best` only after
+//! it.
+//!
+//! ## Load addresses
+//! ```text
+//! idata > RAM @ ROM
+//! tables @ 0xc000
+//! ```
+//! A group can be loaded from somewhere other than where it runs by ending its line
+//! with `@` and either a region, where it follows the last group in that region, or
+//! an address. Labels in the group resolve to where it runs but its bytes are put in
+//! the output where it's loaded from, even if it runs from RAM. Startup code can copy
+//! it into place using the `__copy_table` section described in the `copy` module.
+//!
+//! ## Banks
+//! ```text
+//! region BANK0 0x8000 0x4000 rom bank=0
+//! region BANK1 0x8000 0x4000 rom bank=1
+//! region FIXED 0xC000 0x4000 rom bank=2
+//! far switch_bank
+//! ```
+//! A region with `bank=` is a bank of a bank-switched ROM. Banks may share addresses
+//! with each other, like `BANK0` and `BANK1` sharing the window at `0x8000`, but
+//! nothing else. When there are banks the output is laid out bank by bank in order of
+//! their numbers, each in full, and everything else placed in ROM must be in a bank.
+//! A group in a bank can't have a load address, but it may be loaded from one.
+//!
+//! Symbols in a bank have the bank number in bits 16-23 of their address, as written
+//! to symbol tables. Referring to a label in a bank that shares its window with
+//! another bank is an error unless the reference comes from the same bank, since the
+//! other bank may be the one switched in, so code in `FIXED` may be referred to from
+//! anywhere. `far` lists symbols that may be referred to from any bank anyway, for
+//! code that switches banks itself, and is only a keyword at the start of a line.
+//! Symbols from a symbol table are only known to be in a bank if it isn't bank 0.
+//! ```text
+//! trampoline far_call fixed_call
+//! __trampolines > FIXED
+//! ```
+//! Instead of being an error, a `jsr` into a bank that may not be switched in can go
+//! through a trampoline made from a template section, as described in the
+//! `trampoline` module. The second template is optional and is used for calls from
+//! code that's always switched in. `trampoline` is only a keyword at the start of a
+//! line.
+//!
+//! ## Entry and vectors
+//! ```text
+//! entry reset
+//! vectors nmi=nmi_handler irq=irq_handler
+//! ```
+//! `entry` names the symbol the program starts at, which `--gc-sections` keeps and
+//! output formats with a start address use. `vectors` makes the linker generate the
+//! interrupt vector table at `0xFFFA` as described in the `vectors` module, with
+//! `reset=` defaulting to the entry. The command line's `--entry`, `--nmi` and `--irq`
+//! take the place of these. `entry` and `vectors` are only keywords at the start of
+//! a line.
+//!
+//! ## Cartridges
+//! ```text
+//! ines mapper=1 prg=8 chr=16 mirroring=vertical battery
+//! chr tiles sprites
+//! crt type=0 name=GAME
+//! ```
+//! `ines` gives the settings for the header of an iNES file, with `nes2` for a NES 2.0
+//! header, and `crt` for a C64 cartridge, as described in the `cart` module. `chr`
+//! lists sections holding CHR data, which aren't placed but put after the PRG ROM in
+//! an iNES file in the order listed. The command line's `--ines` and `--crt` take the
+//! same settings, which take the place of the ones given here. `ines`, `chr` and `crt`
+//! are only keywords at the start of a line.
+//!
+//! ## Symbols and expressions
+//! ```text
+//! __stack_top = 0x01FF
+//! . = ALIGN(0x100)
+//! buffer = .
+//! buffer_end = buffer + 0x40
+//! text_end = ADDR(text) + SIZEOF(text)
+//! ```
+//! A line of `name = expression` defines a symbol that references in objects resolve
+//! to just like a global label. `.` is the location counter, the address the next
+//! group without an address or region is placed at, and assigning to it moves it.
+//! Expressions have `+ - * / % & | ^ << >>`, comparisons `== != < <= > >=` and `&& ||`
+//! which are 1 when true and 0 when false, unary `-`, `~` and `!`, parentheses,
+//! numbers in decimal, `0x` or `$` hex, symbols and these functions:
+//! ```text
+//! ALIGN(align)         . rounded up to a multiple of align
+//! ALIGN(value, align)  value rounded up to a multiple of align
+//! SIZEOF(sect)         the size of a section from every object combined
+//! ADDR(sect)           the address of a section
+//! ```
+//! Lines are evaluated in order while placing, so an expression may only use symbols
+//! assigned above it or from symbol tables, and the addresses of sections placed above
+//! it. Symbol names may be up to 63 characters long and section names up to 31.
 
 use std::collections::HashSet;
 
-use super::formats::RelocGroup;
+use super::error;
+use super::expr::{BinOp, Expr, UnOp};
+use super::formats::{
+    Crt, Fit, Ines, Layout, Load, Memory, Mirroring, PackGroup, Packed, Region, RelocGroup,
+    Statement, Vectors,
+};
 use logos::{Lexer, Logos};
 
 /// The tokens recognized in the linker script.
-#[derive(Logos)]
-// extras is (offset, line number)
-#[logos(extras=(usize, usize))]
+#[derive(Logos, Clone, PartialEq)]
+// extras is the line number
+#[logos(extras = usize)]
 enum Token<'a> {
     // treat comment as eol cause it goes up to eol
     #[regex("//.*\n")]
     #[token("\n")]
     Eol,
-    #[regex("0x[0-9a-fA-F]+", |lex| u16::from_str_radix(&lex.slice()[2..], 16).map(|num| num as usize))]
-    #[regex("[0-9]+", |lex| u16::from_str_radix(lex.slice(), 10).map(|num| num as usize))]
+    #[regex("0x[0-9a-fA-F]+", |lex| u32::from_str_radix(&lex.slice()[2..], 16).map(|num| num as usize))]
+    #[regex("\\$[0-9a-fA-F]+", |lex| u32::from_str_radix(&lex.slice()[1..], 16).map(|num| num as usize))]
+    #[regex("[0-9]+", |lex| lex.slice().parse::<u32>().map(|num| num as usize))]
     Number(usize),
-    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| if lex.slice().len() > 31 { None } else { Some(lex.slice()) })]
+    // symbols may be longer than section names
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*", |lex| if lex.slice().len() > 63 { None } else { Some(lex.slice()) })]
     Ident(&'a str),
+    #[token("=")]
+    Equals,
+    #[token(">")]
+    Greater,
+    #[token("@")]
+    At,
+    #[token(".")]
+    Dot,
+    #[token("(")]
+    LParen,
+    #[token(")")]
+    RParen,
+    #[token(",")]
+    Comma,
+    #[token("+")]
+    Plus,
+    #[token("-")]
+    Minus,
+    #[token("*")]
+    Star,
+    #[token("/")]
+    Slash,
+    #[token("%")]
+    Percent,
+    #[token("&")]
+    Amp,
+    #[token("|")]
+    Pipe,
+    #[token("^")]
+    Caret,
+    #[token("~")]
+    Tilde,
+    #[token("<<")]
+    Shl,
+    #[token(">>")]
+    Shr,
+    #[token("==")]
+    EqEq,
+    #[token("!=")]
+    NotEq,
+    #[token("<")]
+    Less,
+    #[token("<=")]
+    LessEq,
+    #[token(">=")]
+    GreaterEq,
+    #[token("&&")]
+    AmpAmp,
+    #[token("||")]
+    PipePipe,
+    #[token("!")]
+    Bang,
     #[error]
     // unimportant whitespace
     #[regex(r"[ \t]+", logos::skip)]
@@ -61,34 +259,423 @@ enum Token<'a> {
 
 use Token::*;
 
+/// The largest group or region.
+const MAX_SIZE: usize = 0x10000;
+
 // TODO maybe also create first and last part of address space at this point as a range
-/// Reads a linker script into relocation groups.
-pub fn read_script(script: String) -> Result<Vec<RelocGroup>, (usize, String)> {
-    let mut lexer = Token::lexer(&script);
+/// Reads a linker script into a layout.
+pub fn read_script(script: &str) -> Result<Layout, error::Error> {
+    read_layout(script).map_err(|(line, message)| error::Error::Script { line, message })
+}
+
+fn read_layout(script: &str) -> Result<Layout, (usize, String)> {
+    let mut lexer = Token::lexer(script);
     // start line number at 1
-    lexer.extras.1 += 1;
+    lexer.extras += 1;
     //keep a set of sections names to ensure it's not listed multiple times
     let mut sect_names = HashSet::with_capacity(5);
-    // list of relocation groups
-    let mut reloc_table = Vec::with_capacity(5);
+    let mut layout = Layout::default();
 
     // read beginning of line
     while let Some(tok) = lexer.next() {
+        // assignments are the only lines with `=` second
+        let assign = matches!(tok, Ident(_) | Dot) && lexer.clone().next() == Some(Equals);
         match tok {
+            Ident(_) | Dot if assign => {
+                let name = match tok {
+                    Ident(name) => Some(name.to_string()),
+                    _ => None,
+                };
+                lexer.next();
+                let line = lexer.extras;
+                let value = read_expr(&mut lexer)?;
+                layout
+                    .statements
+                    .push(Statement::Assign { name, value, line });
+                lexer.extras += 1;
+            }
+            Ident("region") => {
+                let region = read_region(&mut lexer)?;
+                if layout.regions.iter().any(|r| r.name == region.name) {
+                    return Err((
+                        lexer.extras,
+                        format!("region {} declared multiple times", region.name),
+                    ));
+                }
+                if let Some(bank) = region.bank {
+                    if layout.regions.iter().any(|r| r.bank == Some(bank)) {
+                        return Err((
+                            lexer.extras,
+                            format!("bank {} declared multiple times", bank),
+                        ));
+                    }
+                }
+                layout.regions.push(region);
+                lexer.extras += 1;
+            }
+            Ident("pack") => {
+                let pack = read_pack(&mut lexer, &mut sect_names, &mut layout)?;
+                for region in &pack.regions {
+                    if !layout.regions.iter().any(|r| &r.name == region) {
+                        return Err((lexer.extras, format!("region {} is not declared", region)));
+                    }
+                }
+                layout.statements.push(Statement::Pack(pack));
+                lexer.extras += 1;
+            }
+            Ident("trampoline") => {
+                let (name, fixed) = match (lexer.next(), lexer.next()) {
+                    (Some(Ident(name)), Some(Eol) | None) if name.len() <= 31 => (name, None),
+                    (Some(Ident(name)), Some(Ident(fixed)))
+                        if name.len() <= 31 && fixed.len() <= 31 =>
+                    {
+                        match lexer.next() {
+                            Some(Eol) | None => (name, Some(fixed.to_string())),
+                            _ => return Err((lexer.extras, "expected end of line".to_string())),
+                        }
+                    }
+                    _ => return Err((lexer.extras, "expected section name".to_string())),
+                };
+                layout.fixed_trampoline = fixed;
+                if layout.trampoline.replace(name.to_string()).is_some() {
+                    return Err((
+                        lexer.extras,
+                        "trampoline template given multiple times".to_string(),
+                    ));
+                }
+                lexer.extras += 1;
+            }
+            Ident("entry") => {
+                let name = match (lexer.next(), lexer.next()) {
+                    (Some(Ident(name)), Some(Eol) | None) => name,
+                    _ => return Err((lexer.extras, "expected symbol name".to_string())),
+                };
+                if layout.entry.replace(name.to_string()).is_some() {
+                    return Err((lexer.extras, "entry given multiple times".to_string()));
+                }
+                lexer.extras += 1;
+            }
+            Ident("vectors") => {
+                if layout.vectors.is_some() {
+                    return Err((lexer.extras, "vectors given multiple times".to_string()));
+                }
+                layout.vectors = Some(read_vectors(&mut lexer)?);
+                lexer.extras += 1;
+            }
+            Ident("chr") => {
+                loop {
+                    match lexer.next() {
+                        Some(Ident(name)) if name.len() <= 31 && sect_names.insert(name) => {
+                            layout.chr.push(name.to_string())
+                        }
+                        Some(Ident(name)) if name.len() <= 31 => {
+                            return Err((
+                                lexer.extras,
+                                format!("section {} listed multiple times", name),
+                            ))
+                        }
+                        Some(Eol) | None => break,
+                        _ => return Err((lexer.extras, "expected section name".to_string())),
+                    }
+                }
+                lexer.extras += 1;
+            }
+            Ident("ines") => {
+                if layout.ines.is_some() {
+                    return Err((lexer.extras, "ines given multiple times".to_string()));
+                }
+                read_ines(&mut lexer, layout.ines.get_or_insert_with(Ines::default))?;
+                lexer.extras += 1;
+            }
+            Ident("crt") => {
+                if layout.crt.is_some() {
+                    return Err((lexer.extras, "crt given multiple times".to_string()));
+                }
+                read_crt(&mut lexer, layout.crt.get_or_insert_with(Crt::default))?;
+                lexer.extras += 1;
+            }
+            Ident("far") => {
+                loop {
+                    match lexer.next() {
+                        Some(Ident(name)) => layout.far.push(name.to_string()),
+                        Some(Eol) | None => break,
+                        _ => return Err((lexer.extras, "expected symbol name".to_string())),
+                    }
+                }
+                lexer.extras += 1;
+            }
             // start of group list
-            Ident(_) | Number(_) => reloc_table.push(read_group(&mut lexer, tok, &mut sect_names)?),
+            Ident(_) | Number(_) => {
+                // `overlay` and `keep` may come before the group in any order
+                let (mut tok, mut overlay, mut keep) = (tok, false, false);
+                loop {
+                    let prefix = match tok {
+                        Ident(prefix @ "overlay") if !overlay => {
+                            overlay = true;
+                            prefix
+                        }
+                        Ident(prefix @ "keep") if !keep => {
+                            keep = true;
+                            prefix
+                        }
+                        _ => break,
+                    };
+                    tok = match lexer.next() {
+                        Some(tok @ Ident(_)) | Some(tok @ Number(_)) => tok,
+                        _ => {
+                            return Err((lexer.extras, format!("expected group after {}", prefix)))
+                        }
+                    };
+                }
+                let mut group = read_group(&mut lexer, tok, &mut sect_names, &mut layout)?;
+                group.overlay = overlay;
+                group.keep = keep;
+                let load = match &group.load {
+                    Some(Load::Region(region)) => Some(region),
+                    _ => None,
+                };
+                for region in group.region.iter().chain(load) {
+                    if !layout.regions.iter().any(|r| &r.name == region) {
+                        return Err((lexer.extras, format!("region {} is not declared", region)));
+                    }
+                }
+                let banked = |name: &String| {
+                    layout
+                        .regions
+                        .iter()
+                        .any(|r| &r.name == name && r.bank.is_some())
+                };
+                if group.load.is_some() && group.region.as_ref().is_some_and(banked) {
+                    return Err((
+                        lexer.extras,
+                        "a group in a bank can't have a load address".to_string(),
+                    ));
+                }
+                layout.statements.push(Statement::Group(group));
+                // the group consumed its end of line
+                lexer.extras += 1;
+            }
             // empty line
-            Eol => lexer.extras.1 += 1,
-            Error => {
+            Eol => lexer.extras += 1,
+            _ => {
                 return Err((
-                    lexer.extras.1,
+                    lexer.extras,
                     format!("unrecognized token {}", lexer.slice()),
                 ))
             }
         }
     }
 
-    Ok(reloc_table)
+    Ok(layout)
+}
+
+/// Parses iNES settings on their own, like from the command line, over `ines`.
+pub fn parse_ines(text: &str, ines: &mut Ines) -> Result<(), String> {
+    read_ines(&mut Token::lexer(text), ines).map_err(|(_, message)| message)
+}
+
+/// Parses C64 cartridge settings on their own, like from the command line, over `crt`.
+pub fn parse_crt(text: &str, crt: &mut Crt) -> Result<(), String> {
+    read_crt(&mut Token::lexer(text), crt).map_err(|(_, message)| message)
+}
+
+/// Parses an expression on its own, like an assertion's from an object.
+pub fn parse_expr(text: &str) -> Result<Expr, String> {
+    read_expr(&mut Token::lexer(text)).map_err(|(_, message)| message)
+}
+
+/// Reads the rest of a line as an expression.
+fn read_expr<'a>(lexer: &mut Lexer<'a, Token<'a>>) -> Result<Expr, (usize, String)> {
+    let mut tokens = Vec::with_capacity(8);
+    loop {
+        match lexer.next() {
+            Some(Eol) | None => break,
+            Some(Error) => {
+                return Err((
+                    lexer.extras,
+                    format!("unrecognized token {}", lexer.slice()),
+                ))
+            }
+            Some(tok) => tokens.push(tok),
+        }
+    }
+
+    let mut parser = Parser {
+        tokens: &tokens,
+        pos: 0,
+    };
+    match parser.expr(0) {
+        Some(expr) if parser.pos == tokens.len() => Ok(expr),
+        _ => Err((lexer.extras, "invalid expression".to_string())),
+    }
+}
+
+/// Binary operators from lowest to highest precedence.
+const PRECEDENCE: &[&[(Token<'static>, BinOp)]] = &[
+    &[(PipePipe, BinOp::LogicalOr)],
+    &[(AmpAmp, BinOp::LogicalAnd)],
+    &[(Pipe, BinOp::Or)],
+    &[(Caret, BinOp::Xor)],
+    &[(Amp, BinOp::And)],
+    &[(EqEq, BinOp::Eq), (NotEq, BinOp::Ne)],
+    &[
+        (Less, BinOp::Lt),
+        (LessEq, BinOp::Le),
+        (Greater, BinOp::Gt),
+        (GreaterEq, BinOp::Ge),
+    ],
+    &[(Shl, BinOp::Shl), (Shr, BinOp::Shr)],
+    &[(Plus, BinOp::Add), (Minus, BinOp::Sub)],
+    &[
+        (Star, BinOp::Mul),
+        (Slash, BinOp::Div),
+        (Percent, BinOp::Mod),
+    ],
+];
+
+/// Parses the tokens of an expression.
+struct Parser<'t, 'a> {
+    tokens: &'t [Token<'a>],
+    pos: usize,
+}
+
+impl<'t, 'a> Parser<'t, 'a> {
+    fn next(&mut self) -> Option<Token<'a>> {
+        let tok = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn expect(&mut self, tok: Token<'a>) -> Option<()> {
+        if self.next()? == tok {
+            Some(())
+        } else {
+            None
+        }
+    }
+
+    /// Parses operators of a precedence level and everything above it.
+    fn expr(&mut self, level: usize) -> Option<Expr> {
+        let ops = match PRECEDENCE.get(level) {
+            Some(ops) => ops,
+            None => return self.unary(),
+        };
+        let mut left = self.expr(level + 1)?;
+        while let Some(&(_, op)) = self
+            .tokens
+            .get(self.pos)
+            .and_then(|tok| ops.iter().find(|(op_tok, _)| op_tok == tok))
+        {
+            self.pos += 1;
+            let right = self.expr(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<Expr> {
+        Some(match self.next()? {
+            Minus => Expr::Unary(UnOp::Neg, Box::new(self.unary()?)),
+            Tilde => Expr::Unary(UnOp::Not, Box::new(self.unary()?)),
+            Bang => Expr::Unary(UnOp::LogicalNot, Box::new(self.unary()?)),
+            Number(num) => Expr::Number(num),
+            Dot => Expr::Location,
+            LParen => {
+                let expr = self.expr(0)?;
+                self.expect(RParen)?;
+                expr
+            }
+            Ident(name) if self.tokens.get(self.pos) == Some(&LParen) => {
+                self.pos += 1;
+                let expr = match name {
+                    "ALIGN" => {
+                        let first = self.expr(0)?;
+                        if self.tokens.get(self.pos) == Some(&Comma) {
+                            self.pos += 1;
+                            Expr::Align(Some(Box::new(first)), Box::new(self.expr(0)?))
+                        } else {
+                            Expr::Align(None, Box::new(first))
+                        }
+                    }
+                    "SIZEOF" | "ADDR" => match self.next()? {
+                        Ident(sect) if name == "SIZEOF" => Expr::SizeOf(sect.to_string()),
+                        Ident(sect) => Expr::Addr(sect.to_string()),
+                        _ => return None,
+                    },
+                    _ => return None,
+                };
+                self.expect(RParen)?;
+                expr
+            }
+            Ident(name) => Expr::Symbol(name.to_string()),
+            _ => return None,
+        })
+    }
+}
+
+/// Reads the rest of a line into a region, `name start size attributes...`.
+fn read_region<'a>(lexer: &mut Lexer<'a, Token<'a>>) -> Result<Region, (usize, String)> {
+    let name = match lexer.next() {
+        Some(Ident(name)) => name.to_string(),
+        _ => return Err((lexer.extras, "expected region name".to_string())),
+    };
+    let (start, size) = match (lexer.next(), lexer.next()) {
+        (Some(Number(start)), Some(Number(size))) => (start, size),
+        _ => {
+            return Err((
+                lexer.extras,
+                format!("expected start and size of region {}", name),
+            ))
+        }
+    };
+    if start >= MAX_SIZE || size > MAX_SIZE - start {
+        return Err((
+            lexer.extras,
+            format!("region {} is outside of the address space", name),
+        ));
+    }
+
+    let mut region = Region {
+        name,
+        start,
+        size,
+        memory: Memory::Rom,
+        zero_page: false,
+        fill: None,
+        bank: None,
+    };
+    loop {
+        match lexer.next() {
+            Some(Ident("rom")) => region.memory = Memory::Rom,
+            Some(Ident("ram")) => region.memory = Memory::Ram,
+            Some(Ident("zp")) => region.zero_page = true,
+            Some(Ident("fill")) => match (lexer.next(), lexer.next()) {
+                (Some(Equals), Some(Number(fill))) if fill < 0x100 => {
+                    region.fill = Some(fill as u8)
+                }
+                _ => return Err((lexer.extras, "expected fill=<byte>".to_string())),
+            },
+            Some(Ident("bank")) => match (lexer.next(), lexer.next()) {
+                (Some(Equals), Some(Number(bank))) if bank < 0x100 => region.bank = Some(bank),
+                _ => return Err((lexer.extras, "expected bank=<byte>".to_string())),
+            },
+            Some(Eol) | None => break,
+            _ => {
+                return Err((
+                    lexer.extras,
+                    format!("unknown region attribute {}", lexer.slice()),
+                ))
+            }
+        }
+    }
+
+    if region.zero_page && region.end() > 0x100 {
+        return Err((
+            lexer.extras,
+            format!("region {} is not inside of the zero page", region.name),
+        ));
+    }
+    Ok(region)
 }
 
 /// Reads a line into a relocation group.
@@ -96,31 +683,30 @@ fn read_group<'a>(
     lexer: &mut Lexer<'a, Token<'a>>,
     start: Token<'a>,
     names: &mut HashSet<&'a str>,
+    layout: &mut Layout,
 ) -> Result<RelocGroup, (usize, String)> {
     let mut group = RelocGroup {
         relocations: Vec::with_capacity(2),
-        address: lexer.extras.0,
+        address: None,
         max_size: None,
+        region: None,
+        load: None,
+        overlay: false,
+        keep: false,
     };
 
     // check first token of the line
     match start {
         // line begins with explicit address
-        Number(addr) => group.address = addr,
-        // begins with first section
-        Ident(first) => {
-            // ensure a section isn't listed more than once
-            if !names.insert(first) {
-                return Err((
-                    lexer.extras.1,
-                    format!(
-                        "section {} listed multiple times in the linker script",
-                        first,
-                    ),
-                ));
-            }
-            group.relocations.push(first.to_string());
+        Number(addr) if addr < MAX_SIZE => group.address = Some(addr),
+        Number(addr) => {
+            return Err((
+                lexer.extras,
+                format!("address {:#x} is outside of the address space", addr),
+            ))
         }
+        // begins with first section
+        Ident(first) => add_section(&mut group, names, first).map_err(|m| (lexer.extras, m))?,
         // this function is not called for other variants
         _ => unsafe { std::hint::unreachable_unchecked() },
     };
@@ -129,42 +715,287 @@ fn read_group<'a>(
     loop {
         match lexer.next() {
             // another section
-            Some(Ident(sect)) => {
-                if !names.insert(sect) {
-                    return Err((
-                        lexer.extras.1,
-                        format!(
-                            "section {} listed multiple times in the linker script",
-                            sect
-                        ),
-                    ));
-                }
-                group.relocations.push(sect.to_string());
+            Some(Ident(sect)) if group.max_size.is_none() => {
+                add_section(&mut group, names, sect).map_err(|m| (lexer.extras, m))?
+            }
+            // options of the last section
+            Some(LParen) if group.max_size.is_none() && !group.relocations.is_empty() => {
+                let name = group.relocations.last().unwrap();
+                read_options(lexer, layout, name, None)?
             }
             // max size given
-            Some(Number(size)) => {
+            Some(Number(size)) if group.max_size.is_none() && size <= MAX_SIZE => {
                 group.max_size = Some(size);
-                // expect Eol next
-                match lexer.next() {
-                    Some(Eol) | None => break Ok(group),
-                    _ => {
-                        return Err((
-                            lexer.extras.1,
-                            format!(
-                                "expected end of line after group size, found {}",
-                                lexer.slice()
-                            ),
-                        ));
-                    }
-                }
             }
-            Some(Error) => {
+            // where the group goes, expect Eol after it
+            Some(tok @ Greater) | Some(tok @ At) => break read_targets(lexer, group, tok),
+            Some(Eol) | None => break Ok(group),
+            Some(Number(size)) if group.max_size.is_none() => {
                 return Err((
-                    lexer.extras.1,
+                    lexer.extras,
+                    format!("group size {:#x} is larger than the address space", size),
+                ));
+            }
+            Some(_) if group.max_size.is_some() => {
+                return Err((
+                    lexer.extras,
+                    format!(
+                        "expected region, load address or end of line after group size, found {}",
+                        lexer.slice()
+                    ),
+                ));
+            }
+            _ => {
+                return Err((
+                    lexer.extras,
                     format!("unrecognized token {}", lexer.slice()),
                 ))
             }
-            Some(Eol) | None => break Ok(group),
         }
     }
 }
+
+/// Reads the rest of a line into the handlers of the vector table,
+/// `[nmi=sym] [reset=sym] [irq=sym]`.
+fn read_vectors<'a>(lexer: &mut Lexer<'a, Token<'a>>) -> Result<Vectors, (usize, String)> {
+    let mut vectors = Vectors::default();
+    loop {
+        let vector = match lexer.next() {
+            Some(Ident("nmi")) => &mut vectors.nmi,
+            Some(Ident("reset")) => &mut vectors.reset,
+            Some(Ident("irq")) => &mut vectors.irq,
+            Some(Eol) | None => break Ok(vectors),
+            _ => return Err((lexer.extras, format!("unknown vector {}", lexer.slice()))),
+        };
+        match (lexer.next(), lexer.next()) {
+            (Some(Equals), Some(Ident(name))) if vector.is_none() => {
+                *vector = Some(name.to_string())
+            }
+            _ => {
+                return Err((
+                    lexer.extras,
+                    "expected each vector once as <vector>=<symbol>".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// Reads the rest of a line into iNES settings,
+/// `mapper=N submapper=N prg=N chr=N mirroring=horizontal|vertical|four battery nes2`.
+fn read_ines<'a>(lexer: &mut Lexer<'a, Token<'a>>, ines: &mut Ines) -> Result<(), (usize, String)> {
+    read_settings(lexer, |setting, value| {
+        match (setting, value) {
+            ("mapper", Some(Number(mapper))) if mapper <= 0xFFF => ines.mapper = mapper,
+            ("submapper", Some(Number(sub))) if sub <= 0xF => ines.submapper = sub,
+            ("prg", Some(Number(banks))) => ines.prg = Some(banks),
+            ("chr", Some(Number(banks))) => ines.chr = Some(banks),
+            ("mirroring", Some(Ident("horizontal"))) => ines.mirroring = Mirroring::Horizontal,
+            ("mirroring", Some(Ident("vertical"))) => ines.mirroring = Mirroring::Vertical,
+            ("mirroring", Some(Ident("four"))) => ines.mirroring = Mirroring::FourScreen,
+            ("battery", None) => ines.battery = true,
+            ("nes2", None) => ines.nes2 = true,
+            _ => return Err(format!("invalid iNES setting {}", setting)),
+        }
+        Ok(())
+    })
+}
+
+/// Reads the rest of a line into C64 cartridge settings,
+/// `type=N exrom=0|1 game=0|1 name=NAME`.
+fn read_crt<'a>(lexer: &mut Lexer<'a, Token<'a>>, crt: &mut Crt) -> Result<(), (usize, String)> {
+    read_settings(lexer, |setting, value| {
+        match (setting, value) {
+            ("type", Some(Number(hardware))) if hardware <= 0xFFFF => crt.hardware = hardware,
+            ("exrom", Some(Number(line))) if line <= 1 => crt.exrom = line as u8,
+            ("game", Some(Number(line))) if line <= 1 => crt.game = Some(line as u8),
+            ("name", Some(Ident(name))) if name.len() <= 32 => crt.name = name.to_string(),
+            _ => return Err(format!("invalid cartridge setting {}", setting)),
+        }
+        Ok(())
+    })
+}
+
+/// Reads the rest of a line as settings, each a word with an optional `=` and value.
+fn read_settings<'a>(
+    lexer: &mut Lexer<'a, Token<'a>>,
+    mut set: impl FnMut(&'a str, Option<Token<'a>>) -> Result<(), String>,
+) -> Result<(), (usize, String)> {
+    let mut tok = lexer.next();
+    loop {
+        let setting = match tok {
+            Some(Ident(setting)) => setting,
+            Some(Eol) | None => return Ok(()),
+            _ => return Err((lexer.extras, "expected a setting".to_string())),
+        };
+        tok = lexer.next();
+        let value = if tok == Some(Equals) {
+            let value = lexer.next();
+            tok = lexer.next();
+            value
+        } else {
+            None
+        };
+        set(setting, value).map_err(|message| (lexer.extras, message))?;
+    }
+}
+
+/// Reads the rest of a line into packed sections,
+/// `[first|best] sect[(align=N, page, with=sect)]... > REGION...`.
+fn read_pack<'a>(
+    lexer: &mut Lexer<'a, Token<'a>>,
+    names: &mut HashSet<&'a str>,
+    layout: &mut Layout,
+) -> Result<PackGroup, (usize, String)> {
+    let mut pack = PackGroup {
+        sections: Vec::with_capacity(4),
+        regions: Vec::with_capacity(4),
+        fit: Fit::First,
+    };
+    let mut tok = lexer.next();
+    match tok {
+        Some(Ident("first")) => tok = lexer.next(),
+        Some(Ident("best")) => {
+            pack.fit = Fit::Best;
+            tok = lexer.next();
+        }
+        _ => (),
+    }
+
+    // sections and their options up to the regions
+    loop {
+        match (tok, pack.sections.last_mut()) {
+            (Some(Ident(name)), _) => {
+                check_section(names, name).map_err(|m| (lexer.extras, m))?;
+                pack.sections.push(Packed {
+                    name: name.to_string(),
+                    with: None,
+                });
+            }
+            (Some(LParen), Some(packed)) => {
+                let name = packed.name.clone();
+                read_options(lexer, layout, &name, Some(&mut packed.with))?
+            }
+            (Some(Greater), Some(_)) => break,
+            _ => {
+                return Err((
+                    lexer.extras,
+                    "expected a section or `>` and the regions to pack into".to_string(),
+                ))
+            }
+        }
+        tok = lexer.next();
+    }
+
+    loop {
+        match lexer.next() {
+            Some(Ident(region)) => pack.regions.push(region.to_string()),
+            Some(Eol) | None if !pack.regions.is_empty() => break Ok(pack),
+            _ => break Err((lexer.extras, "expected region name".to_string())),
+        }
+    }
+}
+
+/// Reads the options of a section after its `(`, `align=N` and `page`, along with
+/// `with=sect` if it's packed.
+fn read_options<'a>(
+    lexer: &mut Lexer<'a, Token<'a>>,
+    layout: &mut Layout,
+    name: &str,
+    mut with: Option<&mut Option<String>>,
+) -> Result<(), (usize, String)> {
+    loop {
+        match lexer.next() {
+            Some(Ident("align")) => match (lexer.next(), lexer.next()) {
+                (Some(Equals), Some(Number(align))) if align != 0 && align <= MAX_SIZE => {
+                    layout.align.insert(name.to_string(), align);
+                }
+                _ => return Err((lexer.extras, "expected align=<size>".to_string())),
+            },
+            Some(Ident("page")) => layout.pages.push(name.to_string()),
+            Some(Ident("with")) if with.is_some() => match (lexer.next(), lexer.next()) {
+                (Some(Equals), Some(Ident(sect))) => {
+                    *with.as_deref_mut().unwrap() = Some(sect.to_string())
+                }
+                _ => return Err((lexer.extras, "expected with=<section>".to_string())),
+            },
+            _ => {
+                return Err((
+                    lexer.extras,
+                    format!("unknown option {} for section {}", lexer.slice(), name),
+                ))
+            }
+        }
+        match lexer.next() {
+            Some(Comma) => (),
+            Some(RParen) => return Ok(()),
+            _ => return Err((lexer.extras, "expected `,` or `)`".to_string())),
+        }
+    }
+}
+
+/// Reads the region and load address at the end of a group's line, `> REGION` then
+/// `@ REGION` or `@ address`.
+fn read_targets<'a>(
+    lexer: &mut Lexer<'a, Token<'a>>,
+    mut group: RelocGroup,
+    first: Token<'a>,
+) -> Result<RelocGroup, (usize, String)> {
+    let mut tok = Some(first);
+    if tok == Some(Greater) {
+        match lexer.next() {
+            Some(Ident(region)) => group.region = Some(region.to_string()),
+            _ => return Err((lexer.extras, "expected region name after >".to_string())),
+        }
+        tok = lexer.next();
+    }
+    if tok == Some(At) {
+        group.load = match lexer.next() {
+            Some(Ident(region)) => Some(Load::Region(region.to_string())),
+            Some(Number(address)) if address < MAX_SIZE => Some(Load::Address(address)),
+            _ => {
+                return Err((
+                    lexer.extras,
+                    "expected region name or address after @".to_string(),
+                ))
+            }
+        };
+        tok = lexer.next();
+    }
+    match tok {
+        Some(Eol) | None => Ok(group),
+        _ => Err((
+            lexer.extras,
+            format!("expected end of line, found {}", lexer.slice()),
+        )),
+    }
+}
+
+/// Adds a section to a group if it's a valid name that isn't listed anywhere else.
+fn add_section<'a>(
+    group: &mut RelocGroup,
+    names: &mut HashSet<&'a str>,
+    sect: &'a str,
+) -> Result<(), String> {
+    check_section(names, sect)?;
+    group.relocations.push(sect.to_string());
+    Ok(())
+}
+
+/// Make sure a section name is valid and hasn't been listed before.
+fn check_section<'a>(names: &mut HashSet<&'a str>, sect: &'a str) -> Result<(), String> {
+    if sect.len() > 31 {
+        return Err(format!(
+            "section name {} is longer than 31 characters",
+            sect
+        ));
+    }
+    // ensure a section isn't listed more than once
+    if !names.insert(sect) {
+        return Err(format!(
+            "section {} listed multiple times in the linker script",
+            sect
+        ));
+    }
+    Ok(())
+}
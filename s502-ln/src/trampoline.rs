@@ -0,0 +1,296 @@
+//! Trampolines for calls into another bank.
+//!
+//! A `jsr` from a bank to a label in another bank that may not be switched in is
+//! redirected to a trampoline, which switches the label's bank in, calls it and
+//! switches the caller's bank back. How banks are switched is different for every
+//! mapper, so each trampoline is a copy of a template section named in the linker
+//! script with `trampoline`. The template starts at the trampoline's entry, and these
+//! symbols are filled in for each copy when it refers to them:
+//! ```text
+//! __tramp_target  the label being called
+//! __tramp_bank    the label's bank
+//! __tramp_return  the caller's bank
+//! ```
+//! For example, for a mapper that switches banks by writing the number to `$8000`:
+//! ```text
+//! sct far_call
+//! !!far_call lda #__tramp_bank>
+//!  sta $8000
+//!  jsr __tramp_target
+//!  lda #__tramp_return>
+//!  sta $8000
+//!  rts
+//! ```
+//! There is one trampoline for each label and caller's bank, with the global label
+//! `__trampoline_<label>_<caller's bank>`. They're all put into the `__trampolines`
+//! section of the linker's own object, which must be placed by the layout somewhere
+//! that's always switched in. The template's own labels are renamed for each copy to
+//! start with the trampoline's label, like `__trampoline_print_1.far_call`.
+//!
+//! Code in a bank that's never switched out, or in no bank, doesn't know which bank to
+//! switch back to after the call. Calls from it use the second template given to
+//! `trampoline` instead, which has to keep track of the bank switched in itself, such
+//! as in a variable it saves and restores. Only `__tramp_target` and `__tramp_bank`
+//! are filled in for it, and its copies are named `__trampoline_<label>`:
+//! ```text
+//! trampoline far_call fixed_call
+//! ```
+//! Without the second template these calls are an error. Packed sections aren't known
+//! to be in a bank until they're placed, so calls from or to them don't get a
+//! trampoline.
+
+use std::collections::{HashMap, HashSet};
+
+use super::error::Error;
+use super::formats::*;
+use super::linker::{find_label, linker_object, LINKER_OBJECT};
+
+/// The name of the section the trampolines are put in.
+pub const TRAMPOLINES: &str = "__trampolines";
+
+/// The symbols filled in for each trampoline.
+const TARGET: &str = "__tramp_target";
+const BANK: &str = "__tramp_bank";
+const RETURN: &str = "__tramp_return";
+
+/// The opcode of `jsr`.
+const JSR: u8 = 0x20;
+
+/// One copy of a template.
+struct Trampoline {
+    target: String,
+    bank: usize,
+    /// The caller's bank, or `None` for code that's always switched in.
+    caller: Option<usize>,
+    /// The copy's references to the symbols to fill in.
+    fills: Vec<Reference>,
+}
+
+/// The trampolines made for a link.
+pub struct Trampolines {
+    list: Vec<Trampoline>,
+}
+
+impl Trampolines {
+    /// Take the templates out of the objects and redirect every call into another
+    /// bank to a new trampoline, all in the linker's object. `switched` are the banks
+    /// that may be switched out.
+    pub fn new(
+        layout: &Layout,
+        objects: &mut Vec<Object>,
+        symbols: &HashMap<String, usize>,
+        switched: &HashSet<usize>,
+    ) -> Result<Option<Self>, Vec<Error>> {
+        let name = match &layout.trampoline {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let mut take = |name: &String| {
+            objects
+                .iter_mut()
+                .find_map(|obj| obj.sections.remove(name))
+                .ok_or_else(|| vec![Error::NoTemplate(name.clone())])
+        };
+        let template = take(name)?;
+        let fixed_template = layout.fixed_trampoline.as_ref().map(take).transpose()?;
+
+        // the bank of every section in a group, which is known before placing
+        let regions = layout
+            .regions
+            .iter()
+            .map(|r| (r.name.as_str(), r.bank))
+            .collect::<HashMap<_, _>>();
+        let sect_banks = layout
+            .groups()
+            .map(|g| {
+                let bank = g.region.as_deref().and_then(|r| *regions.get(r)?);
+                (g.relocations.iter(), bank)
+            })
+            .flat_map(|(names, bank)| names.map(move |name| (name.as_str(), bank)))
+            .collect::<HashMap<_, _>>();
+        let mut banks = symbols
+            .iter()
+            .filter(|&(_, &value)| value > 0xFFFF)
+            .map(|(name, &value)| (name.as_str(), value >> 16))
+            .collect::<HashMap<_, _>>();
+        for (sect_name, sect) in objects.iter().flat_map(|obj| &obj.sections) {
+            for (name, lab) in &sect.labels {
+                if let (Visibility::Global, Some(&Some(bank))) =
+                    (lab.vis, sect_banks.get(sect_name.as_str()))
+                {
+                    banks.insert(name, bank);
+                }
+            }
+        }
+
+        // find every call into another bank
+        let mut errors = Vec::new();
+        let mut calls = Vec::new();
+        for (idx, obj) in objects.iter().enumerate() {
+            for (sect_name, sect) in &obj.sections {
+                let caller = match sect_banks.get(sect_name.as_str()) {
+                    Some(&Some(bank)) if switched.contains(&bank) => Some(bank),
+                    Some(_) => None,
+                    None => continue,
+                };
+                for (ref_idx, rf) in sect.references.iter().enumerate() {
+                    let call = !rf.branch
+                        && rf.which_byte == ByteSelect::Both
+                        && rf.offset > 0
+                        && sect.code[rf.offset - 1] == JSR;
+                    if !call
+                        || layout.far.contains(&rf.referred)
+                        || find_label(obj, sect_name, rf)
+                            .is_some_and(|(_, lab)| lab.vis != Visibility::Global)
+                    {
+                        continue;
+                    }
+                    match banks.get(rf.referred.as_str()) {
+                        Some(&bank) if Some(bank) != caller && switched.contains(&bank) => {
+                            if caller.is_none() && fixed_template.is_none() {
+                                errors.push(Error::FixedCall {
+                                    symbol: rf.referred.clone(),
+                                    object: obj.name.clone(),
+                                    section: sect_name.clone(),
+                                    offset: rf.offset,
+                                    bank,
+                                });
+                            } else {
+                                calls.push((idx, sect_name.clone(), ref_idx, bank, caller));
+                            }
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut trampolines = Trampolines { list: Vec::new() };
+        let mut sect = Section {
+            code: Vec::new(),
+            labels: HashMap::new(),
+            references: Vec::new(),
+            base: 0,
+            load: 0,
+            bank: None,
+            size: 0,
+            align: 1,
+            blocks: Vec::new(),
+        };
+        for (idx, sect_name, ref_idx, bank, caller) in calls {
+            let rf = &mut objects[idx]
+                .sections
+                .get_mut(&sect_name)
+                .unwrap()
+                .references[ref_idx];
+            let label = match caller {
+                Some(caller) => format!("__trampoline_{}_{}", rf.referred, caller),
+                None => format!("__trampoline_{}", rf.referred),
+            };
+            let target = std::mem::replace(&mut rf.referred, label.clone());
+            if trampolines
+                .list
+                .iter()
+                .any(|t| t.target == target && t.caller == caller)
+            {
+                continue;
+            }
+
+            // copy the template after the others, keeping its labels to itself
+            let (template, filled) = match caller {
+                Some(_) => (&template, &[TARGET, BANK, RETURN][..]),
+                None => (fixed_template.as_ref().unwrap(), &[TARGET, BANK][..]),
+            };
+            let offset = sect.size.div_ceil(template.align) * template.align;
+            let rename = |name: &String| match template.labels.contains_key(name) {
+                true => format!("{}.{}", label, name),
+                false => name.clone(),
+            };
+            sect.code.resize(offset, 0);
+            sect.code.extend_from_slice(&template.code);
+            sect.size = offset + template.size;
+            sect.align = num::integer::lcm(sect.align, template.align);
+            for (name, lab) in &template.labels {
+                let vis = match lab.vis {
+                    Visibility::Global => Visibility::Object,
+                    vis => vis,
+                };
+                sect.labels.insert(
+                    rename(name),
+                    Label {
+                        vis,
+                        offset: offset + lab.offset,
+                    },
+                );
+            }
+            sect.labels.insert(
+                label.clone(),
+                Label {
+                    vis: Visibility::Global,
+                    offset,
+                },
+            );
+            let mut fills = Vec::new();
+            for rf in &template.references {
+                let mut rf = rf.clone();
+                rf.offset += offset;
+                if filled.contains(&rf.referred.as_str()) {
+                    fills.push(rf);
+                } else {
+                    rf.referred = rename(&rf.referred);
+                    sect.references.push(rf);
+                }
+            }
+            sect.blocks
+                .extend(template.blocks.iter().map(|block| Block {
+                    label: rename(&block.label),
+                    offset: offset + block.offset,
+                    size: block.size,
+                }));
+            trampolines.list.push(Trampoline {
+                target,
+                bank,
+                caller,
+                fills,
+            });
+        }
+        if !trampolines.list.is_empty() {
+            linker_object(objects)
+                .sections
+                .insert(TRAMPOLINES.to_string(), sect);
+        }
+        Ok(Some(trampolines))
+    }
+
+    /// Fill in the symbols of every trampoline once everything has been placed.
+    pub fn write(&self, objects: &mut [Object], symbols: &HashMap<String, usize>) {
+        let sect = match objects
+            .iter_mut()
+            .find(|obj| obj.name == LINKER_OBJECT)
+            .and_then(|obj| obj.sections.get_mut(TRAMPOLINES))
+        {
+            Some(sect) => sect,
+            None => return,
+        };
+        for tramp in &self.list {
+            for rf in &tramp.fills {
+                let value = match rf.referred.as_str() {
+                    TARGET => symbols[&tramp.target] & 0xFFFF,
+                    BANK => tramp.bank,
+                    _ => tramp.caller.unwrap(),
+                };
+                match rf.which_byte {
+                    ByteSelect::Both => {
+                        sect.code[rf.offset] = value as u8;
+                        sect.code[rf.offset + 1] = (value >> 8) as u8;
+                    }
+                    ByteSelect::High => sect.code[rf.offset] = (value >> 8) as u8,
+                    ByteSelect::Low => sect.code[rf.offset] = value as u8,
+                }
+            }
+        }
+    }
+}
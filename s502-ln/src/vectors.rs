@@ -0,0 +1,121 @@
+//! The interrupt vector table.
+//!
+//! The 6502 finds its handlers at the top of the address space, each as a little
+//! endian word:
+//! ```text
+//! 0xFFFA  NMI
+//! 0xFFFC  RESET
+//! 0xFFFE  IRQ and BRK
+//! ```
+//! When the layout asks for the table with `vectors`, or `--nmi` or `--irq` is given,
+//! the linker makes a `__vectors` section that refers to the three handlers like any
+//! other reference. The reset handler is the entry unless it's given separately. The
+//! section is placed at `0xFFFA` in the region holding that address unless the layout
+//! places it itself, and garbage collection always keeps the handlers.
+
+use std::collections::HashMap;
+
+use super::error::Error;
+use super::formats::*;
+
+/// The name of the section holding the table.
+pub const VECTORS: &str = "__vectors";
+
+/// Where the table goes.
+const ADDRESS: usize = 0xFFFA;
+
+/// The handlers in the order they're in the table.
+pub struct VectorTable {
+    handlers: Vec<(&'static str, String)>,
+}
+
+impl VectorTable {
+    /// The table the layout asks for, if it asks for one. Every handler must be given.
+    pub fn new(layout: &Layout) -> Result<Option<Self>, Error> {
+        let vectors = match &layout.vectors {
+            Some(vectors) => vectors,
+            None => return Ok(None),
+        };
+        let reset = vectors.reset.as_ref().or(layout.entry.as_ref());
+        let mut handlers = Vec::with_capacity(3);
+        for (vector, handler) in [
+            ("nmi", vectors.nmi.as_ref()),
+            ("reset", reset),
+            ("irq", vectors.irq.as_ref()),
+        ] {
+            match handler {
+                Some(handler) => handlers.push((vector, handler.clone())),
+                None => return Err(Error::MissingVector(vector.to_string())),
+            }
+        }
+        Ok(Some(VectorTable { handlers }))
+    }
+
+    /// The symbol of every handler.
+    pub fn handlers(&self) -> impl Iterator<Item = &String> {
+        self.handlers.iter().map(|(_, handler)| handler)
+    }
+
+    /// The section holding the table, with a reference to each handler.
+    pub fn section(&self) -> Section {
+        let references = self
+            .handlers()
+            .enumerate()
+            .map(|(idx, handler)| Reference {
+                referred: handler.clone(),
+                offset: idx * 2,
+                which_byte: ByteSelect::Both,
+                branch: false,
+            })
+            .collect();
+        Section {
+            code: vec![0; 6],
+            labels: HashMap::new(),
+            references,
+            base: 0,
+            load: 0,
+            bank: None,
+            size: 6,
+            align: 1,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// A group placing the table at its address, unless the layout already places it.
+    pub fn group(&self, layout: &Layout) -> Option<RelocGroup> {
+        if layout
+            .groups()
+            .any(|g| g.relocations.iter().any(|s| s == VECTORS))
+            || layout
+                .packs()
+                .any(|p| p.sections.iter().any(|s| s.name == VECTORS))
+        {
+            return None;
+        }
+        let region = layout
+            .regions
+            .iter()
+            .find(|r| r.start <= ADDRESS && ADDRESS < r.end());
+        Some(RelocGroup {
+            relocations: vec![VECTORS.to_string()],
+            address: Some(ADDRESS),
+            max_size: None,
+            region: region.map(|r| r.name.clone()),
+            load: None,
+            overlay: false,
+            keep: true,
+        })
+    }
+
+    /// Make sure every handler is a global symbol.
+    pub fn check(&self, symbols: &HashMap<String, usize>) -> Vec<Error> {
+        self.handlers
+            .iter()
+            .filter(|(_, handler)| !symbols.contains_key(handler))
+            .map(|(vector, handler)| Error::NotGlobal {
+                what: format!("{} vector", vector),
+                symbol: handler.clone(),
+            })
+            .collect()
+    }
+}
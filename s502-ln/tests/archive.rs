@@ -0,0 +1,119 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{link_with, linker, object};
+use s502_ln::archive::{extract, read_archive, write_archive, Archive, Member, MAGIC};
+
+fn member(name: &str, source: &str) -> Member {
+    let obj = s502_as::assemble(source, &s502_as::Options::default()).unwrap();
+    Member {
+        name: name.to_string(),
+        data: obj.to_bytes(),
+    }
+}
+
+fn library() -> Archive {
+    Archive::new(vec![
+        member("putchar.65o", "sct text\n!!putchar sta $2007\n rts\n"),
+        member(
+            "getchar.65o",
+            "sct text\n!!getchar lda $2007\n jsr putchar\n rts\n",
+        ),
+        member("unused.65o", "sct text\n!!unused rts\n"),
+    ])
+    .unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let mut bytes = Vec::new();
+    write_archive(&library(), &mut bytes).unwrap();
+    assert_eq!(bytes[..4], MAGIC);
+    assert_eq!(&MAGIC[..3], b"65r");
+    let archive = read_archive(&bytes[..]).unwrap();
+    let names = archive
+        .members
+        .iter()
+        .map(|m| m.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["putchar.65o", "getchar.65o", "unused.65o"]);
+    assert_eq!(
+        archive.symbols,
+        [
+            ("putchar".to_string(), 0),
+            ("getchar".to_string(), 1),
+            ("unused".to_string(), 2)
+        ]
+    );
+}
+
+#[test]
+fn extracts_needed_members() {
+    let mut objects = vec![object("main.65o", "sct text\n!!main jsr getchar\n")];
+    let archives = [("lib.65r".to_string(), library())];
+    extract(&mut objects, &HashMap::new(), &archives, &[]).unwrap();
+    let names = objects.iter().map(|o| o.name.as_str()).collect::<Vec<_>>();
+    // getchar needs putchar in turn
+    assert_eq!(
+        names,
+        ["main.65o", "lib.65r(getchar.65o)", "lib.65r(putchar.65o)"]
+    );
+}
+
+#[test]
+fn symbols_from_tables_are_defined() {
+    let mut objects = vec![object("main.65o", "sct text\n!!main jsr getchar\n")];
+    let symbols = vec![("getchar".to_string(), 0xe000)].into_iter().collect();
+    let archives = [("lib.65r".to_string(), library())];
+    extract(&mut objects, &symbols, &archives, &[]).unwrap();
+    assert_eq!(objects.len(), 1);
+}
+
+#[test]
+fn wrapped_symbols_take_the_wrapper() {
+    let mut library = library();
+    library.members.push(member(
+        "wrap.65o",
+        "sct text\n!!__wrap_putchar lda #$00\n jsr __real_putchar\n rts\n",
+    ));
+    let library = Archive::new(library.members).unwrap();
+    let mut linker = linker(
+        "0x8000 text\n",
+        &[("main.65o", "sct text\n!!main jsr putchar\n rts\n")],
+    );
+    linker.archive("lib.65r".to_string(), library);
+    linker.wrap("putchar".to_string());
+    let image = link_with(linker);
+    let names = image
+        .objects
+        .iter()
+        .map(|o| o.name.as_str())
+        .collect::<Vec<_>>();
+    // the wrapper calls the real putchar, which is taken after it
+    assert_eq!(
+        names,
+        ["main.65o", "lib.65r(wrap.65o)", "lib.65r(putchar.65o)"]
+    );
+    assert_eq!(
+        image.code,
+        [
+            0x20, 0x04, 0x80, 0x60, // main
+            0xa9, 0x00, 0x20, 0x0a, 0x80, 0x60, // __wrap_putchar
+            0x8d, 0x07, 0x20, 0x60, // putchar
+        ]
+    );
+}
+
+#[test]
+fn defined_symbols_take_nothing() {
+    let mut linker = linker(
+        "0x8000 text\n",
+        &[("main.65o", "sct text\n!!main jsr getchar\n")],
+    );
+    linker.archive("lib.65r".to_string(), library());
+    linker.define("getchar=0x9000").unwrap();
+    let image = link_with(linker);
+    assert_eq!(image.objects.len(), 1);
+    assert_eq!(image.code, [0x20, 0x00, 0x90]);
+}
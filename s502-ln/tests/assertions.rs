@@ -0,0 +1,103 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{errors_with, link, link_errors};
+use s502_ln::object::read_object;
+use s502_ln::script::read_script;
+use s502_ln::Linker;
+
+const LAYOUT: &str = "0x8000 text data\n";
+
+/// The errors from linking a source assembled from a file.
+fn file_errors(file: &str, source: &str) -> Vec<String> {
+    let options = s502_as::Options {
+        file: Some(file.to_string()),
+    };
+    let obj = s502_as::assemble(source, &options).unwrap_or_else(|e| panic!("{}", e[0]));
+    let obj = read_object("main.65o".to_string(), &obj.to_bytes()[..]).unwrap();
+    let layout = read_script(LAYOUT).unwrap();
+    errors_with(Linker::new(layout, vec![obj], HashMap::new()))
+}
+
+#[test]
+fn passing_assertions() {
+    link(
+        LAYOUT,
+        &[(
+            "main",
+            "sct text
+!!start nop
+ ast . == start + 1 && . < $c000, \"nop is one byte\"
+ rts
+sct data
+ ast table - start == 2, \"data follows text\"
+table dfb $01
+ ast SIZEOF(data) == 1 && ADDR(data) == table, \"one byte of data\"
+",
+        )],
+    );
+}
+
+#[test]
+fn failing_assertion() {
+    let errors = file_errors(
+        "main.65a",
+        "sct text\n!!start nop\n ast . == start, \"nothing before it\"\n",
+    );
+    assert_eq!(
+        errors,
+        ["assertion failed in main.65a on line 3: nothing before it"]
+    );
+    // without a file it's the object
+    let errors = link_errors(LAYOUT, &[("main", "sct text\n ast 0, \"never\"\n")]);
+    assert_eq!(errors, ["assertion failed in main on line 2: never"]);
+}
+
+#[test]
+fn invalid_expression() {
+    let errors = file_errors(
+        "main.65a",
+        "sct text\n!!start nop\n ast start = $8000, \"one = instead of two\"\n",
+    );
+    assert_eq!(
+        errors,
+        ["error evaluating assertion in main.65a on line 3: invalid expression"]
+    );
+}
+
+#[test]
+fn unknown_sections() {
+    let errors = link_errors(
+        LAYOUT,
+        &[(
+            "main",
+            "sct text\n ast SIZEOF(bss) == 0, \"no bss\"\n ast ADDR(bss) == 0, \"no bss\"\n",
+        )],
+    );
+    assert_eq!(
+        errors,
+        [
+            "error evaluating assertion in main on line 2: no object has a section bss",
+            "error evaluating assertion in main on line 3: section bss is not placed before it's used",
+        ]
+    );
+}
+
+#[test]
+fn script_uses_sections_before_they_are_placed() {
+    let source = "sct text\n nop\nsct data\n dfb $01\n";
+    let image = link(
+        "size = SIZEOF(data)\n0x8000 text data\n",
+        &[("main", source)],
+    );
+    assert_eq!(image.symbols["size"], 1);
+    let errors = link_errors(
+        "start = ADDR(data)\n0x8000 text data\n",
+        &[("main", source)],
+    );
+    assert_eq!(
+        errors,
+        ["error evaluating line 1 of the linker script: section data is not placed before it's used"]
+    );
+}
@@ -0,0 +1,91 @@
+mod common;
+
+use common::{link, link_errors};
+
+const BANKS: &str = "region BANK0 0x8000 0x4000 rom bank=0 fill=0xFF
+region BANK1 0x8000 0x4000 rom bank=1
+region FIXED 0xC000 0x4000 rom bank=2
+entry reset
+vectors nmi=handler irq=handler
+bank0 > BANK0
+bank1 > BANK1
+fixed > FIXED
+";
+
+const FIXED: &str = "sct fixed
+!!reset jmp reset
+!!handler rti
+";
+
+#[test]
+fn symbols_have_their_bank() {
+    let image = link(
+        BANKS,
+        &[
+            ("fixed", FIXED),
+            ("b0", "sct bank0\n!!b0code jsr handler\n rts\n"),
+            ("b1", "sct bank1\n!!b1code lda #$01\n rts\n"),
+        ],
+    );
+    assert_eq!(image.symbols["b0code"], 0x00_8000);
+    assert_eq!(image.symbols["b1code"], 0x01_8000);
+    assert_eq!(image.symbols["reset"], 0x02_c000);
+    // the output is every bank in full and in order
+    assert_eq!(image.base, 0);
+    assert_eq!(image.code.len(), 0xc000);
+    let numbers = image.banks.iter().map(|b| b.number).collect::<Vec<_>>();
+    assert_eq!(numbers, [0, 1, 2]);
+    assert_eq!(&image.code[..4], [0x20, 0x03, 0xc0, 0x60]);
+    assert_eq!(image.code[4], 0xff);
+    assert_eq!(&image.code[0x4000..0x4003], [0xa9, 0x01, 0x60]);
+    // only the low 16 bits of an address are in the code
+    assert_eq!(&image.code[0x8000..0x8003], [0x4c, 0x00, 0xc0]);
+    assert_eq!(&image.code[0xbffa..], [0x03, 0xc0, 0x00, 0xc0, 0x03, 0xc0]);
+}
+
+#[test]
+fn vector_symbols() {
+    let image = link(
+        BANKS,
+        &[("fixed", FIXED), ("b0", "sct bank0\n!!b0code rts\n")],
+    );
+    assert_eq!(image.symbols["__vectors_start"], 0x02_fffa);
+    assert_eq!(image.symbols["__vectors_size"], 6);
+    // one past the end wraps around inside of the bank
+    assert_eq!(image.symbols["__vectors_end"], 0x02_0000);
+    assert!(!image.symbols.contains_key("____vectors_start"));
+    assert_eq!(image.symbols["__fixed_end"], 0x02_c004);
+}
+
+#[test]
+fn switched_banks_need_a_trampoline() {
+    let errors = link_errors(
+        BANKS,
+        &[
+            ("fixed", FIXED),
+            ("b0", "sct bank0\n!!b0code jsr b1code\n rts\n"),
+            ("b1", "sct bank1\n!!b1code rts\n"),
+        ],
+    );
+    assert_eq!(
+        errors,
+        [
+            "reference to `b1code` in b0 section bank0 at offset 0x1 is to bank 1, \
+          which may not be switched in"
+        ]
+    );
+}
+
+#[test]
+fn far_symbols_may_be_referred_to() {
+    let layout = BANKS.replace("entry reset\n", "entry reset\nfar b1code\n");
+    let image = link(
+        &layout,
+        &[
+            ("fixed", FIXED),
+            ("b0", "sct bank0\n!!b0code jsr b1code\n rts\n"),
+            ("b1", "sct bank1\n!!b1code rts\n"),
+        ],
+    );
+    assert_eq!(&image.code[..3], [0x20, 0x00, 0x80]);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use common::{link, link_with, linker};
+use s502_ln::cart::{crt, ines};
+
+const CODE: &str = "sct text\n!!reset nop\n rts\n";
+
+const TILES: &str = "sct tiles\n dfb $aa\n dfb $bb\n";
+
+/// The big endian word at an offset.
+fn word(bytes: &[u8], offset: usize) -> usize {
+    (bytes[offset] as usize) << 8 | bytes[offset + 1] as usize
+}
+
+#[test]
+fn ines_header_and_padding() {
+    let image = link(
+        "0xe000 text\nchr tiles\nines mapper=0x12 mirroring=vertical battery\n",
+        &[("main", CODE), ("tiles", TILES)],
+    );
+    let bytes = ines(&image).unwrap();
+    assert_eq!(&bytes[..4], b"NES\x1a");
+    // one unit of each, mapper 0x12 split across the flags with vertical mirroring
+    // and a battery
+    assert_eq!(&bytes[4..11], [1, 1, 0x23, 0x10, 0, 0, 0]);
+    assert_eq!(bytes.len(), 16 + 0x4000 + 0x2000);
+    // the image ends the window at 0x10000, with zeros before and after it
+    assert!(bytes[16..(16 + 0x2000)].iter().all(|&b| b == 0));
+    assert_eq!(&bytes[(16 + 0x2000)..(16 + 0x2002)], [0xea, 0x60]);
+    assert!(bytes[(16 + 0x2002)..(16 + 0x4000)].iter().all(|&b| b == 0));
+    // the CHR ROM is padded to a whole unit
+    assert_eq!(&bytes[(16 + 0x4000)..(16 + 0x4002)], [0xaa, 0xbb]);
+    assert!(bytes[(16 + 0x4002)..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn ines_sizes_from_the_settings() {
+    let mut linker = linker(
+        "0xc000 text\nchr tiles\n",
+        &[("main", CODE), ("tiles", TILES)],
+    );
+    linker.ines("prg=2 chr=2").unwrap();
+    let bytes = ines(&link_with(linker)).unwrap();
+    assert_eq!(&bytes[4..6], [2, 2]);
+    assert_eq!(bytes.len(), 16 + 0x8000 + 0x4000);
+    assert_eq!(&bytes[(16 + 0x4000)..(16 + 0x4002)], [0xea, 0x60]);
+    assert_eq!(&bytes[(16 + 0x8000)..(16 + 0x8002)], [0xaa, 0xbb]);
+}
+
+#[test]
+fn nes2_header() {
+    let image = link(
+        "0xc000 text\nines nes2 mapper=0x123 submapper=5 mirroring=four battery\n",
+        &[("main", CODE)],
+    );
+    let bytes = ines(&image).unwrap();
+    // the low nibble of the mapper, four screens and a battery, then the middle
+    // nibble and NES 2.0, then the submapper and the high nibble
+    assert_eq!(&bytes[4..11], [1, 0, 0x3a, 0x28, 0x51, 0, 0x70]);
+    // 8K of CHR-RAM without CHR ROM
+    assert_eq!(bytes[11], 7);
+    assert_eq!(bytes.len(), 16 + 0x4000);
+}
+
+#[test]
+fn ines_needs_the_top_of_memory() {
+    let image = link("0x6000 text\n", &[("main", CODE)]);
+    let error = ines(&image).unwrap_err().to_string();
+    assert_eq!(
+        error,
+        "can't write iNES: the image is at 0x6000-0x6001, outside of 0x8000-0xffff"
+    );
+}
+
+#[test]
+fn ines_settings_need_nes2() {
+    let image = link("0xc000 text\nines mapper=0x123\n", &[("main", CODE)]);
+    let error = ines(&image).unwrap_err().to_string();
+    assert_eq!(
+        error,
+        "can't write iNES: mappers above 255 need a NES 2.0 header"
+    );
+}
+
+#[test]
+fn crt_chip_for_each_bank() {
+    let image = link(
+        "region BANK0 0x8000 0x2000 rom bank=0
+region BANK1 0x8000 0x2000 rom bank=1
+crt type=5 name=GAME
+bank0 > BANK0
+bank1 > BANK1
+",
+        &[
+            ("b0", "sct bank0\n!!first nop\n"),
+            ("b1", "sct bank1\n!!second rts\n"),
+        ],
+    );
+    let bytes = crt(&image).unwrap();
+    assert_eq!(&bytes[..16], b"C64 CARTRIDGE   ");
+    assert_eq!(&bytes[0x10..0x14], [0, 0, 0, 0x40]);
+    assert_eq!(word(&bytes, 0x14), 0x0100);
+    assert_eq!(word(&bytes, 0x16), 5);
+    // the banks end at 0xa000, so only ROML is used
+    assert_eq!(&bytes[0x18..0x1a], [0, 1]);
+    assert_eq!(&bytes[0x20..0x25], b"GAME\0");
+    assert_eq!(bytes.len(), 0x40 + 2 * (0x10 + 0x2000));
+
+    for (number, code) in [(0, 0xea), (1, 0x60)].iter() {
+        let chip = 0x40 + number * (0x10 + 0x2000);
+        assert_eq!(&bytes[chip..(chip + 4)], b"CHIP");
+        assert_eq!(&bytes[(chip + 4)..(chip + 8)], [0, 0, 0x20, 0x10]);
+        assert_eq!(word(&bytes, chip + 8), 0);
+        assert_eq!(word(&bytes, chip + 10), *number);
+        assert_eq!(word(&bytes, chip + 12), 0x8000);
+        assert_eq!(word(&bytes, chip + 14), 0x2000);
+        assert_eq!(bytes[chip + 16], *code);
+    }
+}
+
+#[test]
+fn crt_chip_for_the_image() {
+    let image = link("0xa000 text\n", &[("main", CODE)]);
+    let bytes = crt(&image).unwrap();
+    // code above 0xa000 needs ROMH
+    assert_eq!(&bytes[0x18..0x1a], [0, 0]);
+    assert_eq!(bytes.len(), 0x40 + 0x10 + 2);
+    assert_eq!(&bytes[0x44..0x48], [0, 0, 0, 0x12]);
+    assert_eq!(word(&bytes, 0x4a), 0);
+    assert_eq!(word(&bytes, 0x4c), 0xa000);
+    assert_eq!(word(&bytes, 0x4e), 2);
+    assert_eq!(&bytes[0x50..], [0xea, 0x60]);
+}
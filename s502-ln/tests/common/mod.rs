@@ -0,0 +1,54 @@
+//! Helpers for linking assembled sources in memory.
+
+// each test file only uses some of these
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use s502_ln::formats::Object;
+use s502_ln::object::read_object;
+use s502_ln::script::read_script;
+use s502_ln::{Image, Linker};
+
+/// Assemble a source into an object as the linker reads it.
+pub fn object(name: &str, source: &str) -> Object {
+    let obj = s502_as::assemble(source, &s502_as::Options::default())
+        .unwrap_or_else(|e| panic!("{}: {}", name, e[0]));
+    read_object(name.to_string(), &obj.to_bytes()[..]).unwrap()
+}
+
+/// A linker for objects assembled from `(name, source)` pairs.
+pub fn linker(script: &str, sources: &[(&str, &str)]) -> Linker {
+    let layout = read_script(script).unwrap_or_else(|e| panic!("{}", e));
+    let objects = sources
+        .iter()
+        .map(|(name, source)| object(name, source))
+        .collect();
+    Linker::new(layout, objects, HashMap::new())
+}
+
+/// Link sources, failing the test on any error.
+pub fn link(script: &str, sources: &[(&str, &str)]) -> Image {
+    link_with(linker(script, sources))
+}
+
+/// Finish a link, failing the test on any error.
+pub fn link_with(linker: Linker) -> Image {
+    linker.link().unwrap_or_else(|errors| {
+        let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+        panic!("{}", errors.join("\n"))
+    })
+}
+
+/// The errors from linking sources.
+pub fn link_errors(script: &str, sources: &[(&str, &str)]) -> Vec<String> {
+    errors_with(linker(script, sources))
+}
+
+/// The errors from finishing a link.
+pub fn errors_with(linker: Linker) -> Vec<String> {
+    match linker.link() {
+        Ok(_) => panic!("the link succeeded"),
+        Err(errors) => errors.iter().map(|e| e.to_string()).collect(),
+    }
+}
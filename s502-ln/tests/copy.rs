@@ -0,0 +1,59 @@
+mod common;
+
+use common::link;
+
+const LAYOUT: &str = "region ROM 0x8000 0x1000 rom
+region RAM 0x0200 0x100 ram
+text __copy_table > ROM
+idata > RAM @ ROM
+bss > RAM
+";
+
+const STARTUP: &str = "sct text
+!!start ldx __copy_table
+ lda counter
+ rts
+sct idata
+!counter dfb $01
+ dfb $02
+ dfb $03
+sct bss
+ dfw $0000
+";
+
+fn words(bytes: &[u8]) -> Vec<usize> {
+    bytes
+        .chunks(2)
+        .map(|w| w[0] as usize | (w[1] as usize) << 8)
+        .collect()
+}
+
+#[test]
+fn copies_loaded_sections_and_zeros_ram() {
+    let image = link(LAYOUT, &[("startup", STARTUP)]);
+    let table = image.symbols["__copy_table"];
+    assert_eq!(table, 0x8007);
+    // the copy entry, its terminator, the zero entry and its terminator
+    let table = words(&image.code[7..(7 + 6 + 6 + 4 + 4)]);
+    assert_eq!(
+        table,
+        [0x801b, 0x0200, 3, 0, 0, 0, 0x0203, 2, 0, 0],
+        "{:x?}",
+        table
+    );
+    // the data is in the output where it's loaded from but labels are where it runs
+    assert_eq!(&image.code[0x1b..], [0x01, 0x02, 0x03]);
+    assert_eq!(&image.code[..6], [0xae, 0x07, 0x80, 0xad, 0x00, 0x02]);
+    assert_eq!(image.symbols["__idata_load"], 0x801b);
+    assert_eq!(image.symbols["__idata_start"], 0x0200);
+}
+
+#[test]
+fn no_table_unless_placed() {
+    let layout = "region ROM 0x8000 0x1000 rom\nregion RAM 0x0200 0x100 ram\n\
+                  text > ROM\nidata > RAM @ ROM\nbss > RAM\n";
+    let startup = STARTUP.replace("ldx __copy_table", "ldx #$00");
+    let image = link(layout, &[("startup", &startup)]);
+    assert!(!image.symbols.contains_key("__copy_table"));
+    assert_eq!(image.code.len(), 6 + 3);
+}
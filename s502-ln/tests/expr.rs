@@ -0,0 +1,94 @@
+mod common;
+
+use common::{link, link_errors};
+
+const SOURCE: &str = "sct text\n!!start nop\n nop\n nop\nsct data\n!!table dfb $01\n";
+
+#[test]
+fn precedence() {
+    let image = link(
+        "a = 2 + 3 * 4
+b = 1 << 2 + 1
+c = 6 & 3 == 2
+d = -2 + 5
+e = (2 + 3) * 4
+f = 10 - 4 - 3
+g = 1 || 0 && 0
+h = ~0xff & 0x1ff
+i = !3 + 1 < 2 * 2
+0x8000 text data
+",
+        &[("main", SOURCE)],
+    );
+    let values = ["a", "b", "c", "d", "e", "f", "g", "h", "i"]
+        .iter()
+        .map(|name| image.symbols[*name])
+        .collect::<Vec<_>>();
+    assert_eq!(values, [14, 8, 0, 3, 20, 3, 1, 0x100, 1]);
+}
+
+#[test]
+fn location_between_groups() {
+    let image = link(
+        "0x8000 text
+text_end = .
+. = . + 0x10
+data
+data_end = .
+",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(image.symbols["text_end"], 0x8003);
+    assert_eq!(image.symbols["table"], 0x8013);
+    assert_eq!(image.symbols["data_end"], 0x8014);
+}
+
+#[test]
+fn align() {
+    let image = link(
+        "0x8000 text
+. = ALIGN(0x100)
+data
+up = ALIGN(0x8001, 0x10)
+same = ALIGN(0x8010, 0x10)
+",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(image.symbols["table"], 0x8100);
+    assert_eq!(image.symbols["up"], 0x8010);
+    assert_eq!(image.symbols["same"], 0x8010);
+    let errors = link_errors(
+        "a = ALIGN(0x8000, 0)\n0x8000 text data\n",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(
+        errors,
+        ["error evaluating line 1 of the linker script: can't align to 0"]
+    );
+}
+
+#[test]
+fn symbols_are_assigned_in_order() {
+    let image = link("a = 1\nb = a + 1\n0x8000 text data\n", &[("main", SOURCE)]);
+    assert_eq!(image.symbols["b"], 2);
+    let errors = link_errors("a = b + 1\nb = 1\n0x8000 text data\n", &[("main", SOURCE)]);
+    assert_eq!(
+        errors,
+        ["error evaluating line 1 of the linker script: `b` is not defined before it's used"]
+    );
+    // labels from objects aren't symbols of the script
+    let errors = link_errors("a = start\n0x8000 text data\n", &[("main", SOURCE)]);
+    assert_eq!(
+        errors,
+        ["error evaluating line 1 of the linker script: `start` is not defined before it's used"]
+    );
+}
+
+#[test]
+fn outside_of_the_address_space() {
+    let errors = link_errors("a = 0 - 1\n0x8000 text data\n", &[("main", SOURCE)]);
+    assert_eq!(
+        errors,
+        ["error evaluating line 1 of the linker script: -0x1 is outside of the address space"]
+    );
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{link_with, linker};
+use s502_ln::Image;
+
+const LAYOUT: &str = "0x8000 text data
+keep 0x9000 header
+entry start
+";
+
+const MAIN: &str = "sct text
+!!start jsr used
+ rts
+sct data
+!!table dfb $01
+";
+
+const LIBRARY: &str = "sct text
+!!used rts
+sct data
+!!unused dfb $02
+ dfb $03
+";
+
+/// Link with `--gc-sections` and the given `-k` symbols.
+fn collect(script: &str, sources: &[(&str, &str)], keep: &[&str]) -> Image {
+    let mut linker = linker(script, sources);
+    linker.gc_sections(keep.iter().map(|s| s.to_string()).collect());
+    link_with(linker)
+}
+
+fn removed(image: &Image) -> Vec<(String, String, usize)> {
+    image
+        .removed
+        .iter()
+        .map(|r| (r.object.clone(), r.section.clone(), r.size))
+        .collect()
+}
+
+#[test]
+fn drops_unreachable_parts() {
+    let image = collect(
+        LAYOUT,
+        &[
+            ("main", MAIN),
+            ("lib", LIBRARY),
+            ("header", "sct header\n!!magic dfb $42\n"),
+        ],
+        &[],
+    );
+    // the library's text is kept, and its data goes while main's stays
+    assert_eq!(
+        removed(&image),
+        [
+            ("main".to_string(), "data".to_string(), 1),
+            ("lib".to_string(), "data".to_string(), 2),
+        ]
+    );
+    assert_eq!(image.symbols["used"], 0x8004);
+    assert!(!image.symbols.contains_key("table"));
+    assert!(!image.symbols.contains_key("unused"));
+    // a keep group stays without any references
+    assert_eq!(image.symbols["magic"], 0x9000);
+}
+
+#[test]
+fn keeps_given_symbols() {
+    let image = collect(LAYOUT, &[("main", MAIN), ("lib", LIBRARY)], &["unused"]);
+    assert_eq!(
+        removed(&image),
+        [("main".to_string(), "data".to_string(), 1)]
+    );
+    assert_eq!(image.symbols["unused"], 0x8005);
+}
+
+#[test]
+fn keeps_vector_handlers() {
+    let image = collect(
+        "0x8000 text
+entry start
+vectors nmi=nmi irq=irq
+",
+        &[
+            ("main", "sct text\n!!start rts\n"),
+            ("nmi", "sct text\n!!nmi rti\n"),
+            ("irq", "sct text\n!!irq rti\n"),
+            ("lib", "sct text\n!!unused rts\n"),
+        ],
+        &[],
+    );
+    assert_eq!(
+        removed(&image),
+        [("lib".to_string(), "text".to_string(), 1)]
+    );
+    assert_eq!(
+        &image.code[image.code.len() - 6..],
+        [1, 0x80, 0, 0x80, 2, 0x80]
+    );
+}
+
+#[test]
+fn keeps_what_assertions_use() {
+    let image = collect(
+        "0x8000 text data\nentry start\n",
+        &[
+            (
+                "main",
+                "sct text\n!!start rts\n ast table == $8001, \"table follows\"\n",
+            ),
+            ("table", "sct data\n!!table dfb $01\n"),
+        ],
+        &[],
+    );
+    assert!(image.removed.is_empty());
+    assert_eq!(image.symbols["table"], 0x8001);
+}
+
+#[test]
+fn drops_assertions_with_their_part() {
+    let image = collect(
+        "0x8000 text\nentry start\n",
+        &[
+            ("main", "sct text\n!!start rts\n"),
+            ("lib", "sct text\n!!unused rts\n ast 0, \"never checked\"\n"),
+        ],
+        &[],
+    );
+    assert_eq!(
+        removed(&image),
+        [("lib".to_string(), "text".to_string(), 1)]
+    );
+}
@@ -0,0 +1,104 @@
+mod common;
+
+use common::link;
+use s502_ln::hex::{write_intel_hex, write_srec};
+use s502_ln::Image;
+
+const SPLIT: &str = "0x9000 data\n0xff00 text\nentry reset\n";
+
+const SOURCE: &str = "sct text\n!!reset nop\n rts\nsct data\n dfb $aa\n";
+
+const BANKS: &str = "region BANK0 0xfffc 4 rom bank=0
+region BANK1 0x0000 0x100 rom bank=1
+bank0 > BANK0
+bank1 > BANK1
+";
+
+fn intel_hex(image: &Image) -> Vec<String> {
+    let mut out = Vec::new();
+    write_intel_hex(image, &mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+fn srec(image: &Image) -> Vec<String> {
+    let mut out = Vec::new();
+    write_srec(image, &mut out).unwrap();
+    String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn intel_hex_leaves_out_gaps() {
+    let image = link(SPLIT, &[("main", SOURCE)]);
+    assert_eq!(
+        intel_hex(&image),
+        [
+            ":01900000AAC5",
+            ":02FF0000EA60B5",
+            ":040000050000FF00F8",
+            ":00000001FF",
+        ]
+    );
+}
+
+#[test]
+fn srec_leaves_out_gaps() {
+    let image = link(SPLIT, &[("main", SOURCE)]);
+    assert_eq!(
+        srec(&image),
+        [
+            "S0030000FC",
+            "S1049000AAC1",
+            "S105FF00EA60B1",
+            "S5030002FA",
+            "S903FF00FD",
+        ]
+    );
+}
+
+#[test]
+fn start_needs_an_entry() {
+    let image = link("0x8000 text data\n", &[("main", SOURCE)]);
+    let hex = intel_hex(&image);
+    assert!(!hex.iter().any(|line| line.starts_with(":04000005")));
+    assert_eq!(hex.last().unwrap(), ":00000001FF");
+    assert_eq!(srec(&image).last().unwrap(), "S9030000FC");
+}
+
+#[test]
+fn intel_hex_across_banks() {
+    let image = link(
+        BANKS,
+        &[
+            ("b0", "sct bank0\n dfb $01\n dfb $02\n dfb $03\n dfb $04\n"),
+            ("b1", "sct bank1\n dfb $05\n dfb $06\n"),
+        ],
+    );
+    // the banks follow each other, so the record is split at bank 1
+    assert_eq!(
+        intel_hex(&image),
+        [
+            ":04FFFC0001020304F7",
+            ":020000040001F9",
+            ":020000000506F3",
+            ":00000001FF",
+        ]
+    );
+    assert_eq!(
+        srec(&image),
+        [
+            "S0030000FC",
+            "S20800FFFC01020304F2",
+            "S2060100000506ED",
+            "S5030002FA",
+            "S804000000FB",
+        ]
+    );
+}
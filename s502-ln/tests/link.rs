@@ -0,0 +1,72 @@
+mod common;
+
+use common::{link, link_errors};
+
+#[test]
+fn places_and_resolves() {
+    let image = link(
+        "0x8000 text data\n",
+        &[
+            ("main", "sct text\n!!main jsr helper\n lda table\n rts\n"),
+            (
+                "lib",
+                "sct text\n!!helper ldx #table>\n ldy #table<\n rts\nsct data\n!!table dfw helper\n",
+            ),
+        ],
+    );
+    assert_eq!(image.base, 0x8000);
+    assert_eq!(image.symbols["main"], 0x8000);
+    assert_eq!(image.symbols["helper"], 0x8007);
+    assert_eq!(image.symbols["table"], 0x800c);
+    assert_eq!(
+        image.code,
+        [
+            0x20, 0x07, 0x80, 0xad, 0x0c, 0x80, 0x60, // main
+            0xa2, 0x0c, 0xa0, 0x80, 0x60, // helper
+            0x07, 0x80, // table
+        ]
+    );
+}
+
+#[test]
+fn resolves_branches_and_children() {
+    let image = link(
+        "0x8000 text\n",
+        &[(
+            "main",
+            "sct text\n!!main ldx #$08\n.loop dex\n bne main.loop\n beq done\n nop\ndone rts\n",
+        )],
+    );
+    assert_eq!(
+        image.code,
+        [0xa2, 0x08, 0xca, 0xd0, 0xfd, 0xf0, 0x01, 0xea, 0x60]
+    );
+    // hidden and object labels aren't global symbols
+    assert!(!image.symbols.contains_key("done"));
+}
+
+#[test]
+fn object_labels_stay_in_their_object() {
+    let errors = link_errors(
+        "0x8000 text\n",
+        &[
+            ("main", "sct text\n!!main jsr helper\n"),
+            ("lib", "sct text\n!helper rts\n"),
+        ],
+    );
+    assert_eq!(
+        errors,
+        ["undefined reference to `helper` in main section text at offset 0x1"]
+    );
+}
+
+#[test]
+fn reports_far_branches() {
+    let far = format!(
+        "sct text\n!!main beq end\n{}end rts\n",
+        " nop\n".repeat(200)
+    );
+    let errors = link_errors("0x8000 text\n", &[("main", &far)]);
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].contains("`end`"), "{}", errors[0]);
+}
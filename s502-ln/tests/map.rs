@@ -0,0 +1,72 @@
+mod common;
+
+use common::link;
+use s502_ln::map::write_map;
+
+#[test]
+fn map() {
+    let image = link(
+        "region FIXED 0xc000 0x100 rom bank=0
+region BANK1 0x8000 0x100 rom bank=1
+text 0x10 > FIXED
+extra > BANK1
+entry start
+",
+        &[
+            ("a", "sct text\n!!start nop\n nop\nlocal rts\n"),
+            ("b", "sct text\n!!helper rts\nsct extra\n!!far nop\n"),
+        ],
+    );
+    let mut out = Vec::new();
+    write_map(&image, &mut out).unwrap();
+    // each object's part of text, and symbols in bank 1 with their bank
+    let expected = "Entry start at c000
+
+Regions
+  name                     start  end    size   used   free   attributes
+  FIXED                    c000   c100   0100   0004   00fc   rom bank=0
+  BANK1                    8000   8100   0100   0001   00ff   rom bank=1
+
+Groups
+  start  end    size   max    free   region                   sections
+  c000   c004   0004   0010   000c   FIXED                    text
+  8000   8001   0001                 BANK1                    extra
+
+Sections
+  name                     start  end    size   load
+  text                     c000   c004   0004
+    a                      c000   c003   0003
+    b                      c003   c004   0001
+  extra                    8000   8001   0001
+    b                      8000   8001   0001
+
+Symbols by address
+  0001     linker  __extra_size
+  0004     linker  __text_size
+  c000     linker  __text_load
+  c000     linker  __text_start
+  c000     global  start                            a
+  c002     object  local                            a
+  c003     global  helper                           b
+  c004     linker  __text_end
+  01:8000  linker  __extra_load
+  01:8000  linker  __extra_start
+  01:8000  global  far                              b
+  01:8001  linker  __extra_end
+
+Symbols by name
+  01:8001  linker  __extra_end
+  01:8000  linker  __extra_load
+  0001     linker  __extra_size
+  01:8000  linker  __extra_start
+  c004     linker  __text_end
+  c000     linker  __text_load
+  0004     linker  __text_size
+  c000     linker  __text_start
+  01:8000  global  far                              b
+  c003     global  helper                           b
+  c002     object  local                            a
+  c000     global  start                            a
+";
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
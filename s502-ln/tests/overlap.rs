@@ -0,0 +1,19 @@
+mod common;
+
+use common::{link, link_errors};
+
+const SOURCE: &str = "sct text\n!!start nop\n nop\n nop\nsct data\n!!table dfb $01\n";
+
+#[test]
+fn groups_overlap() {
+    let errors = link_errors("0x8000 text\n0x8002 data\n", &[("main", SOURCE)]);
+    assert_eq!(errors, ["sections text and data overlap at 0x8002-0x8002"]);
+}
+
+#[test]
+fn overlays_may_overlap() {
+    let image = link("0x8000 text\noverlay 0x8002 data\n", &[("main", SOURCE)]);
+    assert_eq!(image.symbols["table"], 0x8002);
+    // the later group wins
+    assert_eq!(image.code, [0xea, 0xea, 0x01]);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::{link, link_errors};
+
+const REGIONS: &str = "region A 0x8000 0x10 rom
+region B 0x9000 0x8 rom
+";
+
+/// A section of `size` bytes starting with a global label of the same name.
+fn section(name: &str, size: usize) -> String {
+    let mut source = format!("sct {}\n!!{} dfb $00\n", name, name);
+    for _ in 1..size {
+        source.push_str(" dfb $00\n");
+    }
+    source
+}
+
+#[test]
+fn first_fit() {
+    let (big, small) = (section("big", 6), section("small", 4));
+    let image = link(
+        &format!("{}pack small big > A B\n", REGIONS),
+        &[("big", &big), ("small", &small)],
+    );
+    // largest first, both in the first region with room
+    assert_eq!(image.symbols["big"], 0x8000);
+    assert_eq!(image.symbols["small"], 0x8006);
+}
+
+#[test]
+fn best_fit() {
+    let (big, small) = (section("big", 6), section("small", 4));
+    let image = link(
+        &format!("{}pack best small big > A B\n", REGIONS),
+        &[("big", &big), ("small", &small)],
+    );
+    // big leaves 2 bytes in B, then small only fits in A
+    assert_eq!(image.symbols["big"], 0x9000);
+    assert_eq!(image.symbols["small"], 0x8000);
+}
+
+#[test]
+fn with_goes_in_the_same_bank() {
+    let (music, sfx) = (section("music", 2), section("sfx", 2));
+    let image = link(
+        "region BANK0 0x8000 0x10 rom bank=0
+region BANK1 0x8000 0x10 rom bank=1
+music > BANK1
+pack sfx(with=music) > BANK0 BANK1
+",
+        &[("music", &music), ("sfx", &sfx)],
+    );
+    assert_eq!(image.symbols["sfx"], 0x01_8002);
+}
+
+#[test]
+fn with_must_be_placed_first() {
+    let (music, sfx) = (section("music", 2), section("sfx", 2));
+    let errors = link_errors(
+        &format!("{}pack sfx(with=music) > A\nmusic > B\n", REGIONS),
+        &[("music", &music), ("sfx", &sfx)],
+    );
+    assert_eq!(
+        errors,
+        ["section sfx must be with section music, which isn't placed before it"]
+    );
+}
+
+#[test]
+fn aligns_while_packing() {
+    let (table, code) = (section("table", 2), section("code", 3));
+    let image = link(
+        &format!("{}pack table(align=8) code > A\n", REGIONS),
+        &[("table", &table), ("code", &code)],
+    );
+    assert_eq!(image.symbols["code"], 0x8000);
+    assert_eq!(image.symbols["table"], 0x8008);
+}
+
+#[test]
+fn nothing_fits() {
+    let huge = section("huge", 9);
+    let errors = link_errors(&format!("{}pack huge > B\n", REGIONS), &[("huge", &huge)]);
+    assert_eq!(
+        errors,
+        ["section huge (0x9 bytes) doesn't fit in any of the regions it's packed into"]
+    );
+}
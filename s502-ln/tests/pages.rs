@@ -0,0 +1,54 @@
+mod common;
+
+use common::{link, link_errors};
+
+#[test]
+fn script_alignment_moves_the_base() {
+    let image = link(
+        "0x8001 text data(align=0x10)\n",
+        &[("main", "sct text\n!!start rts\nsct data\n!!table dfb $01\n")],
+    );
+    assert_eq!(image.symbols["start"], 0x8001);
+    assert_eq!(image.symbols["table"], 0x8010);
+    assert_eq!(image.code.len(), 0x10);
+}
+
+#[test]
+fn object_alignment_moves_the_base() {
+    let image = link(
+        "0x8000 text data\n",
+        &[(
+            "main",
+            "sct text\n!!start rts\nsct data\n aln $08\n!!table dfb $01\n",
+        )],
+    );
+    assert_eq!(image.symbols["table"], 0x8008);
+}
+
+#[test]
+fn section_crosses_a_page() {
+    let errors = link_errors(
+        "0x80fe text(page)\n",
+        &[("main", "sct text\n nop\n nop\n nop\n")],
+    );
+    assert_eq!(
+        errors,
+        ["section text at 0x80fe-0x8100 crosses from page 0x80 into page 0x81"]
+    );
+    link(
+        "0x80fd text(page)\n",
+        &[("main", "sct text\n nop\n nop\n nop\n")],
+    );
+}
+
+#[test]
+fn block_crosses_a_page() {
+    let source = "sct text\n nop\n!!table pag\n dfb $01\n dfb $02\n!!after rts\n";
+    let errors = link_errors("0x80fe text\n", &[("main", source)]);
+    assert_eq!(
+        errors,
+        ["`table` in main section text at 0x80ff-0x8100 crosses from page 0x80 into page 0x81"]
+    );
+    // the block ends at the next label
+    link("0x80fd text\n", &[("main", source)]);
+}
@@ -0,0 +1,133 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{link, link_with, object};
+use s502_ln::formats::{Object, Visibility};
+use s502_ln::partial::merge;
+use s502_ln::script::read_script;
+use s502_ln::Linker;
+
+const A: &str = "sct text
+!!main jsr helper
+ jsr shared
+ rts
+helper ldx #$03
+.loop dex
+ bne helper.loop
+ rts
+";
+
+const B: &str = "sct text
+ aln $04
+!!shared jsr helper
+ rts
+helper rts
+";
+
+fn merged(sources: &[(&str, &str)]) -> Result<Object, Vec<String>> {
+    let objects = sources
+        .iter()
+        .map(|(name, source)| object(name, source))
+        .collect::<Vec<_>>();
+    merge("all.65o".to_string(), &objects)
+        .map_err(|errors| errors.iter().map(|e| e.to_string()).collect())
+}
+
+/// The labels of a section and their visibility by offset, then name.
+fn labels(obj: &Object, sect: &str) -> Vec<(usize, String, &'static str)> {
+    let mut labels = obj.sections[sect]
+        .labels
+        .iter()
+        .map(|(name, lab)| {
+            let vis = match lab.vis {
+                Visibility::Hidden => "hidden",
+                Visibility::Object => "object",
+                Visibility::Global => "global",
+                Visibility::Weak => "weak",
+            };
+            (lab.offset, name.clone(), vis)
+        })
+        .collect::<Vec<_>>();
+    labels.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    labels
+}
+
+#[test]
+fn sections_are_merged() {
+    let obj = merged(&[("a", A), ("b", B)]).unwrap();
+    let sect = &obj.sections["text"];
+    // each part keeps its alignment
+    assert_eq!(sect.size, 21);
+    assert_eq!(sect.align, 4);
+    assert_eq!(&sect.code[13..16], [0, 0, 0]);
+    assert_eq!(
+        labels(&obj, "text"),
+        [
+            (0, "main".to_string(), "global"),
+            (7, "helper".to_string(), "object"),
+            (9, "helper.loop".to_string(), "hidden"),
+            (16, "shared".to_string(), "global"),
+            (20, "helper_1".to_string(), "object"),
+        ]
+    );
+    // the branch is filled in, everything else is left for the link
+    assert_eq!(&sect.code[10..12], [0xd0, 0xfd]);
+    let references = sect
+        .references
+        .iter()
+        .map(|rf| (rf.offset, rf.referred.as_str()))
+        .collect::<Vec<_>>();
+    assert_eq!(references, [(1, "helper"), (4, "shared"), (17, "helper_1")]);
+}
+
+#[test]
+fn merged_objects_link_the_same() {
+    let layout = "0x8000 text\n";
+    let obj = merged(&[("a", A), ("b", B)]).unwrap();
+    let linker = Linker::new(read_script(layout).unwrap(), vec![obj], HashMap::new());
+    let image = link_with(linker);
+    assert_eq!(image.code, link(layout, &[("a", A), ("b", B)]).code);
+}
+
+#[test]
+fn weak_labels_give_way() {
+    let obj = merged(&[
+        ("a", "sct text\n!!main jsr handler\n rts\n?handler rti\n"),
+        ("b", "sct text\n!!handler nop\n rti\n"),
+    ])
+    .unwrap();
+    assert_eq!(
+        labels(&obj, "text"),
+        [
+            (0, "main".to_string(), "global"),
+            (4, "handler_0".to_string(), "hidden"),
+            (5, "handler".to_string(), "global"),
+        ]
+    );
+    assert_eq!(obj.sections["text"].references[0].referred, "handler");
+}
+
+#[test]
+fn global_labels_may_not_be_duplicated() {
+    let errors = merged(&[
+        ("a", "sct text\n!!main rts\n"),
+        ("b", "sct text\n!!main rts\n"),
+    ])
+    .err()
+    .unwrap();
+    assert_eq!(errors, ["`main` is defined multiple times"]);
+}
+
+#[test]
+fn assertions_follow_their_labels() {
+    let obj = merged(&[
+        ("a", A),
+        ("b", "sct text\n!!other rts\nhelper rts\n ast helper = other + 1, \"helper is after other\"\n"),
+    ])
+    .unwrap();
+    let assertion = &obj.assertions[0];
+    assert_eq!(assertion.expression, "helper_1 = other + 1");
+    assert_eq!(assertion.offset, 15);
+    assert_eq!(assertion.file, "b");
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::link;
+use s502_ln::prg::prg;
+
+const CODE: &str = "sct text\n!!reset nop\n rts\n";
+
+/// The stub `10 SYS 2061` loaded at 0x0801.
+const STUB: &[u8] = b"\x01\x08\x0b\x08\x0a\x00\x9e2061\x00\x00\x00";
+
+#[test]
+fn load_address_header() {
+    let image = link("0xc000 text\n", &[("main", CODE)]);
+    assert_eq!(prg(&image, false).unwrap(), [0x00, 0xc0, 0xea, 0x60]);
+}
+
+#[test]
+fn stub_right_before_the_code() {
+    let image = link("0x080d text\nentry reset\n", &[("main", CODE)]);
+    let bytes = prg(&image, true).unwrap();
+    assert_eq!(&bytes[..STUB.len()], STUB);
+    assert_eq!(&bytes[STUB.len()..], [0xea, 0x60]);
+}
+
+#[test]
+fn stub_starts_the_entry() {
+    let image = link("0x0900 text\nentry reset\n", &[("main", CODE)]);
+    let bytes = prg(&image, true).unwrap();
+    assert_eq!(&bytes[..2], [0x01, 0x08]);
+    assert_eq!(&bytes[6..12], b"\x9e2304\x00");
+    // zeros from the end of the stub up to the code
+    assert_eq!(bytes.len(), 2 + 0x0900 - 0x0801 + 2);
+    assert!(bytes[STUB.len()..(2 + 0x0900 - 0x0801)]
+        .iter()
+        .all(|&b| b == 0));
+    assert_eq!(&bytes[(2 + 0x0900 - 0x0801)..], [0xea, 0x60]);
+}
+
+#[test]
+fn code_under_the_stub() {
+    let image = link("0x0801 text\nentry reset\n", &[("main", CODE)]);
+    assert_eq!(
+        prg(&image, true).unwrap_err().to_string(),
+        "the image starts at 0x0801, before the BASIC stub ends at 0x080d"
+    );
+}
+
+#[test]
+fn stub_needs_an_entry() {
+    let image = link("0x0900 text\n", &[("main", CODE)]);
+    assert_eq!(
+        prg(&image, true).unwrap_err().to_string(),
+        "the BASIC stub needs an entry"
+    );
+}
+
+#[test]
+fn no_banks() {
+    let image = link(
+        "region BANK0 0x8000 0x100 rom bank=0\nregion BANK1 0x8000 0x100 rom bank=1\ntext > BANK0\n",
+        &[("main", CODE)],
+    );
+    assert_eq!(
+        prg(&image, false).unwrap_err().to_string(),
+        "PRG output can't have banks"
+    );
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{link, link_errors};
+
+const SOURCE: &str = "sct text\n!!start nop\n nop\n nop\nsct data\n!!table dfb $01\n";
+
+#[test]
+fn groups_in_a_region() {
+    let image = link(
+        "region ROM 0x8000 0x10 rom\nregion RAM 0x0200 0x10 ram\ntext > ROM\ndata > ROM\n",
+        &[("main", SOURCE)],
+    );
+    // one after another
+    assert_eq!(image.symbols["table"], 0x8003);
+}
+
+#[test]
+fn region_overflows() {
+    let errors = link_errors(
+        "region ROM 0x8000 0x3 rom\ntext data > ROM\n",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(
+        errors,
+        ["group `text data` overflows region ROM by 0x1 bytes"]
+    );
+    // a region that's already full
+    let errors = link_errors(
+        "region ROM 0x8000 0x3 rom\ntext > ROM\ndata > ROM\n",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(
+        errors,
+        ["group `data` starts at 0x8003, outside of region ROM"]
+    );
+}
+
+#[test]
+fn group_overflows() {
+    let errors = link_errors("0x8000 text 0x2\n0x9000 data\n", &[("main", SOURCE)]);
+    assert_eq!(
+        errors,
+        ["group `text` is 0x3 bytes, larger than its maximum of 0x2"]
+    );
+}
+
+#[test]
+fn filled_regions_are_output_in_full() {
+    let image = link(
+        "region ROM 0x8000 0x8 rom fill=0xff\ntext > ROM\n0x8010 data\n",
+        &[("main", SOURCE)],
+    );
+    assert_eq!(image.base, 0x8000);
+    let mut expected = vec![0xea, 0xea, 0xea, 0xff, 0xff, 0xff, 0xff, 0xff];
+    // a gap outside of a region is zeros
+    expected.extend_from_slice(&[0; 8]);
+    expected.push(0x01);
+    assert_eq!(image.code, expected);
+}
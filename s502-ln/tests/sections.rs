@@ -0,0 +1,54 @@
+mod common;
+
+use std::collections::HashMap;
+
+use common::{link, link_with, object};
+use s502_ln::script::read_script;
+use s502_ln::Linker;
+
+const PROGRAM: &str = "sct text\n!!main lda __text_end\n rts\nsct data\n dfb $01\n dfb $02\n";
+
+#[test]
+fn provides_section_symbols() {
+    let image = link("0x9000 text\n0x9100 data\n", &[("b", PROGRAM)]);
+    assert_eq!(image.symbols["__text_start"], 0x9000);
+    assert_eq!(image.symbols["__text_end"], 0x9004);
+    assert_eq!(image.symbols["__text_size"], 4);
+    assert_eq!(image.symbols["__data_load"], 0x9100);
+    assert_eq!(&image.code[..3], [0xad, 0x04, 0x90]);
+    // they aren't exported to symbol tables
+    let exported = image.exported_symbols();
+    assert_eq!(exported.get("main"), Some(&0x9000));
+    assert!(exported.keys().all(|name| !name.starts_with("__")));
+}
+
+#[test]
+fn own_sections_override_linked_ones() {
+    // symbols from a program placed at 0x8000
+    let linked = [
+        ("__text_start", 0x8000),
+        ("__text_end", 0x8004),
+        ("__rom_end", 0xc000),
+    ];
+    let linked = linked
+        .iter()
+        .map(|&(name, value)| (name.to_string(), value))
+        .collect::<HashMap<_, _>>();
+    let layout = read_script("0x9000 text data\n").unwrap();
+    let image = link_with(Linker::new(layout, vec![object("b", PROGRAM)], linked));
+    assert_eq!(image.symbols["__text_start"], 0x9000);
+    assert_eq!(image.symbols["__text_end"], 0x9004);
+    assert_eq!(&image.code[..3], [0xad, 0x04, 0x90]);
+    // there's no section rom here
+    assert_eq!(image.symbols["__rom_end"], 0xc000);
+}
+
+#[test]
+fn definitions_override_section_symbols() {
+    let layout = read_script("0x9000 text data\n").unwrap();
+    let mut linker = Linker::new(layout, vec![object("b", PROGRAM)], HashMap::new());
+    linker.define("__text_end=0x1234").unwrap();
+    let image = link_with(linker);
+    assert_eq!(image.symbols["__text_end"], 0x1234);
+    assert_eq!(&image.code[..3], [0xad, 0x34, 0x12]);
+}
@@ -0,0 +1,195 @@
+mod common;
+
+use common::{link, link_errors};
+use s502_ln::linker::LINKER_OBJECT;
+use s502_ln::Image;
+
+const BANKS: &str = "region BANK0 0x8000 0x4000 rom bank=0
+region BANK1 0x8000 0x4000 rom bank=1
+region FIXED 0xC000 0x4000 rom bank=2
+entry reset
+trampoline far_call
+bank0 > BANK0
+bank1 > BANK1
+fixed > FIXED
+__trampolines > FIXED
+";
+
+/// Switches banks by writing the number to `$8000`, sharing the write with the
+/// switch back.
+const FAR_CALL: &str = "sct far_call
+!!far_call lda #__tramp_bank>
+ jsr switch
+ jsr __tramp_target
+ lda #__tramp_return>
+switch sta $8000
+ rts
+";
+
+/// Keeps the bank switched in at `$00`.
+const FIXED_CALL: &str = "sct fixed_call
+!!fixed_call lda $00
+ pha
+ lda #__tramp_bank>
+ sta $00
+ sta $8000
+ jsr __tramp_target
+ pla
+ sta $00
+ sta $8000
+ rts
+";
+
+const FIXED: &str = "sct fixed
+!!reset jmp reset
+";
+
+/// The bytes of the fixed bank from an address.
+fn fixed(image: &Image, address: usize) -> &[u8] {
+    &image.code[0x8000 + (address & 0xffff) - 0xc000..]
+}
+
+#[test]
+fn calls_go_through_trampolines() {
+    let image = link(
+        BANKS,
+        &[
+            ("fixed", FIXED),
+            ("tramp", FAR_CALL),
+            ("b0", "sct bank0\n!!b0code jsr b1code\n jsr b1code\n rts\n"),
+            ("b1", "sct bank1\n!!b1code jsr b0code\n rts\n"),
+        ],
+    );
+    let to_b1 = image.symbols["__trampoline_b1code_0"];
+    let to_b0 = image.symbols["__trampoline_b0code_1"];
+    assert_eq!(to_b1 >> 16, 2);
+    assert_eq!(to_b0 >> 16, 2);
+
+    // both calls share a trampoline
+    let (lo, hi) = (to_b1 as u8, (to_b1 >> 8) as u8);
+    assert_eq!(&image.code[..7], [0x20, lo, hi, 0x20, lo, hi, 0x60]);
+    let (lo, hi) = (to_b0 as u8, (to_b0 >> 8) as u8);
+    assert_eq!(&image.code[0x4000..0x4004], [0x20, lo, hi, 0x60]);
+
+    // each copy calls its own `switch`
+    let switch = to_b1 + 10;
+    assert_eq!(
+        &fixed(&image, to_b1)[..14],
+        [
+            0xa9,
+            0x01,
+            0x20,
+            switch as u8,
+            (switch >> 8) as u8,
+            0x20,
+            0x00,
+            0x80,
+            0xa9,
+            0x00,
+            0x8d,
+            0x00,
+            0x80,
+            0x60
+        ]
+    );
+    let switch = to_b0 + 10;
+    assert_eq!(
+        &fixed(&image, to_b0)[..14],
+        [
+            0xa9,
+            0x00,
+            0x20,
+            switch as u8,
+            (switch >> 8) as u8,
+            0x20,
+            0x00,
+            0x80,
+            0xa9,
+            0x01,
+            0x8d,
+            0x00,
+            0x80,
+            0x60
+        ]
+    );
+}
+
+#[test]
+fn trampolines_are_in_one_object() {
+    let image = link(
+        BANKS,
+        &[
+            ("fixed", FIXED),
+            ("tramp", FAR_CALL),
+            ("b0", "sct bank0\n!!b0code jsr b1code\n rts\n"),
+            ("b1", "sct bank1\n!!b1code jsr b0code\n rts\n"),
+        ],
+    );
+    let linker = image
+        .objects
+        .iter()
+        .filter(|obj| obj.name == LINKER_OBJECT)
+        .collect::<Vec<_>>();
+    assert_eq!(linker.len(), 1);
+    let sect = &linker[0].sections["__trampolines"];
+    assert_eq!(sect.size, 28);
+    let mut labels = sect.labels.keys().cloned().collect::<Vec<_>>();
+    labels.sort();
+    assert_eq!(
+        labels,
+        [
+            "__trampoline_b0code_1",
+            "__trampoline_b0code_1.far_call",
+            "__trampoline_b0code_1.switch",
+            "__trampoline_b1code_0",
+            "__trampoline_b1code_0.far_call",
+            "__trampoline_b1code_0.switch",
+        ]
+    );
+    // the template itself isn't placed
+    assert!(!image.symbols.contains_key("far_call"));
+}
+
+#[test]
+fn fixed_calls_need_a_second_template() {
+    let errors = link_errors(
+        BANKS,
+        &[
+            ("fixed", "sct fixed\n!!reset jsr b1code\n jmp reset\n"),
+            ("tramp", FAR_CALL),
+            ("b1", "sct bank1\n!!b1code rts\n"),
+        ],
+    );
+    assert_eq!(
+        errors,
+        [
+            "call to `b1code` in bank 1 from fixed section fixed at offset 0x1 needs a second \
+             trampoline template for code that's always switched in, or `b1code` listed as `far`"
+        ]
+    );
+}
+
+#[test]
+fn fixed_calls_use_the_second_template() {
+    let layout = BANKS.replace("far_call\n", "far_call fixed_call\n");
+    let image = link(
+        &layout,
+        &[
+            ("fixed", "sct fixed\n!!reset jsr b1code\n jmp reset\n"),
+            ("tramp", &format!("{}{}", FAR_CALL, FIXED_CALL)),
+            ("b1", "sct bank1\n!!b1code rts\n"),
+        ],
+    );
+    let tramp = image.symbols["__trampoline_b1code"];
+    assert_eq!(
+        &fixed(&image, 0xc000)[..3],
+        [0x20, tramp as u8, (tramp >> 8) as u8]
+    );
+    assert_eq!(
+        &fixed(&image, tramp)[..20],
+        [
+            0xa5, 0x00, 0x48, 0xa9, 0x01, 0x85, 0x00, 0x8d, 0x00, 0x80, 0x20, 0x00, 0x80, 0x68,
+            0x85, 0x00, 0x8d, 0x00, 0x80, 0x60
+        ]
+    );
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::{link, link_errors, link_with, linker};
+
+const HANDLERS: &str = "sct text
+!!start rts
+!!nmi rti
+!!irq rti
+";
+
+#[test]
+fn table_at_the_top() {
+    let image = link(
+        "region ROM 0x8000 0x8000 rom\ntext > ROM\nentry start\nvectors nmi=nmi irq=irq\n",
+        &[("main", HANDLERS)],
+    );
+    assert_eq!(image.symbols["__vectors_start"], 0xfffa);
+    assert_eq!(image.base + image.code.len(), 0x10000);
+    // NMI, RESET and IRQ
+    assert_eq!(
+        &image.code[image.code.len() - 6..],
+        [0x01, 0x80, 0x00, 0x80, 0x02, 0x80]
+    );
+    assert_eq!(image.entry, Some(("start".to_string(), 0x8000)));
+}
+
+#[test]
+fn vectors_from_the_linker() {
+    let mut linker = linker("0x8000 text\n", &[("main", HANDLERS)]);
+    linker.entry("start".to_string());
+    linker.vectors(Some("nmi".to_string()), Some("irq".to_string()));
+    let image = link_with(linker);
+    assert_eq!(image.symbols["__vectors_start"], 0xfffa);
+    assert_eq!(&image.code[image.code.len() - 2..], [0x02, 0x80]);
+}
+
+#[test]
+fn every_handler_is_needed() {
+    let errors = link_errors(
+        "0x8000 text\nentry start\nvectors nmi=nmi\n",
+        &[("main", HANDLERS)],
+    );
+    assert_eq!(errors, ["no handler is given for the irq vector"]);
+    let errors = link_errors(
+        "0x8000 text\nvectors nmi=nmi irq=irq\n",
+        &[("main", HANDLERS)],
+    );
+    assert_eq!(errors, ["no handler is given for the reset vector"]);
+}
+
+#[test]
+fn handlers_are_global() {
+    let mut errors = link_errors(
+        "0x8000 text\nentry start\nvectors nmi=local irq=missing\n",
+        &[("main", "sct text\n!!start rts\nlocal rti\n")],
+    );
+    errors.sort();
+    assert_eq!(
+        errors,
+        [
+            "irq vector `missing` is not a global symbol",
+            "nmi vector `local` is not a global symbol"
+        ]
+    );
+}
+
+#[test]
+fn entry_without_vectors() {
+    let image = link("0x8000 text\nentry irq\n", &[("main", HANDLERS)]);
+    assert_eq!(image.entry, Some(("irq".to_string(), 0x8002)));
+    assert!(!image.symbols.contains_key("__vectors_start"));
+    assert_eq!(image.code.len(), 3);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::{link, link_errors, link_with, linker};
+
+/// Calls the handler, which has a weak default.
+const MAIN: &str = "sct text
+!!main jsr handler
+ rts
+?handler rti
+";
+
+#[test]
+fn global_labels_replace_weak_ones() {
+    let image = link(
+        "0x8000 text\n",
+        &[("main", MAIN), ("irq", "sct text\n!!handler nop\n rti\n")],
+    );
+    assert_eq!(image.symbols["handler"], 0x8005);
+    assert_eq!(image.code[..3], [0x20, 0x05, 0x80]);
+}
+
+#[test]
+fn first_weak_label_is_used() {
+    let image = link(
+        "0x8000 text\n",
+        &[("main", MAIN), ("irq", "sct text\n?handler nop\n rti\n")],
+    );
+    assert_eq!(image.symbols["handler"], 0x8004);
+    assert_eq!(image.code[..3], [0x20, 0x04, 0x80]);
+}
+
+#[test]
+fn weak_labels_give_way_to_defined_symbols() {
+    let mut linker = linker("0x8000 text\n", &[("main", MAIN)]);
+    linker.define("handler=0x9000").unwrap();
+    let image = link_with(linker);
+    assert_eq!(image.code[..3], [0x20, 0x00, 0x90]);
+}
+
+#[test]
+fn children_of_replaced_labels_are_found() {
+    let image = link(
+        "0x8000 text\n",
+        &[
+            (
+                "main",
+                "sct text\n!!main jsr handler\n?handler ldx #$02\n.loop dex\n bne handler.loop\n rti\n",
+            ),
+            ("irq", "sct text\n!!handler rti\n"),
+        ],
+    );
+    assert_eq!(image.symbols["handler"], 0x8009);
+    assert_eq!(
+        image.code,
+        [0x20, 0x09, 0x80, 0xa2, 0x02, 0xca, 0xd0, 0xfd, 0x40, 0x40]
+    );
+    assert!(!image.symbols.contains_key("handler.loop"));
+}
+
+#[test]
+fn global_labels_may_not_be_duplicated() {
+    let errors = link_errors(
+        "0x8000 text\n",
+        &[
+            ("main", MAIN),
+            ("irq", "sct text\n!!handler rti\n"),
+            ("nmi", "sct text\n!!handler rti\n"),
+        ],
+    );
+    assert_eq!(errors, ["`handler` is defined multiple times"]);
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{errors_with, link_with, linker};
+
+const IO: &str = "sct text
+!!putchar sta $2007
+ rts
+!!puts jsr putchar
+ rts
+";
+
+/// Stands in for `putchar` and still calls it.
+const STUB: &str = "sct text
+!!__wrap_putchar inc $00
+ jsr __real_putchar
+ rts
+";
+
+#[test]
+fn wrapped_references_go_to_the_wrapper() {
+    let mut linker = linker(
+        "0x8000 text\n",
+        &[
+            ("main", "sct text\n!!main jsr putchar\n rts\n"),
+            ("io", IO),
+            ("stub", STUB),
+        ],
+    );
+    linker.wrap("putchar".to_string());
+    let image = link_with(linker);
+    assert_eq!(image.symbols["putchar"], 0x8004);
+    assert_eq!(image.symbols["__wrap_putchar"], 0x800c);
+    assert_eq!(
+        image.code,
+        [
+            0x20, 0x0c, 0x80, 0x60, // main calls the wrapper
+            0x8d, 0x07, 0x20, 0x60, // putchar
+            0x20, 0x04, 0x80, 0x60, // puts is in the same object as putchar
+            0xe6, 0x00, 0x20, 0x04, 0x80, 0x60, // the wrapper calls putchar
+        ]
+    );
+}
+
+#[test]
+fn defined_symbols_are_resolved() {
+    let mut linker = linker(
+        "0x8000 text\n",
+        &[("main", "sct text\n!!main sta PPU\n lda #PPU<\n")],
+    );
+    linker.define("PPU=0x2000 + 6").unwrap();
+    let image = link_with(linker);
+    assert_eq!(image.code, [0x8d, 0x06, 0x20, 0xa9, 0x20]);
+    assert_eq!(image.symbols["PPU"], 0x2006);
+}
+
+#[test]
+fn definitions_are_checked() {
+    let mut linker = linker("0x8000 text\n", &[("main", "sct text\n!!main rts\n")]);
+    assert_eq!(
+        linker.define("1x=2").unwrap_err().to_string(),
+        "invalid symbol definition `1x=2`: expected a symbol name before `=`"
+    );
+    assert_eq!(
+        linker.define("PPU").unwrap_err().to_string(),
+        "invalid symbol definition `PPU`: expected `NAME=VALUE`"
+    );
+    linker.define("main=0x9000").unwrap();
+    // a definition is a global symbol like any other
+    assert_eq!(errors_with(linker), ["`main` is defined multiple times"]);
+}
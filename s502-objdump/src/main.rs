@@ -0,0 +1,360 @@
+mod print;
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use s502_ln::formats::{Assertion, Block, Object, Reference, Visibility};
+use s502_ln::linker::lookup;
+use s502_ln::object::{read_object, read_symtab};
+
+/// The contents of one file after filtering.
+pub enum Dump {
+    Object {
+        file: String,
+        sections: Vec<SectionDump>,
+        /// Labels referred to but not defined in the object and where they are used.
+        undefined: BTreeMap<String, Vec<(String, usize)>>,
+    },
+    Symbols {
+        file: String,
+        symbols: Vec<(String, usize)>,
+    },
+}
+
+pub struct SectionDump {
+    pub name: String,
+    pub size: usize,
+    /// The multiple the object's part of the section starts at.
+    pub align: usize,
+    pub labels: Vec<ParentDump>,
+    pub references: Vec<Reference>,
+    /// The parts that must not cross a page.
+    pub blocks: Vec<Block>,
+    /// The assertions made in the section, which aren't filtered by symbol.
+    pub assertions: Vec<Assertion>,
+}
+
+/// A parent label with its children, all ordered by offset.
+pub struct ParentDump {
+    pub name: String,
+    pub offset: usize,
+    pub vis: Visibility,
+    pub children: Vec<(String, usize, Visibility)>,
+}
+
+/// Which sections and symbols to show, everything is shown when a list is empty.
+struct Filter {
+    sections: Vec<String>,
+    symbols: Vec<String>,
+}
+
+impl Filter {
+    fn section(&self, name: &str) -> bool {
+        self.sections.is_empty() || self.sections.iter().any(|s| s == name)
+    }
+
+    /// A child matches if it or its parent was asked for.
+    fn symbol(&self, name: &str) -> bool {
+        let parent = name.split('.').next().unwrap();
+        self.symbols.is_empty() || self.symbols.iter().any(|s| s == name || s == parent)
+    }
+}
+
+fn main() -> ExitCode {
+    let arg_matches = clap::App::new("s502-objdump 0.1")
+        .arg(
+            clap::Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Output JSON instead of text"),
+        )
+        .arg(
+            clap::Arg::with_name("section")
+                .short("S")
+                .long("section")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only show this section"),
+        )
+        .arg(
+            clap::Arg::with_name("symbol")
+                .short("y")
+                .long("symbol")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Only show this symbol, a parent label includes its children"),
+        )
+        .arg(
+            clap::Arg::with_name("undefined")
+                .short("u")
+                .long("undefined")
+                .help("Only show what each object refers to but doesn't define"),
+        )
+        .arg(
+            clap::Arg::with_name("output file")
+                .short("o")
+                .long("output")
+                .takes_value(true)
+                .help("Name for output file (default stdout)"),
+        )
+        .arg(
+            clap::Arg::with_name("inputs")
+                .multiple(true)
+                .required(true)
+                .help("The object files (*.65o) and symbol tables (*.65s) to inspect"),
+        )
+        .get_matches();
+
+    let filter = Filter {
+        sections: arg_matches.values_of_lossy("section").unwrap_or_default(),
+        symbols: arg_matches.values_of_lossy("symbol").unwrap_or_default(),
+    };
+    let undefined_only = arg_matches.is_present("undefined");
+
+    let mut dumps = Vec::new();
+    for file in arg_matches.values_of("inputs").into_iter().flatten() {
+        match read_dump(file, &filter) {
+            Ok(mut dump) => {
+                if undefined_only {
+                    match &mut dump {
+                        Dump::Object { sections, .. } => sections.clear(),
+                        Dump::Symbols { .. } => continue,
+                    }
+                }
+                dumps.push(dump);
+            }
+            Err(e) => {
+                eprintln!("error reading {}: {}", file, e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let json = arg_matches.is_present("json");
+    let write = |out: &mut dyn Write| {
+        if json {
+            print::json(&dumps, out)
+        } else {
+            print::text(&dumps, out)
+        }
+    };
+    let result = match arg_matches.value_of("output file") {
+        Some(out) => File::create(out).and_then(|f| {
+            let mut out = BufWriter::new(f);
+            write(&mut out)?;
+            out.flush()
+        }),
+        None => write(&mut io::stdout().lock()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error writing output: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Read an object or symbol table and keep only what the filter allows.
+fn read_dump(file: &str, filter: &Filter) -> io::Result<Dump> {
+    match Path::new(file).extension().and_then(OsStr::to_str) {
+        Some("65o") => {
+            let obj = read_object(file.to_string(), BufReader::new(File::open(file)?))?;
+            Ok(dump_object(&obj, filter))
+        }
+        Some("65s") => {
+            let mut symbols = read_symtab(BufReader::new(File::open(file)?))?;
+            symbols.retain(|(name, _)| filter.symbol(name));
+            symbols.sort_by_key(|&(_, address)| address);
+            Ok(Dump::Symbols {
+                file: file.to_string(),
+                symbols,
+            })
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected an object (*.65o) or symbol table (*.65s)",
+        )),
+    }
+}
+
+fn dump_object(obj: &Object, filter: &Filter) -> Dump {
+    let mut names = obj.sections.keys().collect::<Vec<_>>();
+    names.sort();
+
+    let mut sections = Vec::with_capacity(names.len());
+    let mut undefined = BTreeMap::<String, Vec<(String, usize)>>::new();
+    for name in names.into_iter().filter(|name| filter.section(name)) {
+        let sect = &obj.sections[name];
+
+        // rebuild the label tree
+        let mut labels = sect
+            .labels
+            .iter()
+            .filter(|(name, _)| !name.contains('.'))
+            .map(|(name, lab)| ParentDump {
+                name: name.clone(),
+                offset: lab.offset,
+                vis: lab.vis,
+                children: Vec::new(),
+            })
+            .collect::<Vec<_>>();
+        for (name, lab) in &sect.labels {
+            if let Some(dot) = name.find('.') {
+                if let Some(parent) = labels.iter_mut().find(|p| p.name == name[..dot]) {
+                    parent
+                        .children
+                        .push((name[(dot + 1)..].to_string(), lab.offset, lab.vis));
+                }
+            }
+        }
+        for parent in &mut labels {
+            let name = &parent.name;
+            parent
+                .children
+                .retain(|(child, _, _)| filter.symbol(&format!("{}.{}", name, child)));
+            parent
+                .children
+                .sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        }
+        labels.retain(|p| filter.symbol(&p.name) || !p.children.is_empty());
+        labels.sort_by(|a, b| a.offset.cmp(&b.offset).then_with(|| a.name.cmp(&b.name)));
+
+        let mut references = sect
+            .references
+            .iter()
+            .filter(|rf| filter.symbol(&rf.referred))
+            .cloned()
+            .collect::<Vec<_>>();
+        references.sort_by_key(|rf| rf.offset);
+        for rf in &references {
+            if lookup(obj, name, rf).is_none() {
+                undefined
+                    .entry(rf.referred.clone())
+                    .or_default()
+                    .push((name.clone(), rf.offset));
+            }
+        }
+
+        let mut blocks = sect
+            .blocks
+            .iter()
+            .filter(|block| filter.symbol(&block.label))
+            .cloned()
+            .collect::<Vec<_>>();
+        blocks.sort_by_key(|block| block.offset);
+        let mut assertions = obj
+            .assertions
+            .iter()
+            .filter(|assertion| assertion.section == *name)
+            .cloned()
+            .collect::<Vec<_>>();
+        assertions.sort_by_key(|assertion| (assertion.offset, assertion.line));
+
+        sections.push(SectionDump {
+            name: name.clone(),
+            size: sect.size,
+            align: sect.align,
+            labels,
+            references,
+            blocks,
+            assertions,
+        });
+    }
+
+    Dump::Object {
+        file: obj.name.clone(),
+        sections,
+        undefined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "sct text
+!!start ldx #$00
+.loop dex
+ bne start.loop
+ jsr helper
+ jsr print
+helper rts
+sct data
+!!table dfw start
+";
+
+    fn dump(sections: &[&str], symbols: &[&str]) -> String {
+        let obj = s502_as::assemble(SOURCE, &s502_as::Options::default()).unwrap();
+        let obj = read_object("main.65o".to_string(), &obj.to_bytes()[..]).unwrap();
+        let filter = Filter {
+            sections: sections.iter().map(|s| s.to_string()).collect(),
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        };
+        let mut out = Vec::new();
+        print::text(&[dump_object(&obj, &filter)], &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn everything() {
+        assert_eq!(
+            dump(&[], &[]),
+            "object main.65o
+
+section data, 2 bytes
+  labels
+    0000  table                    global
+  references
+    0000  start                    both
+
+section text, 12 bytes
+  labels
+    0000  start                    global
+    0002    .loop                  hidden
+    000b  helper                   object
+  references
+    0004  start.loop               branch
+    0006  helper                   both
+    0009  print                    both
+
+undefined
+  print                    text:0009
+"
+        );
+    }
+
+    #[test]
+    fn filtered() {
+        // a parent includes its children, and only the sections asked for are shown
+        assert_eq!(
+            dump(&["text"], &["start", "print"]),
+            "object main.65o
+
+section text, 12 bytes
+  labels
+    0000  start                    global
+    0002    .loop                  hidden
+  references
+    0004  start.loop               branch
+    0009  print                    both
+
+undefined
+  print                    text:0009
+"
+        );
+        assert_eq!(
+            dump(&["data"], &["start.loop"]),
+            "object main.65o
+
+section data, 2 bytes
+"
+        );
+    }
+}
@@ -0,0 +1,306 @@
+//! Writes dumps as text for reading or JSON for other tools.
+
+use std::io::{self, Write};
+
+use super::Dump;
+use s502_ln::formats::{ByteSelect, Reference, Visibility};
+
+fn vis_name(vis: Visibility) -> &'static str {
+    match vis {
+        Visibility::Hidden => "hidden",
+        Visibility::Object => "object",
+        Visibility::Global => "global",
+        Visibility::Weak => "weak",
+    }
+}
+
+/// How a reference is filled in, the byte selection doesn't matter for branches.
+fn kind_name(rf: &Reference) -> &'static str {
+    match (rf.branch, rf.which_byte) {
+        (true, _) => "branch",
+        (false, ByteSelect::Both) => "both",
+        (false, ByteSelect::High) => "high",
+        (false, ByteSelect::Low) => "low",
+    }
+}
+
+/// Write every dump as indented text.
+pub fn text(dumps: &[Dump], out: &mut dyn Write) -> io::Result<()> {
+    for (idx, dump) in dumps.iter().enumerate() {
+        if idx != 0 {
+            writeln!(out)?;
+        }
+        match dump {
+            Dump::Object {
+                file,
+                sections,
+                undefined,
+            } => {
+                writeln!(out, "object {}", file)?;
+                for sect in sections {
+                    writeln!(out)?;
+                    match sect.align {
+                        1 => writeln!(out, "section {}, {} bytes", sect.name, sect.size)?,
+                        align => writeln!(
+                            out,
+                            "section {}, {} bytes aligned to {:#x}",
+                            sect.name, sect.size, align
+                        )?,
+                    }
+                    if !sect.labels.is_empty() {
+                        writeln!(out, "  labels")?;
+                    }
+                    for parent in &sect.labels {
+                        writeln!(
+                            out,
+                            "    {:04x}  {:24} {}",
+                            parent.offset,
+                            parent.name,
+                            vis_name(parent.vis)
+                        )?;
+                        for (child, offset, vis) in &parent.children {
+                            writeln!(
+                                out,
+                                "    {:04x}    .{:21} {}",
+                                offset,
+                                child,
+                                vis_name(*vis)
+                            )?;
+                        }
+                    }
+                    if !sect.references.is_empty() {
+                        writeln!(out, "  references")?;
+                    }
+                    for rf in &sect.references {
+                        writeln!(
+                            out,
+                            "    {:04x}  {:24} {}",
+                            rf.offset,
+                            rf.referred,
+                            kind_name(rf)
+                        )?;
+                    }
+                    if !sect.blocks.is_empty() {
+                        writeln!(out, "  blocks")?;
+                    }
+                    for block in &sect.blocks {
+                        writeln!(
+                            out,
+                            "    {:04x}  {:24} {:#x} bytes",
+                            block.offset, block.label, block.size
+                        )?;
+                    }
+                    if !sect.assertions.is_empty() {
+                        writeln!(out, "  assertions")?;
+                    }
+                    for assertion in &sect.assertions {
+                        writeln!(
+                            out,
+                            "    {:04x}  line {}: {}, \"{}\"",
+                            assertion.offset,
+                            assertion.line,
+                            assertion.expression,
+                            assertion.message
+                        )?;
+                    }
+                }
+                if !undefined.is_empty() {
+                    writeln!(out)?;
+                    writeln!(out, "undefined")?;
+                }
+                for (symbol, uses) in undefined {
+                    let uses = uses
+                        .iter()
+                        .map(|(sect, offset)| format!("{}:{:04x}", sect, offset))
+                        .collect::<Vec<_>>();
+                    writeln!(out, "  {:24} {}", symbol, uses.join(" "))?;
+                }
+            }
+            Dump::Symbols { file, symbols } => {
+                writeln!(out, "symbols {}", file)?;
+                for (name, address) in symbols {
+                    writeln!(out, "  {:04x}  {}", address, name)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Quote a string for JSON.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Write every dump as one JSON array.
+pub fn json(dumps: &[Dump], out: &mut dyn Write) -> io::Result<()> {
+    let mut files = Vec::with_capacity(dumps.len());
+    for dump in dumps {
+        files.push(match dump {
+            Dump::Object {
+                file,
+                sections,
+                undefined,
+            } => {
+                let sections = sections
+                    .iter()
+                    .map(|sect| {
+                        let labels = sect
+                            .labels
+                            .iter()
+                            .map(|parent| {
+                                let children = parent
+                                    .children
+                                    .iter()
+                                    .map(|(child, offset, vis)| {
+                                        format!(
+                                            "{{\"name\":{},\"offset\":{},\"visibility\":\"{}\"}}",
+                                            quote(child),
+                                            offset,
+                                            vis_name(*vis)
+                                        )
+                                    })
+                                    .collect::<Vec<_>>();
+                                format!(
+                                    "{{\"name\":{},\"offset\":{},\"visibility\":\"{}\",\"children\":[{}]}}",
+                                    quote(&parent.name),
+                                    parent.offset,
+                                    vis_name(parent.vis),
+                                    children.join(",")
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let references = sect
+                            .references
+                            .iter()
+                            .map(|rf| {
+                                format!(
+                                    "{{\"referred\":{},\"offset\":{},\"which_byte\":\"{}\",\"branch\":{}}}",
+                                    quote(&rf.referred),
+                                    rf.offset,
+                                    match rf.which_byte {
+                                        ByteSelect::Both => "both",
+                                        ByteSelect::High => "high",
+                                        ByteSelect::Low => "low",
+                                    },
+                                    rf.branch
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let blocks = sect
+                            .blocks
+                            .iter()
+                            .map(|block| {
+                                format!(
+                                    "{{\"label\":{},\"offset\":{},\"size\":{}}}",
+                                    quote(&block.label),
+                                    block.offset,
+                                    block.size
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        let assertions = sect
+                            .assertions
+                            .iter()
+                            .map(|assertion| {
+                                format!(
+                                    "{{\"offset\":{},\"line\":{},\"expression\":{},\"message\":{}}}",
+                                    assertion.offset,
+                                    assertion.line,
+                                    quote(&assertion.expression),
+                                    quote(&assertion.message)
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        format!(
+                            "{{\"name\":{},\"size\":{},\"align\":{},\"labels\":[{}],\"references\":[{}],\"blocks\":[{}],\"assertions\":[{}]}}",
+                            quote(&sect.name),
+                            sect.size,
+                            sect.align,
+                            labels.join(","),
+                            references.join(","),
+                            blocks.join(","),
+                            assertions.join(",")
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                let undefined = undefined
+                    .iter()
+                    .map(|(symbol, uses)| {
+                        let uses = uses
+                            .iter()
+                            .map(|(sect, offset)| {
+                                format!("{{\"section\":{},\"offset\":{}}}", quote(sect), offset)
+                            })
+                            .collect::<Vec<_>>();
+                        format!(
+                            "{{\"symbol\":{},\"uses\":[{}]}}",
+                            quote(symbol),
+                            uses.join(",")
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"file\":{},\"kind\":\"object\",\"sections\":[{}],\"undefined\":[{}]}}",
+                    quote(file),
+                    sections.join(","),
+                    undefined.join(",")
+                )
+            }
+            Dump::Symbols { file, symbols } => {
+                let symbols = symbols
+                    .iter()
+                    .map(|(name, address)| {
+                        format!("{{\"name\":{},\"address\":{}}}", quote(name), address)
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{{\"file\":{},\"kind\":\"symbols\",\"symbols\":[{}]}}",
+                    quote(file),
+                    symbols.join(",")
+                )
+            }
+        });
+    }
+    writeln!(out, "[{}]", files.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes() {
+        assert_eq!(quote("main"), "\"main\"");
+        assert_eq!(
+            quote("say \"hi\"\\\n\t"),
+            "\"say \\\"hi\\\"\\\\\\u000a\\u0009\""
+        );
+    }
+
+    #[test]
+    fn json_symbols() {
+        let dumps = [Dump::Symbols {
+            file: "C:\\roms\\game.65s".to_string(),
+            symbols: vec![("start".to_string(), 0x8000)],
+        }];
+        let mut out = Vec::new();
+        json(&dumps, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[{\"file\":\"C:\\\\roms\\\\game.65s\",\"kind\":\"symbols\",\
+             \"symbols\":[{\"name\":\"start\",\"address\":32768}]}]\n"
+        );
+    }
+}